@@ -0,0 +1,80 @@
+"""ctypes wrapper around the beacon-ffi cdylib (cargo build --release -p beacon-ffi)."""
+
+import ctypes
+import struct
+import sys
+from pathlib import Path
+
+_EXT = {"darwin": "dylib", "win32": "dll"}.get(sys.platform, "so")
+_DEFAULT_LIB = Path(__file__).parent.parent / "target" / "release" / f"libbeacon_ffi.{_EXT}"
+
+_ERRORS = {
+    -1: "unknown beacon",
+    -2: "bad crc",
+    -3: "frame too short",
+    -4: "output buffer too small",
+    -5: "serialization failed",
+    -6: "unknown command",
+    -7: "unknown field",
+    -8: "null pointer",
+}
+
+
+class SouthFFI:
+    def __init__(self, path=_DEFAULT_LIB):
+        self._lib = ctypes.CDLL(str(path))
+        self._lib.south_crc16.restype = ctypes.c_uint16
+        self._lib.south_crc16.argtypes = [ctypes.c_char_p, ctypes.c_size_t]
+        self._lib.south_decode_beacon.restype = ctypes.c_ssize_t
+        self._lib.south_decode_beacon.argtypes = [
+            ctypes.c_char_p, ctypes.c_size_t, ctypes.c_char_p, ctypes.c_size_t
+        ]
+        self._lib.south_encode_beacon.restype = ctypes.c_ssize_t
+        self._lib.south_encode_beacon.argtypes = [
+            ctypes.c_char_p, ctypes.c_size_t, ctypes.c_uint64,
+            ctypes.c_char_p, ctypes.c_size_t, ctypes.c_char_p, ctypes.c_size_t
+        ]
+        self._lib.south_lst_command.restype = ctypes.c_ssize_t
+        self._lib.south_lst_command.argtypes = [
+            ctypes.c_uint16, ctypes.c_uint8, ctypes.c_char_p, ctypes.c_size_t
+        ]
+
+    @staticmethod
+    def _check(ret, out):
+        if ret < 0:
+            raise ValueError(_ERRORS.get(ret, f"error {ret}"))
+        return out.raw[:ret]
+
+    def crc16(self, data: bytes) -> int:
+        return self._lib.south_crc16(data, len(data))
+
+    def decode_beacon(self, frame: bytes) -> dict:
+        """decode a relayed frame into {subject: cbor encoded value}"""
+        out = ctypes.create_string_buffer(64 * 1024)
+        records = self._check(self._lib.south_decode_beacon(frame, len(frame), out, len(out)), out)
+        values, pos = {}, 0
+        while pos < len(records):
+            (subject_len,) = struct.unpack_from("<H", records, pos)
+            subject = records[pos + 2 : pos + 2 + subject_len].decode()
+            pos += 2 + subject_len
+            (value_len,) = struct.unpack_from("<I", records, pos)
+            values[subject] = records[pos + 4 : pos + 4 + value_len]
+            pos += 4 + value_len
+        return values
+
+    def encode_beacon(self, name: str, timestamp: int, fields: dict) -> bytes:
+        """beacon frame from {can id: raw value bytes}, e.g. to feed ground tools test frames"""
+        records = b"".join(
+            struct.pack("<HH", can_id, len(value)) + value for can_id, value in fields.items()
+        )
+        name = name.encode()
+        out = ctypes.create_string_buffer(256)
+        ret = self._lib.south_encode_beacon(
+            name, len(name), timestamp, records, len(records), out, len(out)
+        )
+        return self._check(ret, out)
+
+    def lst_command(self, hwid: int, cmd: int) -> bytes:
+        """uart frame of an openlst command, e.g. cmd 0x17 to request telemetry"""
+        out = ctypes.create_string_buffer(256)
+        return self._check(self._lib.south_lst_command(hwid, cmd, out, len(out)), out)
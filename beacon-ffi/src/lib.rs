@@ -0,0 +1,228 @@
+//! C ABI bindings to the ground-decode beacon encoding and decoding and the openlst
+//! command framing, so ground tools (e.g. python over ctypes) use the same code as the firmware.
+//!
+//! Functions return the number of bytes written to `out` or a negative error code.
+//! Null pointers are rejected with ERR_NULL_POINTER.
+
+use std::{slice, sync::Mutex};
+
+use embassy_futures::block_on;
+use embedded_io_async::{ErrorType, Write};
+use ground_decode::{Beacons, DecodeError, EncodeError, crc16_ccitt};
+use openlst_driver::{
+    link,
+    lst_sender::{LSTCmd, LSTSender},
+};
+
+pub const ERR_UNKNOWN_BEACON: isize = -1;
+pub const ERR_BAD_CRC: isize = -2;
+pub const ERR_TOO_SHORT: isize = -3;
+pub const ERR_BUFFER_TOO_SMALL: isize = -4;
+pub const ERR_SERIALIZE: isize = -5;
+pub const ERR_UNKNOWN_CMD: isize = -6;
+pub const ERR_UNKNOWN_FIELD: isize = -7;
+pub const ERR_NULL_POINTER: isize = -8;
+
+// header of an encode field record: can id(2 LE) value len(2 LE)
+const FIELD_HEADER_LEN: usize = 4;
+
+/// # Safety
+/// data must be null or valid for len bytes
+unsafe fn input<'a>(data: *const u8, len: usize) -> Result<&'a [u8], isize> {
+    if data.is_null() {
+        return Err(ERR_NULL_POINTER);
+    }
+    // SAFETY: checked for null, the caller guarantees the length
+    Ok(unsafe { slice::from_raw_parts(data, len) })
+}
+
+fn copy_out(bytes: &[u8], out: *mut u8, out_cap: usize) -> isize {
+    if out.is_null() {
+        return ERR_NULL_POINTER;
+    }
+    if bytes.len() > out_cap {
+        return ERR_BUFFER_TOO_SMALL;
+    }
+    // SAFETY: the caller guarantees out is valid for out_cap bytes
+    unsafe { slice::from_raw_parts_mut(out, bytes.len()) }.copy_from_slice(bytes);
+    bytes.len() as isize
+}
+
+/// decode a relayed frame into `[u16 subject len][subject][u32 value len][cbor value]` records,
+/// the met, burst and payload header markers in front of the beacon are skipped
+pub fn decode_beacon(frame: &[u8]) -> Result<Vec<u8>, isize> {
+    let (_, frame) = link::split_envelope(frame);
+    let decoded = Beacons::new().decode(frame).map_err(|e| match e {
+        DecodeError::WrongId => ERR_UNKNOWN_BEACON,
+        DecodeError::BadCrc => ERR_BAD_CRC,
+        DecodeError::TooShort => ERR_TOO_SHORT,
+        DecodeError::Serialize => ERR_SERIALIZE,
+    })?;
+    let mut records = Vec::new();
+    for (subject, value) in decoded.values {
+        records.extend_from_slice(&(subject.len() as u16).to_le_bytes());
+        records.extend_from_slice(subject.as_bytes());
+        records.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        records.extend_from_slice(&value);
+    }
+    Ok(records)
+}
+
+/// encode the named beacon from `[u16 can id][u16 value len][value]` field records into the
+/// frame the vehicle downlinks, without the header markers
+pub fn encode_beacon(name: &str, timestamp: u64, mut records: &[u8]) -> Result<Vec<u8>, isize> {
+    let mut fields = Vec::new();
+    while !records.is_empty() {
+        let header = records.get(..FIELD_HEADER_LEN).ok_or(ERR_TOO_SHORT)?;
+        let id = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let value = records
+            .get(FIELD_HEADER_LEN..FIELD_HEADER_LEN + len)
+            .ok_or(ERR_TOO_SHORT)?;
+        fields.push((id, value));
+        records = &records[FIELD_HEADER_LEN + len..];
+    }
+    Beacons::new()
+        .encode(name, timestamp, &fields)
+        .map_err(|e| match e {
+            EncodeError::UnknownBeacon => ERR_UNKNOWN_BEACON,
+            EncodeError::UnknownField => ERR_UNKNOWN_FIELD,
+            EncodeError::TooShort => ERR_TOO_SHORT,
+        })
+}
+
+// frames written by the lst sender
+static FRAME: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+struct FrameBuffer;
+
+impl ErrorType for FrameBuffer {
+    type Error = std::convert::Infallible;
+}
+
+impl Write for FrameBuffer {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        FRAME
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+// shared sender so sequence numbers keep counting between calls
+static SENDER: Mutex<Option<LSTSender<FrameBuffer>>> = Mutex::new(None);
+
+/// build the uart frame of an openlst command addressed to the given hwid
+pub fn lst_command(hwid: u16, cmd: u8) -> Result<Vec<u8>, isize> {
+    let cmd = match cmd {
+        0x12 => LSTCmd::Reboot,
+        0x17 => LSTCmd::GetTelem,
+        0x1C => LSTCmd::GetVersion,
+        _ => return Err(ERR_UNKNOWN_CMD),
+    };
+    let mut sender = SENDER.lock().unwrap_or_else(|e| e.into_inner());
+    let sender = sender.get_or_insert_with(|| LSTSender::new(FrameBuffer, 0));
+    block_on(sender.cmd_remote(hwid, cmd)).map_err(|_| ERR_SERIALIZE)?;
+    Ok(core::mem::take(
+        &mut *FRAME.lock().unwrap_or_else(|e| e.into_inner()),
+    ))
+}
+
+/// # Safety
+/// data must be valid for len bytes, a null pointer is taken as no data
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn south_crc16(data: *const u8, len: usize) -> u16 {
+    crc16_ccitt(unsafe { input(data, len) }.unwrap_or_default())
+}
+
+/// # Safety
+/// frame must be valid for frame_len bytes and out for out_cap bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn south_decode_beacon(
+    frame: *const u8,
+    frame_len: usize,
+    out: *mut u8,
+    out_cap: usize,
+) -> isize {
+    match unsafe { input(frame, frame_len) }.and_then(decode_beacon) {
+        Ok(records) => copy_out(&records, out, out_cap),
+        Err(e) => e,
+    }
+}
+
+/// # Safety
+/// name must be valid for name_len bytes, fields for fields_len bytes and out for out_cap bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn south_encode_beacon(
+    name: *const u8,
+    name_len: usize,
+    timestamp: u64,
+    fields: *const u8,
+    fields_len: usize,
+    out: *mut u8,
+    out_cap: usize,
+) -> isize {
+    let encoded = unsafe { input(name, name_len) }.and_then(|name| {
+        let name = std::str::from_utf8(name).map_err(|_| ERR_UNKNOWN_BEACON)?;
+        encode_beacon(name, timestamp, unsafe { input(fields, fields_len) }?)
+    });
+    match encoded {
+        Ok(frame) => copy_out(&frame, out, out_cap),
+        Err(e) => e,
+    }
+}
+
+/// # Safety
+/// out must be valid for out_cap bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn south_lst_command(
+    hwid: u16,
+    cmd: u8,
+    out: *mut u8,
+    out_cap: usize,
+) -> isize {
+    match lst_command(hwid, cmd) {
+        Ok(frame) => copy_out(&frame, out, out_cap),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_frames_count_sequence() {
+        let first = lst_command(0x2DED, 0x17).unwrap();
+        let second = lst_command(0x2DED, 0x17).unwrap();
+        assert_eq!(&first[..5], &[0x22, 0x69, 6, 0xED, 0x2D]);
+        assert_eq!(first[7..], [0x01, 0x17]);
+        assert_eq!(
+            u16::from_le_bytes([second[5], second[6]]),
+            u16::from_le_bytes([first[5], first[6]]).wrapping_add(1)
+        );
+        assert_eq!(lst_command(0x2DED, 0x42), Err(ERR_UNKNOWN_CMD));
+    }
+
+    #[test]
+    fn rejects_null_pointers() {
+        let mut out = [0; 16];
+        let ret = unsafe { south_decode_beacon(core::ptr::null(), 4, out.as_mut_ptr(), 16) };
+        assert_eq!(ret, ERR_NULL_POINTER);
+        let ret = unsafe { south_lst_command(0x2DED, 0x17, core::ptr::null_mut(), 16) };
+        assert_eq!(ret, ERR_NULL_POINTER);
+    }
+
+    #[test]
+    fn rejects_truncated_field_records() {
+        assert_eq!(
+            encode_beacon("eps_beacon", 0, &[0x01, 0x02, 4, 0, 0xAA]),
+            Err(ERR_TOO_SHORT)
+        );
+        assert_eq!(encode_beacon("no_beacon", 0, &[]), Err(ERR_UNKNOWN_BEACON));
+    }
+}
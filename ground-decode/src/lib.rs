@@ -0,0 +1,320 @@
+//! Beacon decoding shared by the ground station firmware and the host tools, so replayed
+//! and ffi decoded frames end up on the same subjects as the live telemetry.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+
+pub use south_common::chell::{Beacon, BeaconOperationError, ParseError};
+use south_common::definitions::telemetry as tm;
+
+#[cfg(feature = "primary")]
+use south_common::beacons::{
+    EPSBeacon, HighRateUpperSensorBeacon, LSTBeacon, LowRateUpperSensorBeacon, LowerSensorBeacon,
+    PyroBeacon,
+};
+
+#[cfg(feature = "secondary")]
+use south_common::beacons::SecondaryLstBeacon;
+
+// beacons of the tmtc board itself come without payload header
+pub const BUS_PAYLOAD: u8 = 0;
+
+// cbor major types used for the batch map
+const CBOR_TEXT: u8 = 3;
+const CBOR_MAP: u8 = 5;
+
+/// serialized values of a beacon as (value subject, cbor value)
+pub type Values = Vec<(String, Vec<u8>)>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    /// the frame is not this beacon, or none of the known beacons
+    WrongId,
+    BadCrc,
+    TooShort,
+    Serialize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodeError {
+    UnknownBeacon,
+    /// no telemetry definition with the can id, or it is not part of the beacon
+    UnknownField,
+    /// the value is shorter than its definition
+    TooShort,
+}
+
+/// software crc16_ccitt, matching the hardware crc configuration of both boards
+pub fn crc16_ccitt(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if (crc & 0x8000) != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+pub fn cbor_serializer(
+    value: &dyn erased_serde::Serialize,
+) -> Result<Vec<u8>, erased_serde::Error> {
+    let mut buffer = Vec::new();
+    let mut serializer = minicbor_serde::Serializer::new(&mut buffer);
+    value.erased_serialize(&mut <dyn erased_serde::Serializer>::erase(&mut serializer))?;
+    Ok(buffer)
+}
+
+/// parse a frame into the beacon and serialize its values, evaluates to
+/// `Result<Values, DecodeError>`
+#[macro_export]
+macro_rules! decode {
+    ($frame:expr, $beacon:expr, $crc_func:expr) => {{
+        use $crate::Beacon as _;
+        match $beacon.from_bytes($frame, $crc_func) {
+            Ok(()) => $beacon
+                .serialize(&$crate::cbor_serializer)
+                .map(|values| {
+                    values
+                        .into_iter()
+                        .map(|v| (v.0.into(), v.1))
+                        .collect::<$crate::Values>()
+                })
+                .map_err(|_| $crate::DecodeError::Serialize),
+            Err($crate::ParseError::WrongId) => Err($crate::DecodeError::WrongId),
+            Err($crate::ParseError::BadCRC) => Err($crate::DecodeError::BadCrc),
+            Err($crate::ParseError::OutOfMemory) => Err($crate::DecodeError::TooShort),
+        }
+    }};
+}
+
+fn cbor_head(buf: &mut Vec<u8>, major: u8, len: usize) {
+    let major = major << 5;
+    match len {
+        0..=23 => buf.push(major | len as u8),
+        24..=0xFF => buf.extend_from_slice(&[major | 24, len as u8]),
+        0x100..=0xFFFF => {
+            buf.push(major | 25);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            buf.push(major | 26);
+            buf.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+/// combine the serialized values of a beacon into one cbor map from the
+/// value subject to the value
+pub fn batch<S: AsRef<str>>(values: impl IntoIterator<Item = (S, Vec<u8>)>) -> Vec<u8> {
+    let mut entries = Vec::new();
+    let mut count = 0;
+    for (subject, value) in values {
+        let subject = subject.as_ref();
+        cbor_head(&mut entries, CBOR_TEXT, subject.len());
+        entries.extend_from_slice(subject.as_bytes());
+        // the values are already cbor encoded and are copied as they are
+        entries.extend_from_slice(&value);
+        count += 1;
+    }
+    let mut batch = Vec::with_capacity(entries.len() + 5);
+    cbor_head(&mut batch, CBOR_MAP, count);
+    batch.extend_from_slice(&entries);
+    batch
+}
+
+/// subject of the batched beacon values, tm.<beacon> or tm.payload<id>.<beacon> for experiments
+pub fn batch_subject(payload: u8, beacon: &str) -> String {
+    if payload == BUS_PAYLOAD {
+        format!("tm.{}", beacon)
+    } else {
+        format!("tm.payload{}.{}", payload, beacon)
+    }
+}
+
+/// messages of a decoded beacon as (subject, payload), one batch on the subject of the
+/// payload or one message per value
+pub fn messages(
+    beacon: &str,
+    payload: u8,
+    values: Values,
+    per_field: bool,
+) -> Vec<(String, Vec<u8>)> {
+    if per_field {
+        values
+    } else {
+        let mut messages = Vec::with_capacity(1);
+        messages.push((batch_subject(payload, beacon), batch(values)));
+        messages
+    }
+}
+
+/// a beacon decoded by Beacons, named like its subject
+pub struct Decoded {
+    pub beacon: &'static str,
+    pub values: Values,
+}
+
+/// One instance of every beacon the vehicle sends, for host tools that
+/// decode frames without caring which beacon they are
+pub struct Beacons {
+    #[cfg(feature = "primary")]
+    pub lst_beacon: LSTBeacon,
+    #[cfg(feature = "primary")]
+    pub eps_beacon: EPSBeacon,
+    #[cfg(feature = "primary")]
+    pub high_rate_upper_beacon: HighRateUpperSensorBeacon,
+    #[cfg(feature = "primary")]
+    pub low_rate_upper_beacon: LowRateUpperSensorBeacon,
+    #[cfg(feature = "primary")]
+    pub lower_sensor_beacon: LowerSensorBeacon,
+    #[cfg(feature = "primary")]
+    pub pyro_beacon: PyroBeacon,
+    #[cfg(feature = "secondary")]
+    pub secondary_lst_beacon: SecondaryLstBeacon,
+}
+
+impl Default for Beacons {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Beacons {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "primary")]
+            lst_beacon: LSTBeacon::new(),
+            #[cfg(feature = "primary")]
+            eps_beacon: EPSBeacon::new(),
+            #[cfg(feature = "primary")]
+            high_rate_upper_beacon: HighRateUpperSensorBeacon::new(),
+            #[cfg(feature = "primary")]
+            low_rate_upper_beacon: LowRateUpperSensorBeacon::new(),
+            #[cfg(feature = "primary")]
+            lower_sensor_beacon: LowerSensorBeacon::new(),
+            #[cfg(feature = "primary")]
+            pyro_beacon: PyroBeacon::new(),
+            #[cfg(feature = "secondary")]
+            secondary_lst_beacon: SecondaryLstBeacon::new(),
+        }
+    }
+
+    /// the beacon with the name, as used in its subject
+    pub fn beacon_mut(&mut self, name: &str) -> Option<&mut dyn Beacon<Timestamp = u64>> {
+        macro_rules! find_beacon {
+            ($($beacon:ident),*) => { $(
+                if name == stringify!($beacon) {
+                    return Some(&mut self.$beacon);
+                }
+            )* };
+        }
+        #[cfg(feature = "primary")]
+        find_beacon!(
+            lst_beacon,
+            eps_beacon,
+            high_rate_upper_beacon,
+            low_rate_upper_beacon,
+            lower_sensor_beacon,
+            pyro_beacon
+        );
+        #[cfg(feature = "secondary")]
+        find_beacon!(secondary_lst_beacon);
+        None
+    }
+
+    /// build the frame of the named beacon from (can id, value) fields with the software crc,
+    /// the same bytes the vehicle downlinks. Fields not given keep their value in this instance
+    pub fn encode(
+        &mut self,
+        name: &str,
+        timestamp: u64,
+        fields: &[(u16, &[u8])],
+    ) -> Result<Vec<u8>, EncodeError> {
+        let beacon = self.beacon_mut(name).ok_or(EncodeError::UnknownBeacon)?;
+        for (id, value) in fields {
+            let def = tm::from_id(*id).ok_or(EncodeError::UnknownField)?;
+            beacon.insert_slice(def, value).map_err(|e| match e {
+                BeaconOperationError::DefNotInBeacon => EncodeError::UnknownField,
+                BeaconOperationError::OutOfMemory => EncodeError::TooShort,
+            })?;
+        }
+        beacon.set_timestamp(timestamp);
+        Ok(beacon.to_bytes(&mut crc16_ccitt).to_vec())
+    }
+
+    /// decode a beacon frame with the software crc, WrongId if no beacon matches
+    pub fn decode(&mut self, frame: &[u8]) -> Result<Decoded, DecodeError> {
+        macro_rules! try_beacon {
+            ($($beacon:ident),*) => { $(
+                match decode!(frame, self.$beacon, &mut crc16_ccitt) {
+                    Ok(values) => {
+                        return Ok(Decoded {
+                            beacon: stringify!($beacon),
+                            values,
+                        })
+                    }
+                    Err(DecodeError::WrongId) => (),
+                    Err(e) => return Err(e),
+                }
+            )* };
+        }
+        #[cfg(feature = "primary")]
+        try_beacon!(
+            lst_beacon,
+            eps_beacon,
+            high_rate_upper_beacon,
+            low_rate_upper_beacon,
+            lower_sensor_beacon,
+            pyro_beacon
+        );
+        #[cfg(feature = "secondary")]
+        try_beacon!(secondary_lst_beacon);
+        Err(DecodeError::WrongId)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn batches_on_the_payload_subject() {
+        let values = vec![(String::from("tm.a"), vec![0x01])];
+        let messages = messages("eps_beacon", 2, values.clone(), false);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, "tm.payload2.eps_beacon");
+        // map(1) text(4) "tm.a" 1
+        assert_eq!(messages[0].1, [0xA1, 0x64, b't', b'm', b'.', b'a', 0x01]);
+        assert_eq!(
+            messages("eps_beacon", BUS_PAYLOAD, values.clone(), true),
+            values
+        );
+        assert_eq!(batch_subject(BUS_PAYLOAD, "eps_beacon"), "tm.eps_beacon");
+    }
+
+    #[cfg(feature = "primary")]
+    #[test]
+    fn encoded_beacon_decodes() {
+        let mut beacons = Beacons::new();
+        let frame = beacons.encode("eps_beacon", 1000, &[]).unwrap();
+        assert_eq!(beacons.decode(&frame).unwrap().beacon, "eps_beacon");
+        assert_eq!(
+            beacons.encode("no_beacon", 0, &[]),
+            Err(EncodeError::UnknownBeacon)
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Replays raw OpenLST uart captures through the ground decoding path and
+//! republishes the decoded telemetry to NATS on the subjects of the ground station.
+//!
+//! usage: ground-replay [capture file, default stdin] [nats addr, default 127.0.0.1:4222]
+
+mod nats;
+
+use std::{fs::File, io};
+
+use embassy_futures::block_on;
+use embedded_io_async::{ErrorType, Read, ReadExactError};
+use ground_decode::{BUS_PAYLOAD, Beacons, DecodeError, messages};
+use openlst_driver::{
+    link,
+    lst_receiver::{LSTMessage, LSTReceiver, ReceiverError},
+};
+
+use crate::nats::NatsPublisher;
+
+const DEFAULT_NATS_ADDR: &str = "127.0.0.1:4222";
+
+/// async read adapter for blocking std readers
+struct StdReader<R>(R);
+
+impl<R: io::Read> ErrorType for StdReader<R> {
+    type Error = io::Error;
+}
+
+impl<R: io::Read> Read for StdReader<R> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+
+fn replay<R: io::Read>(input: R, nats: &mut NatsPublisher) {
+    let mut lst_rx = LSTReceiver::new(StdReader(input));
+    let mut beacons = Beacons::new();
+
+    let mut frames = 0u32;
+    loop {
+        match block_on(lst_rx.receive()) {
+            Ok(LSTMessage::Relay(data)) => {
+                frames += 1;
+                // the markers are station side status, only the beacon behind them is replayed
+                let (envelope, data) = link::split_envelope(data);
+                let payload = envelope.payload.unwrap_or(BUS_PAYLOAD);
+                match beacons.decode(data) {
+                    Ok(decoded) => {
+                        println!("{} received", decoded.beacon);
+                        for (subject, value) in
+                            messages(decoded.beacon, payload, decoded.values, false)
+                        {
+                            if let Err(e) = nats.publish(&subject, &value) {
+                                eprintln!("could not publish {}: {}", subject, e);
+                            }
+                        }
+                    }
+                    Err(DecodeError::WrongId) => (),
+                    Err(e) => eprintln!("beacon could not be decoded: {:?}", e),
+                }
+            }
+            Ok(_) => frames += 1,
+            Err(ReceiverError::ReadError(ReadExactError::UnexpectedEof)) => break,
+            Err(ReceiverError::ReadError(ReadExactError::Other(e))) => {
+                eprintln!("could not read capture: {}", e);
+                break;
+            }
+            Err(e) => eprintln!("error in receiving frame: {:?}", e),
+        }
+    }
+    println!("replayed {} frames", frames);
+}
+
+fn main() -> io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let input = args.next().filter(|path| path != "-");
+    let nats_addr = args.next().unwrap_or_else(|| DEFAULT_NATS_ADDR.into());
+
+    let mut nats = NatsPublisher::connect(&nats_addr, "nats", "nats")?;
+
+    match input {
+        Some(path) => replay(File::open(path)?, &mut nats),
+        None => replay(io::stdin().lock(), &mut nats),
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    thread,
+};
+
+/// Minimal blocking NATS publisher for host tools
+pub struct NatsPublisher {
+    stream: TcpStream,
+}
+
+impl NatsPublisher {
+    pub fn connect(addr: &str, user: &str, pass: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        // the server greets with its INFO line
+        let mut info = String::new();
+        reader.read_line(&mut info)?;
+        if !info.starts_with("INFO") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no nats INFO received",
+            ));
+        }
+
+        write!(
+            stream,
+            "CONNECT {{\"verbose\":false,\"pedantic\":false,\"user\":\"{}\",\"pass\":\"{}\"}}\r\n",
+            user, pass
+        )?;
+
+        // answer server pings so long replays don't get disconnected
+        let mut pong = stream.try_clone()?;
+        thread::spawn(move || {
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 0) {
+                if line.starts_with("PING") && pong.write_all(b"PONG\r\n").is_err() {
+                    break;
+                }
+                if line.starts_with("-ERR") {
+                    eprintln!("nats error: {}", line.trim_end());
+                }
+                line.clear();
+            }
+        });
+
+        Ok(Self { stream })
+    }
+
+    pub fn publish(&mut self, subject: &str, payload: &[u8]) -> io::Result<()> {
+        write!(self.stream, "PUB {} {}\r\n", subject, payload.len())?;
+        self.stream.write_all(payload)?;
+        self.stream.write_all(b"\r\n")
+    }
+}
@@ -0,0 +1,46 @@
+//! Protocol of the openlst serial bootloader, shared by the sender, the receiver and
+//! the flashing in lst_bootloader
+
+// opcodes of the openlst serial bootloader
+pub const BOOTLOADER_PING: u8 = 0x00;
+pub const BOOTLOADER_ACK: u8 = 0x01;
+pub const BOOTLOADER_WRITE_PAGE: u8 = 0x02;
+pub const BOOTLOADER_ERASE: u8 = 0x0C;
+pub const BOOTLOADER_NACK: u8 = 0x0F;
+// ack messages of ping and erase, a page write is acked with its page number
+pub const ACK_PONG: u8 = 0;
+pub const ACK_ERASED: u8 = 1;
+// writing this page ends the update, the bootloader checks the signature and boots the app
+pub const FINISH_PAGE: u8 = 255;
+
+/// bytes written per bootloader page
+pub const PAGE_SIZE: usize = 128;
+/// flash address of the application, below is the bootloader
+pub const APP_START: usize = 0x0400;
+/// end of the application area including the signature, above is the nonvolatile storage
+pub const APP_END: usize = 0x6C00;
+
+/// non empty pages of an application image starting at APP_START, with their page number
+pub fn pages(image: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    image
+        .chunks(PAGE_SIZE)
+        .enumerate()
+        .filter(|(_, data)| data.iter().any(|b| *b != 0xFF))
+        .map(|(i, data)| ((APP_START / PAGE_SIZE + i) as u8, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_pages_are_skipped() {
+        let mut image = [0xFF; 3 * PAGE_SIZE + 10];
+        image[0] = 0x02;
+        image[3 * PAGE_SIZE + 9] = 0x00;
+        let mut pages = pages(&image);
+        assert_eq!(pages.next().map(|(page, _)| page), Some(8));
+        assert_eq!(pages.next(), Some((11, &image[3 * PAGE_SIZE..])));
+        assert_eq!(pages.next(), None);
+    }
+}
@@ -0,0 +1,49 @@
+use embedded_hal_async::delay::DelayNs;
+
+/// Time source of the protocol timeouts. Waiting goes through DelayNs, so a
+/// simulated clock makes the timing deterministic in host tests
+pub trait Clock: DelayNs {
+    /// monotonic time since startup
+    fn now_us(&self) -> u64;
+}
+
+/// Simulated clock for host tests: every delay completes immediately and
+/// advances the time by its duration
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now_us: u64,
+}
+
+impl MockClock {
+    pub const fn new() -> Self {
+        Self { now_us: 0 }
+    }
+
+    /// time passing outside of delays, e.g. while a message is in flight
+    pub fn advance(&mut self, us: u64) {
+        self.now_us += us;
+    }
+}
+
+impl DelayNs for MockClock {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.now_us += ns.div_ceil(1000) as u64;
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.now_us += ms as u64 * 1000;
+    }
+}
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        self.now_us
+    }
+}
+
+#[cfg(feature = "embassy-time")]
+impl Clock for embassy_time::Delay {
+    fn now_us(&self) -> u64 {
+        embassy_time::Instant::now().as_micros()
+    }
+}
@@ -0,0 +1,92 @@
+use crate::telemetry_layout::Endianness;
+
+/// longest supported header, without start bytes and length byte
+pub const MAX_HEADER_LEN: usize = 16;
+
+/// layout of the frame header between the length byte and the payload
+#[derive(Clone, Copy, Debug)]
+pub struct HeaderProfile {
+    pub len: usize,
+    pub hwid_offset: usize,
+    pub hwid_endianness: Endianness,
+    pub seq_offset: usize,
+    pub dest_offset: usize,
+}
+
+/// header of the openlst firmwares in this repository: hwid(2 LE) seq(2 LE) dest(1)
+pub const OPENLST_HEADER: HeaderProfile = HeaderProfile {
+    len: 5,
+    hwid_offset: 0,
+    hwid_endianness: Endianness::Little,
+    seq_offset: 2,
+    dest_offset: 4,
+};
+
+impl HeaderProfile {
+    /// all fields fit into the header without overlapping
+    pub const fn is_valid(&self) -> bool {
+        let hwid = self.hwid_offset..self.hwid_offset + 2;
+        let seq = self.seq_offset..self.seq_offset + 2;
+        self.len <= MAX_HEADER_LEN
+            && hwid.end <= self.len
+            && seq.end <= self.len
+            && self.dest_offset < self.len
+            && (hwid.end <= seq.start || seq.end <= hwid.start)
+            && (self.dest_offset < hwid.start || self.dest_offset >= hwid.end)
+            && (self.dest_offset < seq.start || self.dest_offset >= seq.end)
+    }
+    /// write the header fields into the first len bytes of out, other bytes are zeroed
+    pub fn write(&self, out: &mut [u8], hwid: u16, seq_num: u16, dest: u8) {
+        out[..self.len].fill(0);
+        let hwid = match self.hwid_endianness {
+            Endianness::Little => hwid.to_le_bytes(),
+            Endianness::Big => hwid.to_be_bytes(),
+        };
+        out[self.hwid_offset..self.hwid_offset + 2].copy_from_slice(&hwid);
+        out[self.seq_offset..self.seq_offset + 2].copy_from_slice(&seq_num.to_le_bytes());
+        out[self.dest_offset] = dest;
+    }
+    pub fn hwid(&self, header: &[u8]) -> u16 {
+        let bytes = [header[self.hwid_offset], header[self.hwid_offset + 1]];
+        match self.hwid_endianness {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        }
+    }
+    pub fn seq_num(&self, header: &[u8]) -> u16 {
+        u16::from_le_bytes([header[self.seq_offset], header[self.seq_offset + 1]])
+    }
+    pub fn dest(&self, header: &[u8]) -> u8 {
+        header[self.dest_offset]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openlst_header_roundtrip() {
+        let mut header = [0; 5];
+        OPENLST_HEADER.write(&mut header, 0x2DEC, 0x0102, 0x11);
+        assert_eq!(header, [0xEC, 0x2D, 0x02, 0x01, 0x11]);
+        assert_eq!(OPENLST_HEADER.hwid(&header), 0x2DEC);
+        assert_eq!(OPENLST_HEADER.seq_num(&header), 0x0102);
+        assert_eq!(OPENLST_HEADER.dest(&header), 0x11);
+    }
+
+    #[test]
+    fn rejects_overlapping_fields() {
+        assert!(OPENLST_HEADER.is_valid());
+        let overlapping = HeaderProfile {
+            seq_offset: 1,
+            ..OPENLST_HEADER
+        };
+        assert!(!overlapping.is_valid());
+        let too_short = HeaderProfile {
+            len: 4,
+            ..OPENLST_HEADER
+        };
+        assert!(!too_short.is_valid());
+    }
+}
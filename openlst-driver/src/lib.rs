@@ -1,4 +1,19 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod bootloader_protocol;
+pub mod clock;
+pub mod header_profile;
+pub mod link;
+#[cfg(feature = "bootloader")]
+pub mod lst_bootloader;
+pub mod lst_channels;
+#[cfg(all(feature = "sender", feature = "receiver"))]
+pub mod lst_control;
+#[cfg(feature = "receiver")]
 pub mod lst_receiver;
+#[cfg(feature = "sender")]
 pub mod lst_sender;
+pub mod relay_route;
+pub mod telemetry_layout;
+#[cfg(feature = "receiver")]
+pub mod uart_recovery;
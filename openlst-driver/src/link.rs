@@ -0,0 +1,177 @@
+//! Addresses, frame ids and uplink op codes of the link between radio-air and
+//! radio-ground. Both firmwares take them from here, so the two ends can not drift apart
+
+// nodes of the routing header, independent of the lst hwids which are equal on the flight
+// and the ground lst. Repeaters take ids from ROUTE_NODE_REPEATER on
+pub const ROUTE_NODE_AIR: u16 = 0x0001;
+pub const ROUTE_NODE_GROUND: u16 = 0x0002;
+pub const ROUTE_NODE_REPEATER: u16 = 0x0010;
+
+// frame ids, the first byte of the relayed frames that are not beacons and distinct from
+// the beacon ids
+// blackbox record: id(1) timestamp us(8 LE) reason(1) frame len(2 LE) stored bytes
+pub const BLACKBOX_DUMP_ID: u8 = 0xBB;
+// uplinked command: id(1) source hwid(2 LE) seq(2 LE) op(1) args
+pub const UPLINK_ID: u8 = 0xC1;
+// acknowledgement of an uplinked command: id(1) source hwid(2 LE) seq(2 LE) status(1)
+pub const UPLINK_ACK_ID: u8 = 0xC2;
+// crc self test beacon: id(1) seq(2 LE) valid(1) pattern(16) crc(2 LE)
+pub const TEST_BEACON_ID: u8 = 0xC3;
+// pending time-tagged commands: id(1) count(1) per entry: source hwid(2 LE) seq(2 LE)
+// op(1) due utc ms(8 LE)
+pub const SCHEDULE_LIST_ID: u8 = 0xC4;
+// beacon of an attached experiment: id(1) payload id(1) beacon
+pub const PAYLOAD_HEADER_ID: u8 = 0xC5;
+// current mission phase: id(1) phase(1)
+pub const PHASE_ID: u8 = 0xC6;
+// raw payload of a ground tool: id(1) source hwid(2 LE) seq(2 LE) payload
+pub const RAW_UPLINK_ID: u8 = 0xC7;
+// lst uart health: id(1) framing(2 LE) noise(2 LE) overrun(2 LE) other(2 LE) recoveries(2 LE)
+pub const UART_HEALTH_ID: u8 = 0xC8;
+// time correlation: id(1) seq(2 LE) local ms(8 LE)
+pub const TIME_CORRELATION_ID: u8 = 0xC9;
+// duty cycle budget: id(1) used airtime ms(4 LE) budget ms(4 LE) deferred beacons(2 LE)
+pub const DUTY_CYCLE_ID: u8 = 0xCA;
+// frame routed over repeaters, see relay_route
+pub const ROUTED_ID: u8 = 0xCB;
+// beacon sent during a burst: id(1) trigger(1) frame, the trigger is the entered phase
+// or BURST_UPLINK_TRIGGER
+pub const BURST_ID: u8 = 0xCC;
+pub const BURST_UPLINK_TRIGGER: u8 = 0xFF;
+// beacon stamped with the mission elapsed time: id(1) met ms(4 LE, signed) frame
+pub const MET_ID: u8 = 0xCD;
+// traffic shaper: id(1) per class: quota utilization permille(2 LE) deferred beacons(2 LE)
+pub const SHAPER_ID: u8 = 0xCE;
+// flight state sent in the terminal phase: id(1) phase(1) altitude m(4 LE, f32)
+// ecef position x y z m(4 LE each, f32)
+pub const FLIGHT_STATE_ID: u8 = 0xCF;
+// can receive statistics of the report window: id(1) frames per s(2 LE) untracked(2 LE)
+// backlogged(2 LE) max latency us(4 LE) per busiest id: can id(4 LE) frames per s(2 LE),
+// CAN_STATS_BUSIEST entries, unused entries are zero
+pub const CAN_STATS_ID: u8 = 0xD0;
+pub const CAN_STATS_BUSIEST: usize = 3;
+// cpu and stack usage: id(1) stack used(4 LE) stack size(4 LE)
+// per PROFILED_TASKS entry: busy permille of the report window(2 LE)
+pub const PROFILING_ID: u8 = 0xD1;
+pub const PROFILED_TASKS: [&str; 4] = ["beacon tx", "can rx", "can tx", "lst link"];
+// version of the flight lst: id(1) seq of the request(2 LE) hwid(2 LE) git rev(4 LE)
+pub const LST_VERSION_ID: u8 = 0xD2;
+
+// ops of the uplinked commands
+
+pub const OP_PING: u8 = 0x00;
+pub const OP_REBOOT_LST: u8 = 0x01;
+/// send a test beacon with a valid and one with a corrupted crc
+pub const OP_CRC_SELF_TEST: u8 = 0x02;
+/// execute a command later, args: kind(1, SCHEDULE_DELAY / SCHEDULE_UTC) time ms(8 LE) op(1)
+pub const OP_SCHEDULE: u8 = 0x03;
+/// relay the list of scheduled commands
+pub const OP_LIST_SCHEDULE: u8 = 0x04;
+/// drop a scheduled command, args: seq of the schedule command(2 LE)
+pub const OP_CANCEL_SCHEDULED: u8 = 0x05;
+/// switch to the terminal phase beacon rates
+pub const OP_HIGH_RATE: u8 = 0x06;
+/// enable or disable the beacons of a payload, args: payload id(1) enabled(1)
+pub const OP_PAYLOAD_ENABLE: u8 = 0x07;
+/// allocate a share of the beacon rate to a payload, args: payload id(1) percent(1)
+pub const OP_PAYLOAD_RATE: u8 = 0x08;
+/// override the mission phase, args: phase(1)
+pub const OP_SET_PHASE: u8 = 0x09;
+/// downlink only the listed fields of a beacon,
+/// args: beacon index(1) flags(1, 1 append) field can ids(2 LE each)
+pub const OP_TELEMETRY_FILTER: u8 = 0x0A;
+/// send the high rate beacon at its burst rate for a while, args: optional duration s(2 LE)
+pub const OP_BURST: u8 = 0x0B;
+/// enable or disable a beacon and assign its interval,
+/// args: beacon index(1) enabled(1) optional interval ms(4 LE, 0 compiled-in)
+pub const OP_BEACON_CONFIG: u8 = 0x0C;
+/// save the active beacon set into the flash parameter area and reboot, only on the pad,
+/// args: BEACON_SAVE_CONFIRM
+pub const OP_BEACON_SAVE: u8 = 0x0D;
+/// set T-0 of the mission elapsed time, args: T-0 utc ms(8 LE), without args T-0 is cleared
+pub const OP_SET_T0: u8 = 0x0E;
+/// relay the version of the flight lst as an LST_VERSION_ID frame, then ack
+pub const OP_LST_VERSION: u8 = 0x0F;
+
+// args of the beacon save command, a corrupted or misrouted op alone does not reboot the vehicle
+pub const BEACON_SAVE_CONFIRM: [u8; 4] = *b"SAVE";
+
+// time kinds of the schedule command
+pub const SCHEDULE_DELAY: u8 = 0x00;
+pub const SCHEDULE_UTC: u8 = 0x01;
+
+// status of an acknowledgement
+pub const ACK_EXECUTED: u8 = 0x00;
+/// retransmission of an already executed command, not executed again
+pub const ACK_DUPLICATE: u8 = 0x01;
+pub const ACK_FAILED: u8 = 0x02;
+pub const ACK_UNKNOWN_OP: u8 = 0x03;
+
+/// markers radio-air puts in front of a relayed beacon
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BeaconEnvelope {
+    /// mission elapsed time of the stamped beacon
+    pub met_ms: Option<i32>,
+    /// trigger of the burst the beacon was sampled in
+    pub burst_trigger: Option<u8>,
+    /// experiment the beacon belongs to, None for beacons of the tmtc board
+    pub payload: Option<u8>,
+}
+
+/// split the met, burst and payload header markers off a relayed frame, in that order,
+/// returns them and the beacon behind them
+pub fn split_envelope(frame: &[u8]) -> (BeaconEnvelope, &[u8]) {
+    let mut envelope = BeaconEnvelope::default();
+    let mut frame = frame;
+    if let [MET_ID, a, b, c, d, rest @ ..] = frame {
+        envelope.met_ms = Some(i32::from_le_bytes([*a, *b, *c, *d]));
+        frame = rest;
+    }
+    if let [BURST_ID, trigger, rest @ ..] = frame {
+        envelope.burst_trigger = Some(*trigger);
+        frame = rest;
+    }
+    if let [PAYLOAD_HEADER_ID, payload, rest @ ..] = frame {
+        envelope.payload = Some(*payload);
+        frame = rest;
+    }
+    (envelope, frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_is_split_in_order() {
+        let frame = [
+            MET_ID,
+            0xF6,
+            0xFF,
+            0xFF,
+            0xFF,
+            BURST_ID,
+            3,
+            PAYLOAD_HEADER_ID,
+            2,
+            0x42,
+        ];
+        let (envelope, beacon) = split_envelope(&frame);
+        assert_eq!(envelope.met_ms, Some(-10));
+        assert_eq!(envelope.burst_trigger, Some(3));
+        assert_eq!(envelope.payload, Some(2));
+        assert_eq!(beacon, [0x42]);
+
+        // markers out of order are left to the beacon parser
+        let frame = [BURST_ID, 3, MET_ID, 0, 0, 0, 0, 0x42];
+        let (envelope, beacon) = split_envelope(&frame);
+        assert_eq!(envelope.met_ms, None);
+        assert_eq!(envelope.burst_trigger, Some(3));
+        assert_eq!(beacon, &frame[2..]);
+        assert_eq!(
+            split_envelope(&[0x42]),
+            (BeaconEnvelope::default(), &[0x42][..])
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Flashing the local lst over its serial bootloader
+
+use embassy_futures::select::{Either, select};
+use embedded_io_async::{Read, Write};
+
+use crate::{
+    bootloader_protocol::{ACK_ERASED, ACK_PONG, APP_END, APP_START, PAGE_SIZE, pages},
+    clock::Clock,
+    lst_receiver::{LSTMessage, LSTReceiver, LSTVersion, ReceiverError},
+    lst_sender::{LSTCmd, LSTSender, SenderError},
+};
+
+// the bootloader only stays active for a moment after a reset
+const PING_INTERVAL_MS: u32 = 100;
+// time between version requests while waiting for the new app to boot
+const BOOT_POLL_INTERVAL_MS: u32 = 500;
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum BootloaderError<TxError, RxError> {
+    SendError(SenderError<TxError>),
+    ReceiveError(ReceiverError<RxError>),
+    Timeout,
+    /// the image does not fit into the application area
+    ImageTooLarge,
+    /// nack from the bootloader, with the expected ack message (the page number for writes)
+    Rejected(u8),
+}
+
+/// summary of a completed update
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub struct FlashReport {
+    pub pages_written: u16,
+    /// version reported by the new firmware
+    pub version: LSTVersion,
+}
+
+/// wait for a bootloader ack with the given message
+async fn wait_ack<S: Write, R: Read, C: Clock>(
+    receiver: &mut LSTReceiver<R>,
+    clock: &mut C,
+    expected: u8,
+    timeout_ms: u32,
+) -> Result<(), BootloaderError<S::Error, R::Error>> {
+    let wait_for_ack = async {
+        loop {
+            match receiver.receive().await {
+                Ok(LSTMessage::BootloaderAck(message)) if message == expected => return Ok(()),
+                Ok(LSTMessage::BootloaderNack) => return Err(BootloaderError::Rejected(expected)),
+                Ok(_) => (),
+                // the uart sees garbage while the lst restarts
+                Err(ReceiverError::ParseError(_) | ReceiverError::MsgTooShort) => (),
+                Err(e) => return Err(BootloaderError::ReceiveError(e)),
+            }
+        }
+    };
+    match select(wait_for_ack, clock.delay_ms(timeout_ms)).await {
+        Either::First(result) => result,
+        Either::Second(()) => Err(BootloaderError::Timeout),
+    }
+}
+
+/// reboot the local lst and catch it in the bootloader
+pub async fn enter_bootloader<S: Write, R: Read, C: Clock>(
+    sender: &mut LSTSender<S>,
+    receiver: &mut LSTReceiver<R>,
+    clock: &mut C,
+    timeout_ms: u32,
+) -> Result<(), BootloaderError<S::Error, R::Error>> {
+    sender
+        .cmd(LSTCmd::Reboot)
+        .await
+        .map_err(BootloaderError::SendError)?;
+    let deadline = clock.now_us() + timeout_ms as u64 * 1000;
+    while clock.now_us() < deadline {
+        sender
+            .bootloader_ping()
+            .await
+            .map_err(BootloaderError::SendError)?;
+        match wait_ack::<S, R, C>(receiver, clock, ACK_PONG, PING_INTERVAL_MS).await {
+            Ok(()) => return Ok(()),
+            Err(BootloaderError::Timeout) => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(BootloaderError::Timeout)
+}
+
+/// Flash an application image starting at APP_START to the local lst: enter the
+/// bootloader, erase the application, write all non empty pages, each acked with
+/// its page number, and verify that the new firmware boots and reports its version.
+/// The signature has to be part of the image, otherwise the bootloader does not
+/// start the application
+pub async fn flash_image<S: Write, R: Read, C: Clock>(
+    sender: &mut LSTSender<S>,
+    receiver: &mut LSTReceiver<R>,
+    clock: &mut C,
+    image: &[u8],
+    timeout_ms: u32,
+) -> Result<FlashReport, BootloaderError<S::Error, R::Error>> {
+    if image.len() > APP_END - APP_START {
+        return Err(BootloaderError::ImageTooLarge);
+    }
+    enter_bootloader(sender, receiver, clock, timeout_ms).await?;
+
+    sender
+        .bootloader_erase()
+        .await
+        .map_err(BootloaderError::SendError)?;
+    wait_ack::<S, R, C>(receiver, clock, ACK_ERASED, timeout_ms).await?;
+
+    let mut pages_written = 0;
+    for (page, data) in pages(image) {
+        let mut padded = [0xFF; PAGE_SIZE];
+        padded[..data.len()].copy_from_slice(data);
+        sender
+            .bootloader_write_page(page, &padded)
+            .await
+            .map_err(BootloaderError::SendError)?;
+        wait_ack::<S, R, C>(receiver, clock, page, timeout_ms).await?;
+        pages_written += 1;
+    }
+    // not acked, the bootloader leaves for the application
+    sender
+        .bootloader_finish()
+        .await
+        .map_err(BootloaderError::SendError)?;
+
+    let deadline = clock.now_us() + timeout_ms as u64 * 1000;
+    while clock.now_us() < deadline {
+        sender
+            .cmd(LSTCmd::GetVersion)
+            .await
+            .map_err(BootloaderError::SendError)?;
+        let wait_for_version = async {
+            loop {
+                match receiver.receive().await {
+                    Ok(LSTMessage::Version(version)) => return Ok(version),
+                    Ok(_) | Err(ReceiverError::ParseError(_) | ReceiverError::MsgTooShort) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        match select(wait_for_version, clock.delay_ms(BOOT_POLL_INTERVAL_MS)).await {
+            Either::First(Ok(version)) => {
+                return Ok(FlashReport {
+                    pages_written,
+                    version,
+                });
+            }
+            Either::First(Err(e)) => return Err(BootloaderError::ReceiveError(e)),
+            Either::Second(()) => (),
+        }
+    }
+    Err(BootloaderError::Timeout)
+}
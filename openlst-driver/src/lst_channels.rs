@@ -0,0 +1,109 @@
+// The channel table commands 0x30 to 0x33 are not part of the stock openlst firmware, the
+// radio firmware in openlst-firmware nacks them. They need a radio build with CUSTOM_COMMANDS
+// that stores the table and retunes FREQ2..0, until then reading the table fails with
+// ChannelError::Unsupported and setting or selecting channels is nacked by the lst
+
+/// longest channel table a radio build with the channel commands stores
+pub const MAX_CHANNELS: usize = 8;
+
+#[cfg(feature = "sender")]
+pub(crate) const SET_CHANNELS: u8 = 0x30;
+#[cfg(feature = "sender")]
+pub(crate) const SELECT_CHANNEL: u8 = 0x31;
+// reply to LSTCmd::GetChannels
+#[cfg(feature = "receiver")]
+pub(crate) const CHANNELS: u8 = 0x33;
+
+/// rf channel table of an lst: len(1) active(1) frequencies(4 LE each, in Hz)
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelTable {
+    pub hwid: u16,
+    pub active: u8,
+    len: u8,
+    frequencies: [u32; MAX_CHANNELS],
+}
+
+impl ChannelTable {
+    pub fn frequencies(&self) -> &[u32] {
+        &self.frequencies[..self.len as usize]
+    }
+    pub fn active_frequency(&self) -> Option<u32> {
+        self.frequencies().get(self.active as usize).copied()
+    }
+    /// first channel at least spacing_hz away from all frequencies used by other vehicles
+    pub fn first_free(&self, occupied: &[u32], spacing_hz: u32) -> Option<u8> {
+        self.frequencies()
+            .iter()
+            .position(|f| occupied.iter().all(|o| f.abs_diff(*o) >= spacing_hz))
+            .map(|i| i as u8)
+    }
+    #[cfg(feature = "receiver")]
+    pub(crate) fn parse(hwid: u16, msg: &[u8]) -> Option<Self> {
+        let (&len, rest) = msg.split_first()?;
+        let (&active, rest) = rest.split_first()?;
+        let len = len as usize;
+        if len > MAX_CHANNELS || rest.len() < len * 4 {
+            return None;
+        }
+        let mut frequencies = [0; MAX_CHANNELS];
+        for (f, bytes) in frequencies.iter_mut().zip(rest[..len * 4].chunks_exact(4)) {
+            *f = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Some(Self {
+            hwid,
+            active,
+            len: len as u8,
+            frequencies,
+        })
+    }
+}
+
+/// command payload programming the channel table, None if the table is too long
+#[cfg(feature = "sender")]
+pub(crate) fn encode_table(
+    frequencies: &[u32],
+) -> Option<heapless::Vec<u8, { 2 + 4 * MAX_CHANNELS }>> {
+    if frequencies.len() > MAX_CHANNELS {
+        return None;
+    }
+    let mut msg = heapless::Vec::new();
+    msg.push(SET_CHANNELS).unwrap();
+    msg.push(frequencies.len() as u8).unwrap();
+    for f in frequencies {
+        msg.extend_from_slice(&f.to_le_bytes()).unwrap();
+    }
+    Some(msg)
+}
+
+#[cfg(all(test, feature = "sender", feature = "receiver"))]
+mod tests {
+    use super::*;
+
+    const FREQUENCIES: [u32; 3] = [433_100_000, 433_500_000, 434_000_000];
+
+    // channel table reply as sent by the lst
+    fn reply(active: u8) -> heapless::Vec<u8, { 2 + 4 * MAX_CHANNELS }> {
+        let mut msg = encode_table(&FREQUENCIES).unwrap();
+        msg[0] = FREQUENCIES.len() as u8;
+        msg[1] = active;
+        msg
+    }
+
+    #[test]
+    fn table_roundtrip() {
+        let table = ChannelTable::parse(0x2DED, &reply(2)).unwrap();
+        assert_eq!(table.frequencies(), FREQUENCIES);
+        assert_eq!(table.active_frequency(), Some(434_000_000));
+        assert!(ChannelTable::parse(0x2DED, &reply(2)[..13]).is_none());
+        assert!(encode_table(&[0; MAX_CHANNELS + 1]).is_none());
+    }
+
+    #[test]
+    fn picks_channel_clear_of_other_vehicles() {
+        let table = ChannelTable::parse(0x2DED, &reply(0)).unwrap();
+        assert_eq!(table.first_free(&[433_150_000], 100_000), Some(1));
+        assert_eq!(table.first_free(&FREQUENCIES[..2], 100_000), Some(2));
+        assert_eq!(table.first_free(&FREQUENCIES, 100_000), None);
+    }
+}
@@ -0,0 +1,226 @@
+use embassy_futures::select::{Either, select};
+use embedded_io_async::Write;
+
+use crate::{
+    clock::Clock,
+    lst_channels::ChannelTable,
+    lst_receiver::{LSTMessage, LSTVersion, MessageSource, ReceiverError},
+    lst_sender::{LSTCmd, LSTSender, SenderError},
+};
+
+// time between telemetry requests while waiting for the lst to come back up
+const BOOT_POLL_INTERVAL_MS: u32 = 500;
+
+/// telemetry received from the freshly booted lst
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub struct BootConfirmation {
+    pub uptime: u32,
+    pub polls: u32,
+}
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum RebootError<TxError, RxError> {
+    SendError(SenderError<TxError>),
+    ReceiveError(ReceiverError<RxError>),
+    Timeout,
+}
+
+/// reboot the local lst and wait until it answers with the telemetry of a fresh boot
+pub async fn reboot_radio<S: Write, R: MessageSource, C: Clock>(
+    sender: &mut LSTSender<S>,
+    receiver: &mut R,
+    clock: &mut C,
+    timeout_ms: u32,
+) -> Result<BootConfirmation, RebootError<S::Error, R::Error>> {
+    sender
+        .cmd(LSTCmd::Reboot)
+        .await
+        .map_err(RebootError::SendError)?;
+
+    // telemetry still in flight from before the reboot has a higher uptime than this
+    let max_uptime = timeout_ms / 1000 + 1;
+    let deadline = clock.now_us() + timeout_ms as u64 * 1000;
+    let mut polls = 0;
+    while clock.now_us() < deadline {
+        polls += 1;
+        sender
+            .cmd(LSTCmd::GetTelem)
+            .await
+            .map_err(RebootError::SendError)?;
+
+        let wait_for_telem = async {
+            loop {
+                match receiver.next_message().await {
+                    Ok(LSTMessage::Telem(tm)) if tm.uptime <= max_uptime => return Ok(tm.uptime),
+                    // a reply from before the reboot or unrelated traffic
+                    Ok(_) => (),
+                    // the uart sees garbage while the lst restarts
+                    Err(ReceiverError::ParseError(_) | ReceiverError::MsgTooShort) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        match select(wait_for_telem, clock.delay_ms(BOOT_POLL_INTERVAL_MS)).await {
+            Either::First(Ok(uptime)) => return Ok(BootConfirmation { uptime, polls }),
+            Either::First(Err(e)) => return Err(RebootError::ReceiveError(e)),
+            Either::Second(()) => (),
+        }
+    }
+    Err(RebootError::Timeout)
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum VersionError<TxError, RxError> {
+    SendError(SenderError<TxError>),
+    ReceiveError(ReceiverError<RxError>),
+    Timeout,
+}
+
+/// request the version of the local lst and wait for the reply
+pub async fn read_version<S: Write, R: MessageSource, C: Clock>(
+    sender: &mut LSTSender<S>,
+    receiver: &mut R,
+    clock: &mut C,
+    timeout_ms: u32,
+) -> Result<LSTVersion, VersionError<S::Error, R::Error>> {
+    sender
+        .cmd(LSTCmd::GetVersion)
+        .await
+        .map_err(VersionError::SendError)?;
+    let wait_for_version = async {
+        loop {
+            match receiver.next_message().await {
+                Ok(LSTMessage::Version(version)) => return Ok(version),
+                Ok(_) => (),
+                Err(e) => return Err(VersionError::ReceiveError(e)),
+            }
+        }
+    };
+    match select(wait_for_version, clock.delay_ms(timeout_ms)).await {
+        Either::First(result) => result,
+        Either::Second(()) => Err(VersionError::Timeout),
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum ChannelError<TxError, RxError> {
+    SendError(SenderError<TxError>),
+    ReceiveError(ReceiverError<RxError>),
+    Timeout,
+    NoFreeChannel,
+    /// the lst nacked the request, its firmware has no channel commands
+    Unsupported,
+}
+
+/// read the channel table of the local lst, Unsupported if the firmware lacks the channel commands
+pub async fn read_channels<S: Write, R: MessageSource, C: Clock>(
+    sender: &mut LSTSender<S>,
+    receiver: &mut R,
+    clock: &mut C,
+    timeout_ms: u32,
+) -> Result<ChannelTable, ChannelError<S::Error, R::Error>> {
+    sender
+        .cmd(LSTCmd::GetChannels)
+        .await
+        .map_err(ChannelError::SendError)?;
+    let wait_for_table = async {
+        loop {
+            match receiver.next_message().await {
+                Ok(LSTMessage::Channels(table)) => return Ok(table),
+                Ok(LSTMessage::Nack) => return Err(ChannelError::Unsupported),
+                Ok(_) => (),
+                Err(e) => return Err(ChannelError::ReceiveError(e)),
+            }
+        }
+    };
+    match select(wait_for_table, clock.delay_ms(timeout_ms)).await {
+        Either::First(result) => result,
+        Either::Second(()) => Err(ChannelError::Timeout),
+    }
+}
+
+/// frequency coordination hook: move the local lst to the first channel of its table
+/// that keeps spacing_hz to all frequencies in use by other vehicles, returns the channel
+pub async fn coordinate_channel<S: Write, R: MessageSource, C: Clock>(
+    sender: &mut LSTSender<S>,
+    receiver: &mut R,
+    clock: &mut C,
+    occupied: &[u32],
+    spacing_hz: u32,
+    timeout_ms: u32,
+) -> Result<u8, ChannelError<S::Error, R::Error>> {
+    let table = read_channels(sender, receiver, clock, timeout_ms).await?;
+    let channel = table
+        .first_free(occupied, spacing_hz)
+        .ok_or(ChannelError::NoFreeChannel)?;
+    if channel != table.active {
+        sender
+            .select_channel(channel)
+            .await
+            .map_err(ChannelError::SendError)?;
+    }
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, lst_receiver::LSTReceiver};
+    use embassy_futures::block_on;
+    use embedded_io_async::Read;
+
+    // uart to a lst that never answers
+    struct Silent;
+
+    impl embedded_io_async::ErrorType for Silent {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Silent {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl Read for Silent {
+        async fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Self::Error> {
+            core::future::pending().await
+        }
+    }
+
+    // lst with the stock firmware, nacks the channel commands
+    struct Nacking;
+
+    impl MessageSource for Nacking {
+        type Error = core::convert::Infallible;
+
+        async fn next_message(&mut self) -> Result<LSTMessage<'_>, ReceiverError<Self::Error>> {
+            Ok(LSTMessage::Nack)
+        }
+    }
+
+    #[test]
+    fn channels_unsupported_on_stock_firmware() {
+        let mut sender = LSTSender::new(Silent, 0x2DEC);
+        let mut clock = MockClock::new();
+        let result = block_on(read_channels(&mut sender, &mut Nacking, &mut clock, 1000));
+        assert!(matches!(result, Err(ChannelError::Unsupported)));
+    }
+
+    #[test]
+    fn reboot_times_out_on_the_clock() {
+        let mut sender = LSTSender::new(Silent, 0x2DEC);
+        let mut receiver = LSTReceiver::new(Silent);
+        let mut clock = MockClock::new();
+        let result = block_on(reboot_radio(&mut sender, &mut receiver, &mut clock, 2000));
+        assert!(matches!(result, Err(RebootError::Timeout)));
+        assert_eq!(clock.now_us(), 2_000_000);
+    }
+}
@@ -1,17 +1,52 @@
+use core::task::Poll;
+
+use embassy_futures::poll_once;
 use embedded_io_async::{Read, ReadExactError};
+use heapless::{Deque, Vec};
+
+use crate::bootloader_protocol::{BOOTLOADER_ACK, BOOTLOADER_NACK};
+use crate::header_profile::{HeaderProfile, OPENLST_HEADER};
+use crate::lst_channels::{CHANNELS, ChannelTable};
+use crate::telemetry_layout::OPENLST_TELEMETRY;
 
-const HEADER_LEN: usize = 5;
 const MAGIC: [u8; 2] = [0x22, 0x69];
 
-const DESTINATION_PTR: usize = 0x04;
 const DESTINATION_RELAY: u8 = 0x11;
 const DESTINATION_LOCAL: u8 = 0x01;
 
 const MAX_LEN: usize = 256;
 
+// number of recent (hwid, seq num, destination) keys remembered for duplicate suppression.
+// The destination is part of the key, local replies and relayed frames are numbered
+// independently and may share a seq num
+const DEDUP_WINDOW: usize = 8;
+
+// relay frames held back while local frames are waiting behind them
+const RELAY_QUEUE_LEN: usize = 4;
+
+/// order in which local and relayed frames are returned from the receiver
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrafficPolicy {
+    /// frames are returned in the order they were received
+    Fifo,
+    /// relayed frames are held back as long as further bytes are already waiting
+    /// on the uart, so local replies (acks, telemetry) are not stuck behind bulk
+    /// relay traffic. Relay frames keep their order and are never dropped
+    LocalFirst,
+}
+
 pub struct LSTReceiver<S: Read> {
     uart_rx: S,
     buffer: [u8; MAX_LEN],
+    recent: [Option<(u16, u16, u8)>; DEDUP_WINDOW],
+    recent_pos: usize,
+    duplicates: u32,
+    promiscuous: bool,
+    profile: HeaderProfile,
+    policy: TrafficPolicy,
+    relay_queue: Deque<Vec<u8, MAX_LEN>, RELAY_QUEUE_LEN>,
+    pending: Option<u8>,
 }
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug)]
@@ -21,7 +56,7 @@ pub enum ReceiverError<UartError> {
     MsgTooShort,
 }
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LSTTelemetry {
     pub uptime: u32,
     pub rssi: i8,
@@ -31,48 +66,177 @@ pub struct LSTTelemetry {
     pub packets_rejected_checksum: u32,
     pub packets_rejected_other: u32,
 }
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct LSTVersion {
+    pub hwid: u16,
+    pub git_rev: u32,
+}
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureReason {
+    UnknownDestination,
+    UnknownCommand,
+    Malformed,
+}
 pub enum LSTMessage<'a> {
     Relay(&'a [u8]),
     Telem(LSTTelemetry),
+    Version(LSTVersion),
+    Channels(ChannelTable),
     Ack,
     Nack,
+    /// reply of the bootloader with its ack message
+    BootloaderAck(u8),
+    BootloaderNack,
     Unknown(u8, &'a [u8]),
+    /// raw frame (header included, without start bytes and length) that could
+    /// not be handled, only surfaced in promiscuous mode
+    Captured(CaptureReason, &'a [u8]),
+}
+
+/// copy of a message that outlives the receive buffer, to hand it from the task
+/// owning the receiver to other tasks
+#[derive(Clone)]
+pub enum OwnedLSTMessage {
+    Relay(Vec<u8, MAX_LEN>),
+    Telem(LSTTelemetry),
+    Version(LSTVersion),
+    Channels(ChannelTable),
+    Ack,
+    Nack,
+    BootloaderAck(u8),
+    BootloaderNack,
+    Unknown(u8, Vec<u8, MAX_LEN>),
+    Captured(CaptureReason, Vec<u8, MAX_LEN>),
+}
+
+impl LSTMessage<'_> {
+    pub fn into_owned(self) -> OwnedLSTMessage {
+        // frames never exceed the receive buffer
+        let copy = |bytes: &[u8]| Vec::from_slice(bytes).unwrap();
+        match self {
+            LSTMessage::Relay(frame) => OwnedLSTMessage::Relay(copy(frame)),
+            LSTMessage::Telem(tm) => OwnedLSTMessage::Telem(tm),
+            LSTMessage::Version(version) => OwnedLSTMessage::Version(version),
+            LSTMessage::Channels(table) => OwnedLSTMessage::Channels(table),
+            LSTMessage::Ack => OwnedLSTMessage::Ack,
+            LSTMessage::Nack => OwnedLSTMessage::Nack,
+            LSTMessage::BootloaderAck(ack) => OwnedLSTMessage::BootloaderAck(ack),
+            LSTMessage::BootloaderNack => OwnedLSTMessage::BootloaderNack,
+            LSTMessage::Unknown(cmd, msg) => OwnedLSTMessage::Unknown(cmd, copy(msg)),
+            LSTMessage::Captured(reason, frame) => OwnedLSTMessage::Captured(reason, copy(frame)),
+        }
+    }
+}
+
+impl OwnedLSTMessage {
+    pub fn as_message(&self) -> LSTMessage<'_> {
+        match self {
+            OwnedLSTMessage::Relay(frame) => LSTMessage::Relay(frame),
+            OwnedLSTMessage::Telem(tm) => LSTMessage::Telem(tm.clone()),
+            OwnedLSTMessage::Version(version) => LSTMessage::Version(version.clone()),
+            OwnedLSTMessage::Channels(table) => LSTMessage::Channels(*table),
+            OwnedLSTMessage::Ack => LSTMessage::Ack,
+            OwnedLSTMessage::Nack => LSTMessage::Nack,
+            OwnedLSTMessage::BootloaderAck(ack) => LSTMessage::BootloaderAck(*ack),
+            OwnedLSTMessage::BootloaderNack => LSTMessage::BootloaderNack,
+            OwnedLSTMessage::Unknown(cmd, msg) => LSTMessage::Unknown(*cmd, msg),
+            OwnedLSTMessage::Captured(reason, frame) => LSTMessage::Captured(*reason, frame),
+        }
+    }
+}
+
+/// source of lst messages for the request helpers waiting on a reply: the receiver
+/// itself, or a queue fed by the task owning it. Receiving is not cancel safe, a
+/// receiver selected against other futures has to live in its own task
+pub trait MessageSource {
+    type Error;
+    fn next_message(
+        &mut self,
+    ) -> impl Future<Output = Result<LSTMessage<'_>, ReceiverError<Self::Error>>>;
+}
+
+impl<S: Read> MessageSource for LSTReceiver<S> {
+    type Error = S::Error;
+    fn next_message(
+        &mut self,
+    ) -> impl Future<Output = Result<LSTMessage<'_>, ReceiverError<Self::Error>>> {
+        self.receive()
+    }
 }
 
 impl<S: Read> LSTReceiver<S> {
     pub const fn new(uart_rx: S) -> Self {
+        Self::with_profile(uart_rx, OPENLST_HEADER)
+    }
+    /// receiver for radio builds with a different header layout
+    pub const fn with_profile(uart_rx: S, profile: HeaderProfile) -> Self {
+        assert!(profile.is_valid(), "invalid lst header profile");
         Self {
             uart_rx,
             buffer: [0; _],
+            recent: [None; _],
+            recent_pos: 0,
+            duplicates: 0,
+            promiscuous: false,
+            profile,
+            policy: TrafficPolicy::Fifo,
+            relay_queue: Deque::new(),
+            pending: None,
+        }
+    }
+    /// in promiscuous mode frames with unexpected destination, unknown commands
+    /// or malformed content are surfaced as raw captured frames instead of being dropped
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        self.promiscuous = enabled;
+    }
+    pub fn set_policy(&mut self, policy: TrafficPolicy) {
+        self.policy = policy;
+    }
+    /// number of relay frames currently held back by the local first policy
+    pub fn queued_relay_frames(&self) -> usize {
+        self.relay_queue.len()
+    }
+    /// the uart below the receiver, e.g. to reinitialize it after persistent errors
+    pub fn uart_mut(&mut self) -> &mut S {
+        &mut self.uart_rx
+    }
+    /// number of frames suppressed as duplicates since startup
+    pub fn duplicate_count(&self) -> u32 {
+        self.duplicates
+    }
+    /// check the (hwid, seq num, destination) key of the buffered frame against the
+    /// recently received frames and remember it if it is new
+    fn is_duplicate(&mut self) -> bool {
+        let key = Some((
+            self.profile.hwid(&self.buffer),
+            self.profile.seq_num(&self.buffer),
+            self.profile.dest(&self.buffer),
+        ));
+        if self.recent.contains(&key) {
+            return true;
         }
+        self.recent[self.recent_pos] = key;
+        self.recent_pos = (self.recent_pos + 1) % DEDUP_WINDOW;
+        false
     }
     fn parse_telem(msg: &[u8]) -> Result<LSTTelemetry, ReceiverError<S::Error>> {
-        // 62 bytes
-        if msg.len() < 62 {
-            Err(ReceiverError::ParseError("telem msg too short"))
+        OPENLST_TELEMETRY
+            .parse(msg)
+            .ok_or(ReceiverError::ParseError("telem msg too short"))
+    }
+    fn parse_version(hwid: u16, msg: &[u8]) -> Result<LSTVersion, ReceiverError<S::Error>> {
+        if msg.len() < 4 {
+            Err(ReceiverError::ParseError("version msg too short"))
         } else {
-            Ok(LSTTelemetry {
-                // u8 reserved: 0
-                uptime: u32::from_le_bytes(msg[1..5].try_into().unwrap()),
-                // u32 uart0 rx count: 5..9
-                // u32 uart1 rx count: 9..13
-                // u8 rx mode: 13
-                // u8 tx mode: 14
-                // i16 * 10 ADC channels: 15..35
-                rssi: msg[35] as i8,
-                lqi: msg[36] as u8,
-                // i8 last frequency estimate: 37
-                packets_sent: u32::from_le_bytes(msg[38..42].try_into().unwrap()),
-                // u32 cs_count (sending collision count): 42..46
-                packets_good: u32::from_le_bytes(msg[46..50].try_into().unwrap()),
-                packets_rejected_checksum: u32::from_le_bytes(msg[50..54].try_into().unwrap()),
-                packets_rejected_other: u32::from_le_bytes(msg[58..62].try_into().unwrap())
-                    + u32::from_le_bytes(msg[54..58].try_into().unwrap()),
-                // reserved + custom
+            Ok(LSTVersion {
+                hwid,
+                git_rev: u32::from_le_bytes(msg[0..4].try_into().unwrap()),
             })
         }
     }
-    fn parse_local_msg(msg: &[u8]) -> Result<LSTMessage<'_>, ReceiverError<S::Error>> {
+    fn parse_local_msg(hwid: u16, msg: &[u8]) -> Result<LSTMessage<'_>, ReceiverError<S::Error>> {
         // parsing the available commands from the openlst firmware
         Ok(
             match msg
@@ -82,18 +246,47 @@ impl<S: Read> LSTReceiver<S> {
                 0x10 => LSTMessage::Ack,
                 0xFF => LSTMessage::Nack,
                 0x18 => LSTMessage::Telem(Self::parse_telem(&msg[1..])?),
+                0x1D => LSTMessage::Version(Self::parse_version(hwid, &msg[1..])?),
+                &CHANNELS => LSTMessage::Channels(
+                    ChannelTable::parse(hwid, &msg[1..])
+                        .ok_or(ReceiverError::ParseError("invalid channel table msg"))?,
+                ),
+                &BOOTLOADER_ACK => LSTMessage::BootloaderAck(
+                    *msg.get(1)
+                        .ok_or(ReceiverError::ParseError("bootloader ack msg too short"))?,
+                ),
+                &BOOTLOADER_NACK => LSTMessage::BootloaderNack,
                 unknown => LSTMessage::Unknown(*unknown, &msg[1..]),
             },
         )
     }
+    /// check without blocking if the next byte has already arrived
+    fn poll_pending(&mut self) -> Result<bool, ReceiverError<S::Error>> {
+        if self.pending.is_some() {
+            return Ok(true);
+        }
+        let mut byte: u8 = 0;
+        match poll_once(self.uart_rx.read_exact(core::slice::from_mut(&mut byte))) {
+            Poll::Ready(result) => {
+                result.map_err(ReceiverError::ReadError)?;
+                self.pending = Some(byte);
+                Ok(true)
+            }
+            Poll::Pending => Ok(false),
+        }
+    }
     async fn sync_frame(&mut self) -> Result<(), ReceiverError<S::Error>> {
         let mut magic_pos = 0;
         loop {
             let mut byte: u8 = 0;
-            self.uart_rx
-                .read_exact(core::slice::from_mut(&mut byte))
-                .await
-                .map_err(ReceiverError::ReadError)?;
+            if let Some(pending) = self.pending.take() {
+                byte = pending;
+            } else {
+                self.uart_rx
+                    .read_exact(core::slice::from_mut(&mut byte))
+                    .await
+                    .map_err(ReceiverError::ReadError)?;
+            }
             if byte == MAGIC[magic_pos] {
                 magic_pos += 1;
                 if magic_pos == MAGIC.len() {
@@ -106,7 +299,7 @@ impl<S: Read> LSTReceiver<S> {
             }
         }
     }
-    pub async fn receive(&mut self) -> Result<LSTMessage<'_>, ReceiverError<S::Error>> {
+    async fn receive_frame(&mut self) -> Result<usize, ReceiverError<S::Error>> {
         // finding framing bytes
         self.sync_frame().await?;
 
@@ -118,7 +311,7 @@ impl<S: Read> LSTReceiver<S> {
             .map_err(ReceiverError::ReadError)?;
         let len = len as usize;
 
-        if len < HEADER_LEN {
+        if len < self.profile.len {
             return Err(ReceiverError::MsgTooShort);
         }
 
@@ -131,12 +324,141 @@ impl<S: Read> LSTReceiver<S> {
         #[cfg(feature = "defmt")]
         defmt::trace!("read lst packet");
 
-        return Ok(match self.buffer[DESTINATION_PTR] {
+        Ok(len)
+    }
+    /// move the oldest held back relay frame into the receive buffer
+    fn dequeue_relay(&mut self) -> usize {
+        let frame = self.relay_queue.pop_front().unwrap();
+        self.buffer[..frame.len()].copy_from_slice(&frame);
+        frame.len()
+    }
+    pub async fn receive(&mut self) -> Result<LSTMessage<'_>, ReceiverError<S::Error>> {
+        let len = loop {
+            if !self.relay_queue.is_empty() && !self.poll_pending()? {
+                // nothing else waiting on the uart
+                break self.dequeue_relay();
+            }
+            let len = self.receive_frame().await?;
+            if self.is_duplicate() {
+                self.duplicates = self.duplicates.wrapping_add(1);
+                #[cfg(feature = "defmt")]
+                defmt::debug!("suppressed duplicate lst frame");
+                continue;
+            }
+            if self.policy != TrafficPolicy::LocalFirst
+                || self.profile.dest(&self.buffer) != DESTINATION_RELAY
+            {
+                break len;
+            }
+            let oldest = if self.relay_queue.is_full() {
+                self.relay_queue.pop_front()
+            } else {
+                None
+            };
+            let mut frame = Vec::new();
+            frame.extend_from_slice(&self.buffer[..len]).unwrap();
+            let _ = self.relay_queue.push_back(frame);
+            if let Some(oldest) = oldest {
+                // queue full, hand out the oldest relay frame to keep the order
+                self.buffer[..oldest.len()].copy_from_slice(&oldest);
+                break oldest.len();
+            }
+        };
+        self.parse_frame(len)
+    }
+    fn parse_frame(&self, len: usize) -> Result<LSTMessage<'_>, ReceiverError<S::Error>> {
+        let promiscuous = self.promiscuous;
+        let frame = &self.buffer[..len];
+        let header_len = self.profile.len;
+        let msg = match self.profile.dest(frame) {
             // msg comming from this lst, not relay
-            DESTINATION_LOCAL => Self::parse_local_msg(&self.buffer[HEADER_LEN..len])?,
+            DESTINATION_LOCAL => {
+                let hwid = self.profile.hwid(frame);
+                Self::parse_local_msg(hwid, &frame[header_len..])
+            }
             // msg received from other lst
-            DESTINATION_RELAY => LSTMessage::Relay(&self.buffer[HEADER_LEN..len]),
-            _ => LSTMessage::Unknown(0x00, &[]),
-        });
+            DESTINATION_RELAY => Ok(LSTMessage::Relay(&frame[header_len..])),
+            _ if promiscuous => Ok(LSTMessage::Captured(
+                CaptureReason::UnknownDestination,
+                frame,
+            )),
+            _ => Ok(LSTMessage::Unknown(0x00, &[])),
+        };
+        if !promiscuous {
+            return msg;
+        }
+        Ok(match msg {
+            Ok(LSTMessage::Unknown(_, _)) => {
+                LSTMessage::Captured(CaptureReason::UnknownCommand, frame)
+            }
+            Err(ReceiverError::ParseError(_)) => {
+                LSTMessage::Captured(CaptureReason::Malformed, frame)
+            }
+            other => other?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    // uart with all bytes already received, waits forever once drained
+    struct Received<'a>(&'a [u8]);
+
+    impl embedded_io_async::ErrorType for Received<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for Received<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.0.is_empty() {
+                core::future::pending::<()>().await;
+            }
+            let n = buf.len().min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn local_first_overtakes_relay() {
+        #[rustfmt::skip]
+        let stream = [
+            0x22, 0x69, 6, 0xED, 0x2D, 0x01, 0x00, 0x11, 0xA1,
+            0x22, 0x69, 6, 0xED, 0x2D, 0x02, 0x00, 0x11, 0xA2,
+            0x22, 0x69, 6, 0xED, 0x2D, 0x03, 0x00, 0x01, 0x10,
+        ];
+        let mut receiver = LSTReceiver::new(Received(&stream));
+        receiver.set_policy(TrafficPolicy::LocalFirst);
+        let mut next = || match block_on(receiver.receive()).unwrap() {
+            LSTMessage::Ack => 0x10,
+            LSTMessage::Relay(msg) => msg[0],
+            _ => panic!("unexpected message"),
+        };
+        assert_eq!([next(), next(), next()], [0x10, 0xA1, 0xA2]);
+    }
+
+    #[test]
+    fn duplicates_are_keyed_by_destination() {
+        #[rustfmt::skip]
+        let stream = [
+            0x22, 0x69, 6, 0xED, 0x2D, 0x01, 0x00, 0x11, 0xA1,
+            // local reply with the seq num of the relay frame
+            0x22, 0x69, 6, 0xED, 0x2D, 0x01, 0x00, 0x01, 0x10,
+            // retransmission of the relay frame
+            0x22, 0x69, 6, 0xED, 0x2D, 0x01, 0x00, 0x11, 0xA1,
+            0x22, 0x69, 6, 0xED, 0x2D, 0x02, 0x00, 0x11, 0xA2,
+        ];
+        let mut receiver = LSTReceiver::new(Received(&stream));
+        let mut next = || match block_on(receiver.receive()).unwrap() {
+            LSTMessage::Ack => 0x10,
+            LSTMessage::Relay(msg) => msg[0],
+            _ => panic!("unexpected message"),
+        };
+        assert_eq!([next(), next(), next()], [0xA1, 0x10, 0xA2]);
+        assert_eq!(receiver.duplicate_count(), 1);
     }
 }
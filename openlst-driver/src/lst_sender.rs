@@ -1,8 +1,17 @@
 use embedded_io_async::Write;
 use heapless::Vec;
 
-const FULL_HEADER_LEN: usize = 8;
-const HEADER_LEN: u8 = 5;
+use crate::{
+    bootloader_protocol::{
+        BOOTLOADER_ERASE, BOOTLOADER_PING, BOOTLOADER_WRITE_PAGE, FINISH_PAGE, PAGE_SIZE,
+    },
+    header_profile::{HeaderProfile, MAX_HEADER_LEN, OPENLST_HEADER},
+    lst_channels::{SELECT_CHANNEL, encode_table},
+    relay_route::Route,
+};
+
+// start bytes and length byte in front of the header
+const FRAMING_LEN: usize = 3;
 const MAX_LEN: usize = 256;
 
 const DESTINATION_RELAY: u8 = 0x11;
@@ -12,50 +21,88 @@ const DESTINATION_LOCAL: u8 = 0x01;
 pub enum LSTCmd {
     Reboot = 0x12,
     GetTelem = 0x17,
+    GetVersion = 0x1C,
+    /// needs a radio build with the channel commands, see lst_channels
+    GetChannels = 0x32,
 }
 
 pub struct LSTSender<S: Write> {
     uart_tx: S,
     hwid: u16,
     seq_num: u16,
+    profile: HeaderProfile,
+    rf_hook: Option<fn()>,
 }
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug)]
 pub enum SenderError<UartError> {
     MessageTooLongError,
+    TooManyChannels,
     WriteError(UartError),
 }
 
 impl<S: Write> LSTSender<S> {
     pub fn new(uart_tx: S, hwid: u16) -> Self {
+        Self::with_profile(uart_tx, hwid, OPENLST_HEADER)
+    }
+    /// sender for radio builds with a different header layout
+    pub fn with_profile(uart_tx: S, hwid: u16, profile: HeaderProfile) -> Self {
+        assert!(profile.is_valid(), "invalid lst header profile");
         Self {
             uart_tx,
             hwid,
             seq_num: 0,
+            profile,
+            rf_hook: None,
         }
     }
-    pub fn get_header(&mut self, msg_len: u8, dest: u8) -> [u8; FULL_HEADER_LEN] {
-        let header = [
-            0x22,
-            0x69,                 // Uart start bytes
-            msg_len + HEADER_LEN, // packet length (+5 for remaining header)
-            self.hwid as u8,
-            (self.hwid >> 8) as u8, // Hardware ID
-            self.seq_num as u8,
-            (self.seq_num >> 8) as u8, // SeqNum
-            dest,                      // Destination (0x01: LST, 0x11: Relay)
-        ];
+    /// called after each frame the lst puts on air, relayed frames and remote commands,
+    /// e.g. to hold off rf switching while the lst transmits
+    pub fn set_rf_hook(&mut self, hook: fn()) {
+        self.rf_hook = Some(hook);
+    }
+    pub fn get_header(
+        &mut self,
+        msg_len: u8,
+        dest: u8,
+    ) -> Vec<u8, { FRAMING_LEN + MAX_HEADER_LEN }> {
+        self.get_header_for(msg_len, self.hwid, dest)
+    }
+    fn get_header_for(
+        &mut self,
+        msg_len: u8,
+        hwid: u16,
+        dest: u8,
+    ) -> Vec<u8, { FRAMING_LEN + MAX_HEADER_LEN }> {
+        let mut header = [0; FRAMING_LEN + MAX_HEADER_LEN];
+        header[0] = 0x22; // Uart start bytes
+        header[1] = 0x69;
+        header[2] = msg_len + self.profile.len as u8; // packet length (+ remaining header)
+        self.profile
+            .write(&mut header[FRAMING_LEN..], hwid, self.seq_num, dest);
         self.seq_num = self.seq_num.wrapping_add(1);
-        header
+        let mut full_header = Vec::new();
+        full_header
+            .extend_from_slice(&header[..FRAMING_LEN + self.profile.len])
+            .unwrap();
+        full_header
     }
     async fn send(&mut self, msg: &[u8], destination: u8) -> Result<(), SenderError<S::Error>> {
-        if msg.len() > MAX_LEN - FULL_HEADER_LEN {
+        self.send_to(msg, self.hwid, destination).await
+    }
+    async fn send_to(
+        &mut self,
+        msg: &[u8],
+        hwid: u16,
+        destination: u8,
+    ) -> Result<(), SenderError<S::Error>> {
+        if msg.len() > MAX_LEN - FRAMING_LEN - self.profile.len {
             return Err(SenderError::MessageTooLongError);
         }
 
         let mut packet: Vec<u8, MAX_LEN> = Vec::new();
         packet
-            .extend_from_slice(&self.get_header(msg.len() as u8, destination))
+            .extend_from_slice(&self.get_header_for(msg.len() as u8, hwid, destination))
             .unwrap();
         packet.extend_from_slice(msg).unwrap();
 
@@ -70,12 +117,126 @@ impl<S: Write> LSTSender<S> {
         #[cfg(feature = "defmt")]
         defmt::trace!("end writing lst packet");
 
+        if (destination == DESTINATION_RELAY || hwid != self.hwid)
+            && let Some(hook) = self.rf_hook
+        {
+            hook();
+        }
         Ok(())
     }
     pub async fn relay(&mut self, msg: &[u8]) -> Result<(), SenderError<S::Error>> {
         self.send(msg, DESTINATION_RELAY).await
     }
+    /// relay a frame behind a routing header, for radios reached over repeaters
+    pub async fn relay_routed(
+        &mut self,
+        route: &Route,
+        msg: &[u8],
+    ) -> Result<(), SenderError<S::Error>> {
+        let mut routed: Vec<u8, MAX_LEN> = Vec::new();
+        routed.extend_from_slice(&route.to_bytes()).unwrap();
+        routed
+            .extend_from_slice(msg)
+            .map_err(|_| SenderError::MessageTooLongError)?;
+        self.relay(&routed).await
+    }
     pub async fn cmd(&mut self, cmd: LSTCmd) -> Result<(), SenderError<S::Error>> {
-        self.send(core::slice::from_ref(&(cmd as u8)), DESTINATION_LOCAL).await
+        self.send(core::slice::from_ref(&(cmd as u8)), DESTINATION_LOCAL)
+            .await
+    }
+    /// send a command to the lst with the given hwid. Commands not addressed
+    /// to the local lst are forwarded over rf by the openlst firmware
+    pub async fn cmd_remote(
+        &mut self,
+        hwid: u16,
+        cmd: LSTCmd,
+    ) -> Result<(), SenderError<S::Error>> {
+        self.send_to(core::slice::from_ref(&(cmd as u8)), hwid, DESTINATION_LOCAL)
+            .await
+    }
+    /// program the channel table of the local lst, frequencies in Hz.
+    /// Needs a radio build with the channel commands, see lst_channels
+    pub async fn set_channels(&mut self, frequencies: &[u32]) -> Result<(), SenderError<S::Error>> {
+        let msg = encode_table(frequencies).ok_or(SenderError::TooManyChannels)?;
+        self.send(&msg, DESTINATION_LOCAL).await
+    }
+    /// switch the local lst to a channel of its table, needs the channel commands
+    pub async fn select_channel(&mut self, index: u8) -> Result<(), SenderError<S::Error>> {
+        self.send(&[SELECT_CHANNEL, index], DESTINATION_LOCAL).await
+    }
+    /// switch the lst with the given hwid to a channel of its table, the
+    /// local lst has to follow to stay in contact
+    pub async fn select_channel_remote(
+        &mut self,
+        hwid: u16,
+        index: u8,
+    ) -> Result<(), SenderError<S::Error>> {
+        self.send_to(&[SELECT_CHANNEL, index], hwid, DESTINATION_LOCAL)
+            .await
+    }
+    /// ping the bootloader of the local lst, only answered right after a reset
+    pub async fn bootloader_ping(&mut self) -> Result<(), SenderError<S::Error>> {
+        self.send(&[BOOTLOADER_PING], DESTINATION_LOCAL).await
+    }
+    /// erase the application of the local lst, the lst has to be in the bootloader
+    pub async fn bootloader_erase(&mut self) -> Result<(), SenderError<S::Error>> {
+        self.send(&[BOOTLOADER_ERASE], DESTINATION_LOCAL).await
+    }
+    /// write a page of the application flash through the bootloader
+    pub async fn bootloader_write_page(
+        &mut self,
+        page: u8,
+        data: &[u8; PAGE_SIZE],
+    ) -> Result<(), SenderError<S::Error>> {
+        let mut msg = [0; 2 + PAGE_SIZE];
+        msg[0] = BOOTLOADER_WRITE_PAGE;
+        msg[1] = page;
+        msg[2..].copy_from_slice(data);
+        self.send(&msg, DESTINATION_LOCAL).await
+    }
+    /// end the update, the bootloader boots the new application if its signature is valid
+    pub async fn bootloader_finish(&mut self) -> Result<(), SenderError<S::Error>> {
+        self.send(&[BOOTLOADER_WRITE_PAGE, FINISH_PAGE], DESTINATION_LOCAL)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use embassy_futures::block_on;
+
+    static ON_AIR: AtomicU32 = AtomicU32::new(0);
+
+    struct Uart;
+
+    impl embedded_io_async::ErrorType for Uart {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Uart {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rf_hook_sees_frames_on_air_only() {
+        let mut sender = LSTSender::new(Uart, 0x2DEC);
+        sender.set_rf_hook(|| {
+            ON_AIR.fetch_add(1, Ordering::Relaxed);
+        });
+        block_on(sender.cmd(LSTCmd::GetTelem)).unwrap();
+        // the lst executes commands addressed to its own hwid itself
+        block_on(sender.cmd_remote(0x2DEC, LSTCmd::GetTelem)).unwrap();
+        assert_eq!(ON_AIR.load(Ordering::Relaxed), 0);
+        block_on(sender.relay(&[0x42])).unwrap();
+        block_on(sender.cmd_remote(0x2DED, LSTCmd::GetTelem)).unwrap();
+        assert_eq!(ON_AIR.load(Ordering::Relaxed), 2);
     }
 }
@@ -0,0 +1,140 @@
+pub use crate::link::ROUTED_ID;
+
+// header of a relayed frame routed over repeaters:
+// id(1) hops left(1) destination hwid(2 LE) origin hwid(2 LE) seq(2 LE) frame
+pub const ROUTE_HEADER_LEN: usize = 8;
+/// hop limit of new routes, bounds how often a frame is retransmitted
+pub const MAX_HOPS: u8 = 4;
+// number of recent (origin hwid, seq) pairs remembered, covers a frame arriving over several paths
+const SEEN_WINDOW: usize = 16;
+
+/// routing fields of a frame on its way over a chain of radios, e.g. the vehicle
+/// lst, a repeater lst on a balloon and the ground station
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Route {
+    pub hops_left: u8,
+    /// hwid of the final receiver
+    pub destination: u16,
+    /// hwid of the sender, with seq identifies the frame on all paths
+    pub origin: u16,
+    pub seq: u16,
+}
+
+impl Route {
+    pub fn new(destination: u16, origin: u16, seq: u16) -> Self {
+        Self {
+            hops_left: MAX_HOPS,
+            destination,
+            origin,
+            seq,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ROUTE_HEADER_LEN] {
+        let mut bytes = [0; ROUTE_HEADER_LEN];
+        bytes[0] = ROUTED_ID;
+        bytes[1] = self.hops_left;
+        bytes[2..4].copy_from_slice(&self.destination.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.origin.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.seq.to_le_bytes());
+        bytes
+    }
+
+    /// route and inner frame of a routed frame, None for other frames
+    pub fn parse(frame: &[u8]) -> Option<(Self, &[u8])> {
+        if frame.len() < ROUTE_HEADER_LEN || frame[0] != ROUTED_ID {
+            return None;
+        }
+        let route = Self {
+            hops_left: frame[1],
+            destination: u16::from_le_bytes([frame[2], frame[3]]),
+            origin: u16::from_le_bytes([frame[4], frame[5]]),
+            seq: u16::from_le_bytes([frame[6], frame[7]]),
+        };
+        Some((route, &frame[ROUTE_HEADER_LEN..]))
+    }
+}
+
+/// what to do with a received routed frame
+#[derive(Debug, PartialEq)]
+pub enum Routing<'a> {
+    /// addressed to this radio, with the inner frame
+    Deliver(&'a [u8]),
+    /// addressed to another radio, retransmit the inner frame with the next hop route
+    Forward(Route, &'a [u8]),
+    /// seen before, sent by this radio or out of hops
+    Drop,
+}
+
+/// Routing decisions of a radio in a chain. Frames are identified by origin and seq,
+/// so a frame looping between repeaters or arriving over several paths is handled once
+pub struct RelayRouter {
+    hwid: u16,
+    seen: [Option<(u16, u16)>; SEEN_WINDOW],
+    next: usize,
+}
+
+impl RelayRouter {
+    pub const fn new(hwid: u16) -> Self {
+        Self {
+            hwid,
+            seen: [None; SEEN_WINDOW],
+            next: 0,
+        }
+    }
+
+    pub fn route<'a>(&mut self, route: Route, frame: &'a [u8]) -> Routing<'a> {
+        let id = Some((route.origin, route.seq));
+        if route.origin == self.hwid || self.seen.contains(&id) {
+            return Routing::Drop;
+        }
+        self.seen[self.next] = id;
+        self.next = (self.next + 1) % SEEN_WINDOW;
+
+        if route.destination == self.hwid {
+            Routing::Deliver(frame)
+        } else if route.hops_left == 0 {
+            Routing::Drop
+        } else {
+            let next_hop = Route {
+                hops_left: route.hops_left - 1,
+                ..route
+            };
+            Routing::Forward(next_hop, frame)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_forwarded_once() {
+        let mut routed = [0; ROUTE_HEADER_LEN + 2];
+        routed[..ROUTE_HEADER_LEN].copy_from_slice(&Route::new(0x0001, 0x0003, 7).to_bytes());
+        routed[ROUTE_HEADER_LEN..].copy_from_slice(&[0xC1, 0xAA]);
+        let (route, frame) = Route::parse(&routed).unwrap();
+        assert_eq!(frame, &[0xC1, 0xAA]);
+
+        let mut repeater = RelayRouter::new(0x0002);
+        let Routing::Forward(next_hop, _) = repeater.route(route, frame) else {
+            panic!("not forwarded");
+        };
+        assert_eq!(next_hop.hops_left, MAX_HOPS - 1);
+        // the retransmission received back from another repeater
+        assert_eq!(repeater.route(next_hop, frame), Routing::Drop);
+
+        let mut ground = RelayRouter::new(0x0001);
+        assert_eq!(ground.route(next_hop, frame), Routing::Deliver(frame));
+        assert_eq!(ground.route(route, frame), Routing::Drop);
+
+        let exhausted = Route {
+            hops_left: 0,
+            seq: 8,
+            ..route
+        };
+        assert_eq!(repeater.route(exhausted, frame), Routing::Drop);
+    }
+}
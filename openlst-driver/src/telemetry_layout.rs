@@ -0,0 +1,171 @@
+#[cfg(feature = "receiver")]
+use crate::lst_receiver::LSTTelemetry;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// position of a single value inside the telemetry message
+#[derive(Clone, Copy, Debug)]
+pub struct FieldLayout {
+    pub offset: usize,
+    pub width: usize,
+    pub endianness: Endianness,
+}
+
+impl FieldLayout {
+    pub const fn le(offset: usize, width: usize) -> Self {
+        Self {
+            offset,
+            width,
+            endianness: Endianness::Little,
+        }
+    }
+    pub const fn end(&self) -> usize {
+        self.offset + self.width
+    }
+    /// read an unsigned value of up to 4 bytes
+    pub fn read_u32(&self, msg: &[u8]) -> Option<u32> {
+        let bytes = msg.get(self.offset..self.end())?;
+        if self.width > 4 {
+            return None;
+        }
+        let mut buf = [0u8; 4];
+        Some(match self.endianness {
+            Endianness::Little => {
+                buf[..self.width].copy_from_slice(bytes);
+                u32::from_le_bytes(buf)
+            }
+            Endianness::Big => {
+                buf[4 - self.width..].copy_from_slice(bytes);
+                u32::from_be_bytes(buf)
+            }
+        })
+    }
+    pub fn read_u8(&self, msg: &[u8]) -> Option<u8> {
+        self.read_u32(msg).map(|v| v as u8)
+    }
+    pub fn read_i8(&self, msg: &[u8]) -> Option<i8> {
+        self.read_u32(msg).map(|v| v as i8)
+    }
+}
+
+/// layout of the telemetry fields used from the firmware's telemetry_t
+#[derive(Clone, Copy, Debug)]
+pub struct TelemetryLayout {
+    pub uptime: FieldLayout,
+    pub last_rssi: FieldLayout,
+    pub last_lqi: FieldLayout,
+    pub packets_sent: FieldLayout,
+    pub packets_good: FieldLayout,
+    pub packets_rejected_checksum: FieldLayout,
+    pub packets_rejected_reserved: FieldLayout,
+    pub packets_rejected_other: FieldLayout,
+}
+
+/// telemetry_t of the openlst firmware (open-lst/radio/telemetry.h), packed little endian
+pub const OPENLST_TELEMETRY: TelemetryLayout = TelemetryLayout {
+    // u8 reserved: 0
+    uptime: FieldLayout::le(1, 4),
+    // u32 uart0 rx count: 5..9
+    // u32 uart1 rx count: 9..13
+    // u8 rx mode: 13
+    // u8 tx mode: 14
+    // i16 * 10 ADC channels: 15..35
+    last_rssi: FieldLayout::le(35, 1),
+    last_lqi: FieldLayout::le(36, 1),
+    // i8 last frequency estimate: 37
+    packets_sent: FieldLayout::le(38, 4),
+    // u32 cs_count (sending collision count): 42..46
+    packets_good: FieldLayout::le(46, 4),
+    packets_rejected_checksum: FieldLayout::le(50, 4),
+    packets_rejected_reserved: FieldLayout::le(54, 4),
+    packets_rejected_other: FieldLayout::le(58, 4),
+    // reserved + custom
+};
+
+impl TelemetryLayout {
+    /// minimum message length containing all used fields
+    pub const fn min_len(&self) -> usize {
+        self.packets_rejected_other.end()
+    }
+    #[cfg(feature = "receiver")]
+    pub fn parse(&self, msg: &[u8]) -> Option<LSTTelemetry> {
+        Some(LSTTelemetry {
+            uptime: self.uptime.read_u32(msg)?,
+            rssi: self.last_rssi.read_i8(msg)?,
+            lqi: self.last_lqi.read_u8(msg)?,
+            packets_sent: self.packets_sent.read_u32(msg)?,
+            packets_good: self.packets_good.read_u32(msg)?,
+            packets_rejected_checksum: self.packets_rejected_checksum.read_u32(msg)?,
+            packets_rejected_other: self
+                .packets_rejected_other
+                .read_u32(msg)?
+                .wrapping_add(self.packets_rejected_reserved.read_u32(msg)?),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "receiver"))]
+mod tests {
+    use super::*;
+
+    // telemetry reply payload (after the command byte) as sent by the openlst firmware
+    const CAPTURE: [u8; 78] = [
+        0x00, // reserved
+        0x10, 0x0e, 0x00, 0x00, // uptime 3600
+        0x2a, 0x00, 0x00, 0x00, // uart0 rx count
+        0x07, 0x00, 0x00, 0x00, // uart1 rx count
+        0x01, 0x02, // rx mode, tx mode
+        0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00, // adc 0..5
+        0x06, 0x00, 0x07, 0x00, 0x08, 0x00, 0x09, 0x00, 0x0a, 0x00, // adc 5..10
+        0xb5, // rssi -75
+        0x2d, // lqi 45
+        0xfe, // freq estimate
+        0xe8, 0x03, 0x00, 0x00, // packets sent 1000
+        0x03, 0x00, 0x00, 0x00, // cs count
+        0xc7, 0x03, 0x00, 0x00, // packets good 967
+        0x0c, 0x00, 0x00, 0x00, // rejected checksum 12
+        0x02, 0x00, 0x00, 0x00, // rejected reserved 2
+        0x05, 0x00, 0x00, 0x00, // rejected other 5
+        0x00, 0x00, 0x00, 0x00, // reserved0
+        0x00, 0x00, 0x00, 0x00, // reserved1
+        0x00, 0x00, 0x00, 0x00, // custom0
+        0x00, 0x00, 0x00, 0x00, // custom1
+    ];
+
+    #[test]
+    fn parses_capture() {
+        let tm = OPENLST_TELEMETRY.parse(&CAPTURE).unwrap();
+        assert_eq!(tm.uptime, 3600);
+        assert_eq!(tm.rssi, -75);
+        assert_eq!(tm.lqi, 45);
+        assert_eq!(tm.packets_sent, 1000);
+        assert_eq!(tm.packets_good, 967);
+        assert_eq!(tm.packets_rejected_checksum, 12);
+        assert_eq!(tm.packets_rejected_other, 7);
+    }
+
+    #[test]
+    fn rejects_truncated_capture() {
+        let len = OPENLST_TELEMETRY.min_len();
+        assert!(OPENLST_TELEMETRY.parse(&CAPTURE[..len]).is_some());
+        assert!(OPENLST_TELEMETRY.parse(&CAPTURE[..len - 1]).is_none());
+    }
+
+    #[test]
+    fn reads_big_endian_fields() {
+        let field = FieldLayout {
+            offset: 1,
+            width: 2,
+            endianness: Endianness::Big,
+        };
+        assert_eq!(field.read_u32(&[0xff, 0x12, 0x34]), Some(0x1234));
+        assert_eq!(
+            FieldLayout::le(1, 2).read_u32(&[0xff, 0x12, 0x34]),
+            Some(0x3412)
+        );
+    }
+}
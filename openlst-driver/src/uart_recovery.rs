@@ -0,0 +1,135 @@
+// consecutive overruns after which the receive buffer is considered stuck
+const OVERRUN_LIMIT: u8 = 3;
+// consecutive errors of any class, e.g. a line that keeps producing framing errors
+const ERROR_LIMIT: u8 = 10;
+
+/// receive error of the uart below the lst receiver, classified by the application
+/// from its hal error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UartErrorClass {
+    Framing,
+    Noise,
+    Overrun,
+    Other,
+}
+
+/// error counters of a uart since startup
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UartHealth {
+    pub framing: u16,
+    pub noise: u16,
+    pub overrun: u16,
+    pub other: u16,
+    /// reinitializations of the receive buffer
+    pub recoveries: u16,
+}
+
+impl UartHealth {
+    pub const LEN: usize = 10;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        for (out, count) in bytes.chunks_exact_mut(2).zip([
+            self.framing,
+            self.noise,
+            self.overrun,
+            self.other,
+            self.recoveries,
+        ]) {
+            out.copy_from_slice(&count.to_le_bytes());
+        }
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; Self::LEN] = bytes.try_into().ok()?;
+        let count = |i: usize| u16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]);
+        Some(Self {
+            framing: count(0),
+            noise: count(1),
+            overrun: count(2),
+            other: count(3),
+            recoveries: count(4),
+        })
+    }
+}
+
+/// Tracks uart receive errors and decides when they are sticky enough that the
+/// receive buffer has to be flushed and reinitialized
+#[derive(Debug, Default)]
+pub struct UartRecovery {
+    health: UartHealth,
+    overruns: u8,
+    errors: u8,
+}
+
+impl UartRecovery {
+    pub const fn new() -> Self {
+        Self {
+            health: UartHealth {
+                framing: 0,
+                noise: 0,
+                overrun: 0,
+                other: 0,
+                recoveries: 0,
+            },
+            overruns: 0,
+            errors: 0,
+        }
+    }
+    pub fn health(&self) -> UartHealth {
+        self.health
+    }
+    /// a frame was received, errors before it were transient
+    pub fn received(&mut self) {
+        self.overruns = 0;
+        self.errors = 0;
+    }
+    /// count an error, returns true if the receive buffer should be reinitialized.
+    /// The reinitialization is counted as recovery
+    pub fn error(&mut self, class: UartErrorClass) -> bool {
+        let count = match class {
+            UartErrorClass::Framing => &mut self.health.framing,
+            UartErrorClass::Noise => &mut self.health.noise,
+            UartErrorClass::Overrun => &mut self.health.overrun,
+            UartErrorClass::Other => &mut self.health.other,
+        };
+        *count = count.wrapping_add(1);
+        if class == UartErrorClass::Overrun {
+            self.overruns = self.overruns.saturating_add(1);
+        }
+        self.errors = self.errors.saturating_add(1);
+        if self.overruns < OVERRUN_LIMIT && self.errors < ERROR_LIMIT {
+            return false;
+        }
+        self.received();
+        self.health.recoveries = self.health.recoveries.wrapping_add(1);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persistent_overruns_trigger_recovery() {
+        let mut recovery = UartRecovery::new();
+        assert!(!recovery.error(UartErrorClass::Overrun));
+        assert!(!recovery.error(UartErrorClass::Overrun));
+        // a good frame in between resets the streak
+        recovery.received();
+        assert!(!recovery.error(UartErrorClass::Overrun));
+        assert!(!recovery.error(UartErrorClass::Overrun));
+        assert!(recovery.error(UartErrorClass::Overrun));
+        assert!(!recovery.error(UartErrorClass::Framing));
+
+        let health = recovery.health();
+        assert_eq!(health.overrun, 5);
+        assert_eq!(health.framing, 1);
+        assert_eq!(health.recoveries, 1);
+        assert_eq!(UartHealth::from_bytes(&health.to_bytes()), Some(health));
+        assert_eq!(UartHealth::from_bytes(&health.to_bytes()[1..]), None);
+    }
+}
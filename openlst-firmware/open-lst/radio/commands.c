@@ -28,6 +28,10 @@
 uint8_t custom_commands(const __xdata command_t *cmd, uint8_t len, __xdata command_t *reply);
 #endif
 
+#ifndef GIT_REV_HEX
+#define GIT_REV_HEX 0
+#endif
+
 static __xdata radio_callsign_t olst_callsign_rx;
 
 uint8_t commands_handle_command(const __xdata command_t *cmd, uint8_t len, __xdata command_t *reply) {
@@ -129,6 +133,12 @@ uint8_t commands_handle_command(const __xdata command_t *cmd, uint8_t len, __xda
 			reply_length += sizeof(*olst_callsign);
 		break;
 
+		case radio_msg_get_version:
+			reply->header.command = radio_msg_version;
+			reply_data->version.git_rev = GIT_REV_HEX;
+			reply_length += sizeof(reply_data->version);
+		break;
+
 		#if RADIO_RANGING_RESPONDER == 1
 		case radio_msg_ranging:
 			reply->header.command = radio_msg_ranging_ack;
@@ -31,7 +31,9 @@ typedef enum {
 	radio_msg_telem        = 0x18,
 	radio_msg_get_callsign = 0x19,
 	radio_msg_set_callsign = 0x1a,
-	radio_msg_callsign     = 0x1b
+	radio_msg_callsign     = 0x1b,
+	radio_msg_get_version  = 0x1c,
+	radio_msg_version      = 0x1d
 } radio_msg_no;
 
 #define RANGING_ACK_TYPE 1
@@ -46,11 +48,16 @@ typedef struct {
 	uint32_t postpone_sec;
 } reboot_postpone_t;
 
+typedef struct {
+	uint32_t git_rev;
+} radio_version_t;
+
 typedef union {
 	timespec_t time;
 	radio_ranging_ack_t ranging_ack;
 	reboot_postpone_t reboot_postpone;
 	telemetry_t telemetry;
+	radio_version_t version;
 	uint8_t data[1];
 } msg_data_t;
 
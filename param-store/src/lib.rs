@@ -0,0 +1,329 @@
+//! Double buffered parameter area in flash.
+//!
+//! Two copies are kept in separate erase sectors. An update always overwrites the
+//! older copy, so an interrupted write leaves the last good copy untouched and it
+//! is loaded again on the next boot.
+#![no_std]
+
+use embedded_storage::nor_flash::NorFlash;
+
+// "SPRM"
+const MAGIC: u32 = 0x5350_524D;
+// magic(4) seq(4) len(4) crc(2) reserved(2)
+const HEADER_LEN: usize = 16;
+// bytes written to flash at once, a multiple of the write size of all supported flashes
+const CHUNK_LEN: usize = 64;
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq)]
+pub enum ParamError<E> {
+    Flash(E),
+    TooLarge,
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    seq: u32,
+    len: usize,
+    crc: u16,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0xFF; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[8..12].copy_from_slice(&(self.len as u32).to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+    fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        (word(0) == MAGIC).then(|| Self {
+            seq: word(4),
+            len: word(8) as usize,
+            crc: u16::from_le_bytes([bytes[12], bytes[13]]),
+        })
+    }
+}
+
+/// crc16 ccitt, the same configuration as the beacon crc
+fn crc16_ccitt(mut crc: u16, bytes: &[u8]) -> u16 {
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if (crc & 0x8000) != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// crc over the sequence number, length and data
+fn record_crc(seq: u32, data: &[u8]) -> u16 {
+    let crc = crc16_ccitt(0xFFFF, &seq.to_le_bytes());
+    let crc = crc16_ccitt(crc, &(data.len() as u32).to_le_bytes());
+    crc16_ccitt(crc, data)
+}
+
+fn is_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+pub struct ParamStore<F> {
+    flash: F,
+    // offsets of the two copies, each at the start of its own erase sector
+    slots: [u32; 2],
+    slot_len: u32,
+    // slot and sequence number of the newest valid copy
+    current: Option<(usize, u32)>,
+}
+
+impl<F: NorFlash> ParamStore<F> {
+    /// slot_len is the erase sector size, the copies must not overlap
+    pub fn new(flash: F, slot_a: u32, slot_b: u32, slot_len: u32) -> Self {
+        assert!(CHUNK_LEN.is_multiple_of(F::WRITE_SIZE));
+        assert!(slot_a.abs_diff(slot_b) >= slot_len);
+        Self {
+            flash,
+            slots: [slot_a, slot_b],
+            slot_len,
+            current: None,
+        }
+    }
+    /// largest parameter set that fits into a slot
+    pub fn capacity(&self) -> usize {
+        self.slot_len as usize - HEADER_LEN
+    }
+    fn read_header(&mut self, slot: usize) -> Result<Option<Header>, ParamError<F::Error>> {
+        let mut bytes = [0; HEADER_LEN];
+        self.flash
+            .read(self.slots[slot], &mut bytes)
+            .map_err(ParamError::Flash)?;
+        Ok(Header::from_bytes(&bytes).filter(|h| h.len <= self.capacity()))
+    }
+    /// read a copy into buf if its crc is valid, TooLarge if it does not fit into buf
+    fn read_valid(
+        &mut self,
+        slot: usize,
+        buf: &mut [u8],
+    ) -> Result<Option<Header>, ParamError<F::Error>> {
+        let Some(header) = self.read_header(slot)? else {
+            return Ok(None);
+        };
+        let data = buf.get_mut(..header.len).ok_or(ParamError::TooLarge)?;
+        self.flash
+            .read(self.slots[slot] + HEADER_LEN as u32, data)
+            .map_err(ParamError::Flash)?;
+        Ok((record_crc(header.seq, data) == header.crc).then_some(header))
+    }
+    /// load the newest valid copy that fits into buf, returns its length or None if no copy
+    /// is valid. A copy larger than buf is skipped for the other one, TooLarge if neither fits
+    pub fn load(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ParamError<F::Error>> {
+        self.current = None;
+        let mut newest = None;
+        let mut too_large = false;
+        for slot in 0..2 {
+            let header = match self.read_valid(slot, buf) {
+                Ok(header) => header,
+                Err(ParamError::TooLarge) => {
+                    too_large = true;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(header) = header
+                && newest.is_none_or(|(_, seq)| is_newer(header.seq, seq))
+            {
+                newest = Some((slot, header.seq));
+            }
+        }
+        let Some((slot, seq)) = newest else {
+            return if too_large {
+                Err(ParamError::TooLarge)
+            } else {
+                Ok(None)
+            };
+        };
+        // the buffer holds the copy read last, read the newest again if it was the first
+        let len = self.read_valid(slot, buf)?.map(|h| h.len);
+        self.current = Some((slot, seq));
+        Ok(len)
+    }
+    /// write a new parameter set over the older copy, the newest copy stays valid until
+    /// the write completed. Without a loaded copy the set goes to the first slot with
+    /// sequence number 1, load first so a copy already in flash is superseded
+    pub fn store(&mut self, data: &[u8]) -> Result<(), ParamError<F::Error>> {
+        if data.len() > self.capacity() {
+            return Err(ParamError::TooLarge);
+        }
+        let (slot, seq) = match self.current {
+            Some((slot, seq)) => (1 - slot, seq.wrapping_add(1)),
+            None => (0, 1),
+        };
+        let offset = self.slots[slot];
+        self.flash
+            .erase(offset, offset + self.slot_len)
+            .map_err(ParamError::Flash)?;
+
+        let header = Header {
+            seq,
+            len: data.len(),
+            crc: record_crc(seq, data),
+        }
+        .to_bytes();
+        let record_len = HEADER_LEN + data.len();
+        let padded_len = record_len.next_multiple_of(F::WRITE_SIZE);
+        let mut chunk = [0xFF; CHUNK_LEN];
+        for start in (0..padded_len).step_by(CHUNK_LEN) {
+            let end = (start + CHUNK_LEN).min(padded_len);
+            for (i, byte) in chunk[..end - start].iter_mut().enumerate() {
+                let pos = start + i;
+                *byte = match pos {
+                    _ if pos < HEADER_LEN => header[pos],
+                    _ if pos < record_len => data[pos - HEADER_LEN],
+                    _ => 0xFF,
+                };
+            }
+            self.flash
+                .write(offset + start as u32, &chunk[..end - start])
+                .map_err(ParamError::Flash)?;
+        }
+        self.current = Some((slot, seq));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+    const SECTOR: u32 = 256;
+
+    #[derive(Debug, PartialEq)]
+    struct PowerLoss;
+
+    impl NorFlashError for PowerLoss {
+        fn kind(&self) -> NorFlashErrorKind {
+            NorFlashErrorKind::Other
+        }
+    }
+
+    // two sector flash, writes fail once the byte budget is used up
+    struct RamFlash {
+        mem: [u8; 2 * SECTOR as usize],
+        write_budget: usize,
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = PowerLoss;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), PowerLoss> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.mem[offset..offset + bytes.len()]);
+            Ok(())
+        }
+        fn capacity(&self) -> usize {
+            self.mem.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 8;
+        const ERASE_SIZE: usize = SECTOR as usize;
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), PowerLoss> {
+            self.mem[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), PowerLoss> {
+            for (i, byte) in bytes.iter().enumerate() {
+                if self.write_budget == 0 {
+                    return Err(PowerLoss);
+                }
+                self.write_budget -= 1;
+                self.mem[offset as usize + i] = *byte;
+            }
+            Ok(())
+        }
+    }
+
+    fn store() -> ParamStore<RamFlash> {
+        let flash = RamFlash {
+            mem: [0xFF; 2 * SECTOR as usize],
+            write_budget: usize::MAX,
+        };
+        ParamStore::new(flash, 0, SECTOR, SECTOR)
+    }
+
+    #[test]
+    fn loads_newest_copy() {
+        let mut params = store();
+        let mut buf = [0; 128];
+        assert_eq!(params.load(&mut buf), Ok(None));
+        params.store(b"first").unwrap();
+        params.store(b"second").unwrap();
+        params.store(&[0xAB; 100]).unwrap();
+
+        // a fresh store on the same flash finds the newest copy
+        let mut params = ParamStore::new(params.flash, 0, SECTOR, SECTOR);
+        assert_eq!(params.load(&mut buf), Ok(Some(100)));
+        assert_eq!(buf[..100], [0xAB; 100]);
+    }
+
+    #[test]
+    fn rolls_back_interrupted_write() {
+        let mut params = store();
+        let mut buf = [0; 128];
+        params.store(b"calibration v1").unwrap();
+        params.store(b"calibration v2").unwrap();
+        params.flash.write_budget = 20;
+        assert_eq!(
+            params.store(b"calibration v3"),
+            Err(ParamError::Flash(PowerLoss))
+        );
+
+        let mut params = ParamStore::new(params.flash, 0, SECTOR, SECTOR);
+        assert_eq!(params.load(&mut buf), Ok(Some(14)));
+        assert_eq!(&buf[..14], b"calibration v2");
+    }
+
+    #[test]
+    fn skips_copy_larger_than_buffer() {
+        let mut params = store();
+        params.store(b"short").unwrap();
+        params.store(&[0xAB; 100]).unwrap();
+
+        let mut params = ParamStore::new(params.flash, 0, SECTOR, SECTOR);
+        let mut buf = [0; 16];
+        assert_eq!(params.load(&mut buf), Ok(Some(5)));
+        assert_eq!(&buf[..5], b"short");
+        // the oversized copy is the older one now and is overwritten next
+        params.store(b"newer").unwrap();
+        assert_eq!(params.load(&mut buf), Ok(Some(5)));
+        assert_eq!(&buf[..5], b"newer");
+
+        let mut buf = [0; 4];
+        assert_eq!(params.load(&mut buf), Err(ParamError::TooLarge));
+    }
+
+    #[test]
+    fn first_store_starts_at_seq_one() {
+        let mut params = store();
+        params.store(b"first").unwrap();
+        assert_eq!(params.current, Some((0, 1)));
+    }
+
+    #[test]
+    fn rejects_oversized_data() {
+        let mut params = store();
+        let data = [0; SECTOR as usize];
+        assert_eq!(params.store(&data), Err(ParamError::TooLarge));
+    }
+}
@@ -1,11 +1,18 @@
+use std::{env, fs, path::PathBuf};
+
 fn main() {
-    if let Ok(v) = std::env::var("FW_VERSION") {
+    // memory.x with the flash parameter area carved out, found by link.x in OUT_DIR
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy("memory.x", out.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+
+    if let Ok(v) = env::var("FW_VERSION") {
         println!("cargo:rustc-env=FW_VERSION={}", v);
     } else {
         println!("cargo:rustc-env=FW_VERSION=v{}", env!("CARGO_PKG_VERSION"));
     }
 
-    if let Ok(h) = std::env::var("FW_HASH") {
+    if let Ok(h) = env::var("FW_HASH") {
         println!("cargo:rustc-env=FW_HASH={}", h);
     } else {
         println!("cargo:rustc-env=FW_HASH=local");
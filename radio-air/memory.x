@@ -0,0 +1,11 @@
+/* STM32H723VG, replaces the memory.x of embassy-stm32 so the firmware can not grow into
+   the flash parameter area */
+MEMORY
+{
+    /* sectors 0 to 5, the firmware */
+    FLASH  : ORIGIN = 0x08000000, LENGTH = 768K
+    /* sectors 6 and 7, the two slots of the param-store, PARAM_SLOT_A and PARAM_SLOT_B */
+    PARAMS : ORIGIN = 0x080C0000, LENGTH = 256K
+    /* axi sram, reachable by the dma, with the default tcm/axi sharing */
+    RAM    : ORIGIN = 0x24000000, LENGTH = 320K
+}
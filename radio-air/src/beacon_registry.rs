@@ -0,0 +1,170 @@
+use core::{cell::Cell, mem::MaybeUninit, ptr};
+
+use defmt::{Format, error, info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::Duration;
+use embedded_storage::nor_flash::NorFlash;
+use param_store::ParamStore;
+
+use crate::command_schedule::checksum;
+
+// beacons of the compiled-in superset, by their position in the spawn list
+pub const MAX_BEACONS: usize = 8;
+// enabled(1) interval ms(4 LE), an interval of 0 keeps the compiled-in one
+const ENTRY_LEN: usize = 5;
+// the parameter set in flash: one entry per beacon
+const PARAMS_LEN: usize = MAX_BEACONS * ENTRY_LEN;
+
+// "BREG", marks a registry written by a previous run
+const MAGIC: u32 = 0x4252_4547;
+// magic(4) checksum(4) save requested(1) entries
+const PERSISTED_LEN: usize = 9 + PARAMS_LEN;
+
+// not zeroed by the startup code, carries the active set and a save request over the
+// reset into the next boot. Erasing a flash sector stalls the cpu for longer than the
+// watchdog timeout, so the flash is only written at boot before the watchdog is unleashed
+#[unsafe(link_section = ".uninit.BEACON_REGISTRY")]
+static mut PERSISTED: MaybeUninit<[u8; PERSISTED_LEN]> = MaybeUninit::uninit();
+
+#[derive(Format, Clone, Copy, PartialEq)]
+struct Entry {
+    enabled: bool,
+    interval_ms: u32,
+}
+
+impl Entry {
+    const DEFAULT: Self = Self {
+        enabled: true,
+        interval_ms: 0,
+    };
+
+    fn to_bytes(self) -> [u8; ENTRY_LEN] {
+        let mut bytes = [0; ENTRY_LEN];
+        bytes[0] = self.enabled as u8;
+        bytes[1..].copy_from_slice(&self.interval_ms.to_le_bytes());
+        bytes
+    }
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            enabled: bytes[0] != 0,
+            interval_ms: u32::from_le_bytes(bytes[1..ENTRY_LEN].try_into().unwrap()),
+        }
+    }
+}
+
+static ENTRIES: Mutex<ThreadModeRawMutex, Cell<[Entry; MAX_BEACONS]>> =
+    Mutex::new(Cell::new([Entry::DEFAULT; MAX_BEACONS]));
+
+fn params_to_bytes(entries: &[Entry; MAX_BEACONS]) -> [u8; PARAMS_LEN] {
+    let mut bytes = [0; PARAMS_LEN];
+    for (entry, out) in entries.iter().zip(bytes.chunks_exact_mut(ENTRY_LEN)) {
+        out.copy_from_slice(&entry.to_bytes());
+    }
+    bytes
+}
+
+fn params_from_bytes(bytes: &[u8]) -> [Entry; MAX_BEACONS] {
+    let mut entries = [Entry::DEFAULT; MAX_BEACONS];
+    for (entry, bytes) in entries.iter_mut().zip(bytes.chunks_exact(ENTRY_LEN)) {
+        *entry = Entry::from_bytes(bytes);
+    }
+    entries
+}
+
+fn persist(entries: &[Entry; MAX_BEACONS], save: bool) {
+    let mut bytes = [0; PERSISTED_LEN];
+    bytes[8] = save as u8;
+    bytes[9..].copy_from_slice(&params_to_bytes(entries));
+    let sum = checksum(&bytes[8..]);
+    bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    bytes[4..8].copy_from_slice(&sum.to_le_bytes());
+    // SAFETY: the area is only accessed from the registry functions, which run in
+    // thread mode, any content is a valid byte array and checked before use
+    unsafe { ptr::write_volatile(&raw mut PERSISTED, MaybeUninit::new(bytes)) };
+}
+
+/// load the active set at boot: the set of the previous run after a soft reset,
+/// the set saved in the flash parameter area after a power cycle. Writes a
+/// requested save to the flash, call before the watchdog is unleashed
+pub fn restore<F: NorFlash>(params: &mut ParamStore<F>)
+where
+    F::Error: Format,
+{
+    let mut stored = [0; PARAMS_LEN];
+    let saved = match params.load(&mut stored) {
+        Ok(Some(PARAMS_LEN)) => Some(params_from_bytes(&stored)),
+        Ok(_) => None,
+        Err(e) => {
+            error!("could not load beacon registry: {}", e);
+            None
+        }
+    };
+
+    // SAFETY: see persist
+    let bytes = unsafe { ptr::read_volatile(&raw const PERSISTED).assume_init() };
+    let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+    let sum = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let entries = if magic == MAGIC && sum == checksum(&bytes[8..]) {
+        let entries = params_from_bytes(&bytes[9..]);
+        if bytes[8] != 0 {
+            match params.store(&params_to_bytes(&entries)) {
+                Ok(()) => info!("beacon registry saved"),
+                Err(e) => error!("could not save beacon registry: {}", e),
+            }
+        }
+        entries
+    } else {
+        saved.unwrap_or([Entry::DEFAULT; MAX_BEACONS])
+    };
+    ENTRIES.lock(|current| current.set(entries));
+    persist(&entries, false);
+    info!("beacon registry: {}", entries);
+}
+
+/// false if the beacon was disabled by command
+pub fn enabled(index: usize) -> bool {
+    ENTRIES.lock(|entries| entries.get()[index].enabled)
+}
+
+/// interval assigned by command, the compiled-in one if none is
+pub fn interval(index: usize, compiled: Duration) -> Duration {
+    match ENTRIES.lock(|entries| entries.get()[index].interval_ms) {
+        0 => compiled,
+        ms => Duration::from_millis(ms as u64),
+    }
+}
+
+/// apply the args of a beacon config command, returns false if they are invalid.
+/// Without an interval the assigned interval is kept
+pub fn apply(args: &[u8]) -> bool {
+    let (index, enabled, interval_ms) = match *args {
+        [index, enabled] => (index as usize, enabled != 0, None),
+        [index, enabled, a, b, c, d] => (
+            index as usize,
+            enabled != 0,
+            Some(u32::from_le_bytes([a, b, c, d])),
+        ),
+        _ => return false,
+    };
+    if index >= MAX_BEACONS {
+        return false;
+    }
+    ENTRIES.lock(|current| {
+        let mut entries = current.get();
+        entries[index].enabled = enabled;
+        if let Some(interval_ms) = interval_ms {
+            entries[index].interval_ms = interval_ms;
+        }
+        current.set(entries);
+        persist(&entries, false);
+    });
+    true
+}
+
+/// request saving the active set into the flash parameter area on the next boot,
+/// the caller resets the controller afterwards
+pub fn request_save() {
+    let entries = ENTRIES.lock(|entries| entries.get());
+    warn!("saving beacon registry on the next boot");
+    persist(&entries, true);
+}
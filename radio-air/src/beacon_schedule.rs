@@ -0,0 +1,339 @@
+use defmt::Format;
+use embassy_time::Duration;
+use openlst_driver::link::{DUTY_CYCLE_ID, SHAPER_ID};
+
+pub enum ScheduleMode {
+    /// send every interval after the initial phase offset
+    Free { phase: Duration },
+    /// send only at the start of this vehicles slot in a frame aligned to the synchronized utc time
+    Slotted {
+        frame: Duration,
+        slot_offset: Duration,
+    },
+}
+
+pub struct ScheduleConfig {
+    pub mode: ScheduleMode,
+    /// random delay of up to this added to every transmission
+    pub jitter: Duration,
+}
+
+/// Computes the beacon transmit times with jitter and optional utc slotting,
+/// so co-channel transmitters do not collide on aligned intervals
+pub struct BeaconScheduler {
+    config: &'static ScheduleConfig,
+    // local time (free) or utc time (slotted) of the last unjittered send time
+    last_us: Option<u64>,
+    rng: u32,
+}
+
+impl BeaconScheduler {
+    pub fn new(config: &'static ScheduleConfig, seed: u32) -> Self {
+        Self {
+            config,
+            last_us: None,
+            // xorshift must not start at zero
+            rng: seed | 1,
+        }
+    }
+
+    fn jitter_us(&mut self) -> u64 {
+        let max = self.config.jitter.as_micros();
+        if max == 0 {
+            return 0;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as u64 % max
+    }
+
+    /// local time of the first transmission
+    pub fn first_send(&mut self, local_us: u64, utc_us: u64) -> u64 {
+        match self.config.mode {
+            ScheduleMode::Free { phase } => {
+                let base = local_us + phase.as_micros();
+                self.last_us = Some(base);
+                base + self.jitter_us()
+            }
+            ScheduleMode::Slotted { .. } => self.next_slot(local_us, utc_us, utc_us),
+        }
+    }
+
+    /// local time of the next transmission after the given interval
+    pub fn next_send(&mut self, interval: Duration, local_us: u64, utc_us: u64) -> u64 {
+        let interval = interval.as_micros();
+        match self.config.mode {
+            ScheduleMode::Free { .. } => {
+                // keep the base times on a fixed grid, unless we fell behind
+                let base = self
+                    .last_us
+                    .map_or(local_us, |last| last + interval)
+                    .max(local_us);
+                self.last_us = Some(base);
+                base + self.jitter_us()
+            }
+            ScheduleMode::Slotted { .. } => {
+                let earliest = self.last_us.map_or(utc_us, |last| last + interval);
+                self.next_slot(local_us, utc_us, earliest)
+            }
+        }
+    }
+
+    fn next_slot(&mut self, local_us: u64, utc_us: u64, earliest_utc_us: u64) -> u64 {
+        let ScheduleMode::Slotted { frame, slot_offset } = self.config.mode else {
+            return local_us;
+        };
+        let frame = frame.as_micros().max(1);
+        let offset = slot_offset.as_micros() % frame;
+
+        // first slot start at or after the earliest time, never in the past
+        let earliest = earliest_utc_us.max(utc_us);
+        let frame_start = earliest - earliest % frame + offset;
+        let slot = if frame_start >= earliest {
+            frame_start
+        } else {
+            frame_start + frame
+        };
+        self.last_us = Some(slot);
+        local_us + (slot - utc_us) + self.jitter_us()
+    }
+}
+
+// duty cycle frame relayed to the ground with the lst telemetry, layout in link::DUTY_CYCLE_ID
+pub const DUTY_CYCLE_LEN: usize = 11;
+// rf framing around the relayed payload: preamble(4) sync(4) length(1) header(6) crc(2)
+const RF_OVERHEAD: usize = 17;
+// the window slides in steps of one bucket
+const BUCKETS: usize = 60;
+
+/// regulatory transmit limit of the band, e.g. 10 % per hour at 869.4 - 869.65 MHz
+pub struct DutyCycleConfig {
+    /// over the air data rate of the lst
+    pub data_rate_bps: u32,
+    pub window: Duration,
+    /// allowed airtime per window in permille
+    pub limit_permille: u32,
+}
+
+impl DutyCycleConfig {
+    fn budget_us(&self) -> u64 {
+        self.window.as_micros() * self.limit_permille as u64 / 1000
+    }
+
+    fn bucket_us(&self) -> u64 {
+        (self.window.as_micros() / BUCKETS as u64).max(1)
+    }
+
+    /// time on air of a relayed payload
+    pub fn airtime_us(&self, len: usize) -> u64 {
+        (len + RF_OVERHEAD) as u64 * 8 * 1_000_000 / self.data_rate_bps.max(1) as u64
+    }
+}
+
+/// Tracks the airtime of the beacons over a sliding window, shared by all beacon senders.
+/// Once the budget is used up only critical beacons are sent
+pub struct AirtimeBudget {
+    config: &'static DutyCycleConfig,
+    // airtime in us per bucket, indexed by the bucket number modulo BUCKETS
+    buckets: [u32; BUCKETS],
+    // bucket number of the latest update
+    current: u64,
+    // non critical beacons held back since startup
+    deferred: u16,
+}
+
+impl AirtimeBudget {
+    pub const fn new(config: &'static DutyCycleConfig) -> Self {
+        Self {
+            config,
+            buckets: [0; BUCKETS],
+            current: 0,
+            deferred: 0,
+        }
+    }
+
+    /// clear the buckets that left the window
+    fn advance(&mut self, local_us: u64) {
+        let bucket = local_us / self.config.bucket_us();
+        let stale = bucket.saturating_sub(self.current).min(BUCKETS as u64);
+        for i in 1..=stale {
+            self.buckets[((self.current + i) % BUCKETS as u64) as usize] = 0;
+        }
+        self.current = self.current.max(bucket);
+    }
+
+    fn used_us(&self) -> u64 {
+        self.buckets.iter().map(|&us| us as u64).sum()
+    }
+
+    /// Account for the transmission of a payload, returns false and counts the beacon as
+    /// deferred if it is not critical and would exceed the budget
+    pub fn transmit(&mut self, local_us: u64, len: usize, critical: bool) -> bool {
+        self.advance(local_us);
+        let airtime = self.config.airtime_us(len);
+        if !critical && self.used_us() + airtime > self.config.budget_us() {
+            self.deferred = self.deferred.saturating_add(1);
+            return false;
+        }
+        let bucket = &mut self.buckets[(self.current % BUCKETS as u64) as usize];
+        *bucket = bucket.saturating_add(airtime as u32);
+        true
+    }
+
+    /// budget usage for the housekeeping downlink
+    pub fn frame(&mut self, local_us: u64) -> [u8; DUTY_CYCLE_LEN] {
+        self.advance(local_us);
+        let used_ms = (self.used_us() / 1000) as u32;
+        let budget_ms = (self.config.budget_us() / 1000) as u32;
+        let mut frame = [0; DUTY_CYCLE_LEN];
+        frame[0] = DUTY_CYCLE_ID;
+        frame[1..5].copy_from_slice(&used_ms.to_le_bytes());
+        frame[5..9].copy_from_slice(&budget_ms.to_le_bytes());
+        frame[9..11].copy_from_slice(&self.deferred.to_le_bytes());
+        frame
+    }
+}
+
+// shaper frame relayed to the ground with the lst telemetry, layout in link::SHAPER_ID
+pub const SHAPER_LEN: usize = 1 + CLASSES * 4;
+const CLASSES: usize = 3;
+
+/// downlink share a beacon belongs to, each class has its own byte quota
+#[derive(Format, Clone, Copy, PartialEq)]
+pub enum BeaconClass {
+    /// low rate beacons needed to fly and recover the vehicle, sent even when the
+    /// duty cycle budget is used up
+    Safety = 0,
+    Housekeeping = 1,
+    Science = 2,
+}
+
+impl BeaconClass {
+    pub fn is_critical(self) -> bool {
+        self == BeaconClass::Safety
+    }
+}
+
+/// what a frame on its way to the lst is, decides the budget and quota it is charged to
+#[derive(Format, Clone, Copy, PartialEq)]
+pub enum Traffic {
+    Beacon(BeaconClass),
+    /// status frames of the vehicle and frames forwarded for other radios,
+    /// held to the housekeeping quota
+    Housekeeping,
+    /// replies to uplinked commands and dumps the ground waits for, sent even over budget
+    /// and quota but charged to both so the beacons make room
+    Response,
+}
+
+impl Traffic {
+    pub fn class(self) -> BeaconClass {
+        match self {
+            Traffic::Beacon(class) => class,
+            Traffic::Housekeeping => BeaconClass::Housekeeping,
+            Traffic::Response => BeaconClass::Safety,
+        }
+    }
+    pub fn is_critical(self) -> bool {
+        match self {
+            Traffic::Beacon(class) => class.is_critical(),
+            Traffic::Housekeeping => false,
+            Traffic::Response => true,
+        }
+    }
+    /// held back once the quota of its class is used up
+    pub fn is_shaped(self) -> bool {
+        self != Traffic::Response
+    }
+}
+
+/// token bucket of a beacon class
+pub struct ClassQuota {
+    /// sustained downlink rate in bytes per second
+    pub rate: u32,
+    /// bytes that can be sent at once after an idle period
+    pub burst: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    // tokens in bytes scaled by 1_000_000, refilled at rate per us
+    tokens: u64,
+    // bytes sent since the last report
+    sent: u32,
+    // beacons held back since startup
+    deferred: u16,
+}
+
+/// Token bucket shaper in front of the lst with a byte quota per beacon class,
+/// so a chatty beacon only uses up the quota of its own class
+pub struct TrafficShaper {
+    quotas: &'static [ClassQuota; CLASSES],
+    buckets: [Bucket; CLASSES],
+    last_refill_us: u64,
+    last_report_us: u64,
+}
+
+impl TrafficShaper {
+    pub const fn new(quotas: &'static [ClassQuota; CLASSES]) -> Self {
+        Self {
+            quotas,
+            buckets: [Bucket {
+                tokens: 0,
+                sent: 0,
+                deferred: 0,
+            }; CLASSES],
+            last_refill_us: 0,
+            last_report_us: 0,
+        }
+    }
+
+    fn refill(&mut self, local_us: u64) {
+        let elapsed = local_us.saturating_sub(self.last_refill_us);
+        self.last_refill_us = self.last_refill_us.max(local_us);
+        for (bucket, quota) in self.buckets.iter_mut().zip(self.quotas) {
+            let full = quota.burst as u64 * 1_000_000;
+            bucket.tokens = (bucket.tokens + elapsed * quota.rate as u64).min(full);
+        }
+    }
+
+    /// true if the quota of the class covers the frame, counts the beacon as deferred if not
+    pub fn allows(&mut self, local_us: u64, class: BeaconClass, len: usize) -> bool {
+        self.refill(local_us);
+        let bucket = &mut self.buckets[class as usize];
+        if bucket.tokens < len as u64 * 1_000_000 {
+            bucket.deferred = bucket.deferred.saturating_add(1);
+            return false;
+        }
+        true
+    }
+
+    /// take a sent frame from the quota of its class
+    pub fn consume(&mut self, class: BeaconClass, len: usize) {
+        let bucket = &mut self.buckets[class as usize];
+        bucket.tokens = bucket.tokens.saturating_sub(len as u64 * 1_000_000);
+        bucket.sent = bucket.sent.saturating_add(len as u32);
+    }
+
+    /// quota utilization since the last report for the housekeeping downlink
+    pub fn frame(&mut self, local_us: u64) -> [u8; SHAPER_LEN] {
+        let period_us = local_us.saturating_sub(self.last_report_us).max(1);
+        self.last_report_us = local_us;
+        let mut frame = [0; SHAPER_LEN];
+        frame[0] = SHAPER_ID;
+        for ((bucket, quota), out) in self
+            .buckets
+            .iter_mut()
+            .zip(self.quotas)
+            .zip(frame[1..].chunks_exact_mut(4))
+        {
+            let quota_bytes = (quota.rate as u64 * period_us / 1_000_000).max(1);
+            let permille = (bucket.sent as u64 * 1000 / quota_bytes).min(u16::MAX as u64) as u16;
+            bucket.sent = 0;
+            out[0..2].copy_from_slice(&permille.to_le_bytes());
+            out[2..4].copy_from_slice(&bucket.deferred.to_le_bytes());
+        }
+        frame
+    }
+}
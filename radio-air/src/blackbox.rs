@@ -0,0 +1,183 @@
+use core::{mem::MaybeUninit, ptr};
+
+use defmt::info;
+use heapless::Vec;
+use openlst_driver::{link::BLACKBOX_DUMP_ID, lst_receiver::CaptureReason};
+
+use crate::command_schedule::checksum;
+
+// number of frames kept, older records are overwritten
+pub const RECORDS: usize = 32;
+// bytes stored per frame, longer frames are truncated
+const RECORD_LEN: usize = 64;
+// minimum time between two captures, so interference can not flood the log
+const MIN_CAPTURE_INTERVAL_US: u64 = 250_000;
+// dump id, timestamp, reason and frame length
+const DUMP_HEADER_LEN: usize = 12;
+// used(1) timestamp us(8 LE) reason(1) len(2 LE) data
+const SLOT_LEN: usize = 12 + RECORD_LEN;
+
+// "BBOX", marks records written by a previous run
+const MAGIC: u32 = 0x4242_4F58;
+// magic(4) checksum(4) next(1) dropped(4 LE) and the record slots
+const PERSISTED_LEN: usize = 13 + RECORDS * SLOT_LEN;
+
+// not zeroed by the startup code, the records of a watchdog reset are kept for the readout
+#[unsafe(link_section = ".uninit.BLACKBOX")]
+static mut PERSISTED: MaybeUninit<[u8; PERSISTED_LEN]> = MaybeUninit::uninit();
+
+#[derive(Clone, Copy)]
+pub struct BlackboxRecord {
+    /// since boot of the run that captured the frame
+    pub timestamp_us: u64,
+    pub reason: CaptureReason,
+    // length of the original frame
+    pub len: u16,
+    pub data: [u8; RECORD_LEN],
+}
+
+impl BlackboxRecord {
+    /// encode the record for downlink over the lst relay
+    pub fn to_bytes(&self) -> Vec<u8, { DUMP_HEADER_LEN + RECORD_LEN }> {
+        let mut bytes = Vec::new();
+        let reason = reason_code(self.reason);
+        let stored = (self.len as usize).min(RECORD_LEN);
+        // capacity covers header and the full record
+        let _ = bytes.push(BLACKBOX_DUMP_ID);
+        let _ = bytes.extend_from_slice(&self.timestamp_us.to_le_bytes());
+        let _ = bytes.push(reason);
+        let _ = bytes.extend_from_slice(&self.len.to_le_bytes());
+        let _ = bytes.extend_from_slice(&self.data[..stored]);
+        bytes
+    }
+    fn to_slot(self, slot: &mut [u8]) {
+        slot[0] = 1;
+        slot[1..9].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        slot[9] = reason_code(self.reason);
+        slot[10..12].copy_from_slice(&self.len.to_le_bytes());
+        slot[12..].copy_from_slice(&self.data);
+    }
+    fn from_slot(slot: &[u8]) -> Option<Self> {
+        if slot[0] == 0 {
+            return None;
+        }
+        let reason = match slot[9] {
+            0 => CaptureReason::UnknownDestination,
+            1 => CaptureReason::UnknownCommand,
+            _ => CaptureReason::Malformed,
+        };
+        Some(Self {
+            timestamp_us: u64::from_le_bytes(slot[1..9].try_into().unwrap()),
+            reason,
+            len: u16::from_le_bytes([slot[10], slot[11]]),
+            data: slot[12..].try_into().unwrap(),
+        })
+    }
+}
+
+fn reason_code(reason: CaptureReason) -> u8 {
+    match reason {
+        CaptureReason::UnknownDestination => 0,
+        CaptureReason::UnknownCommand => 1,
+        CaptureReason::Malformed => 2,
+    }
+}
+
+/// Bounded log of rejected or garbled lst frames, read out with the debug probe after
+/// recovery and kept over soft resets. Only frames the lst receiver reports as captured are
+/// recorded, those are the frames it rejects as malformed or misaddressed; frames that
+/// parse are left to the telemetry
+pub struct Blackbox {
+    records: [Option<BlackboxRecord>; RECORDS],
+    next: usize,
+    last_capture_us: Option<u64>,
+    dropped: u32,
+}
+
+impl Blackbox {
+    pub const fn new() -> Self {
+        Self {
+            records: [None; RECORDS],
+            next: 0,
+            last_capture_us: None,
+            dropped: 0,
+        }
+    }
+
+    /// take over the records of the previous run, none after a power cycle.
+    /// Only one blackbox may exist, it owns the persisted area
+    pub fn restore(&mut self) {
+        // SAFETY: the area is only accessed through the single blackbox in thread mode, any
+        // content is a valid byte array and checked against the checksum before use
+        let bytes = unsafe { ptr::read_volatile(&raw const PERSISTED).assume_init() };
+        let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let sum = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if magic == MAGIC && sum == checksum(&bytes[8..]) && (bytes[8] as usize) < RECORDS {
+            self.next = bytes[8] as usize;
+            self.dropped = u32::from_le_bytes(bytes[9..13].try_into().unwrap());
+            for (record, slot) in self
+                .records
+                .iter_mut()
+                .zip(bytes[13..].chunks_exact(SLOT_LEN))
+            {
+                *record = BlackboxRecord::from_slot(slot);
+            }
+            info!("restored {} blackbox records", self.records().count());
+        }
+        self.persist();
+    }
+    fn persist(&self) {
+        let mut bytes = [0; PERSISTED_LEN];
+        bytes[8] = self.next as u8;
+        bytes[9..13].copy_from_slice(&self.dropped.to_le_bytes());
+        for (record, slot) in self
+            .records
+            .iter()
+            .zip(bytes[13..].chunks_exact_mut(SLOT_LEN))
+        {
+            if let Some(record) = record {
+                record.to_slot(slot);
+            }
+        }
+        let sum = checksum(&bytes[8..]);
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&sum.to_le_bytes());
+        // SAFETY: see restore
+        unsafe { ptr::write_volatile(&raw mut PERSISTED, MaybeUninit::new(bytes)) };
+    }
+
+    /// store a frame, returns false if it was dropped by the rate limit
+    pub fn record(&mut self, timestamp_us: u64, reason: CaptureReason, frame: &[u8]) -> bool {
+        if let Some(last) = self.last_capture_us
+            && timestamp_us.saturating_sub(last) < MIN_CAPTURE_INTERVAL_US
+        {
+            self.dropped = self.dropped.wrapping_add(1);
+            return false;
+        }
+        self.last_capture_us = Some(timestamp_us);
+
+        let stored = frame.len().min(RECORD_LEN);
+        let mut data = [0; RECORD_LEN];
+        data[..stored].copy_from_slice(&frame[..stored]);
+        self.records[self.next] = Some(BlackboxRecord {
+            timestamp_us,
+            reason,
+            len: frame.len() as u16,
+            data,
+        });
+        self.next = (self.next + 1) % RECORDS;
+        self.persist();
+        true
+    }
+
+    /// stored records from oldest to newest
+    pub fn records(&self) -> impl Iterator<Item = &BlackboxRecord> {
+        let (newer, older) = self.records.split_at(self.next);
+        older.iter().chain(newer).flatten()
+    }
+
+    /// frames rejected by the rate limit
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
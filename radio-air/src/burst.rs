@@ -0,0 +1,66 @@
+use core::cell::Cell;
+
+use defmt::{Format, info};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+use openlst_driver::link::{BURST_ID, BURST_UPLINK_TRIGGER};
+
+use crate::mission_phase::{Phase, PhaseSet};
+
+// entering these phases starts a burst, the launch and the apogee
+const TRIGGER_PHASES: PhaseSet = PhaseSet::of(&[Phase::Ascent, Phase::Descent]);
+/// burst length if the trigger does not set one
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Format, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Phase(Phase),
+    Uplink,
+}
+
+#[derive(Clone, Copy)]
+struct Burst {
+    until: Instant,
+    trigger: Trigger,
+}
+
+static CURRENT: Mutex<ThreadModeRawMutex, Cell<Option<Burst>>> = Mutex::new(Cell::new(None));
+
+/// start a burst, or extend the running one
+pub fn start(trigger: Trigger, duration: Duration) {
+    let until = Instant::now() + duration;
+    CURRENT.lock(|current| {
+        let until = current.get().map_or(until, |burst| burst.until.max(until));
+        current.set(Some(Burst { until, trigger }));
+    });
+    info!(
+        "burst capture ({}) for {} ms",
+        trigger,
+        duration.as_millis()
+    );
+}
+
+/// start a burst if entering the phase is a trigger
+pub fn on_phase(phase: Phase) {
+    if TRIGGER_PHASES.contains(phase) {
+        start(Trigger::Phase(phase), DEFAULT_DURATION);
+    }
+}
+
+/// trigger of the running burst, None outside of a burst
+pub fn active() -> Option<Trigger> {
+    CURRENT.lock(|current| {
+        current
+            .get()
+            .filter(|burst| Instant::now() < burst.until)
+            .map(|burst| burst.trigger)
+    })
+}
+
+/// marker in front of the frames sent during the burst
+pub fn marker(trigger: Trigger) -> [u8; 2] {
+    match trigger {
+        Trigger::Phase(phase) => [BURST_ID, phase as u8],
+        Trigger::Uplink => [BURST_ID, BURST_UPLINK_TRIGGER],
+    }
+}
@@ -0,0 +1,137 @@
+//! Bench test command channel on the debug uart. Injects synthetic can frames into
+//! the beacon pipeline as if received on the bus and triggers beacon sends on demand,
+//! so new telemetry definitions can be tested end to end without other hardware.
+//! One command per line:
+//!   can <id hex> <data hex>   insert the frame into the beacons
+//!   send <beacon name|all>    send the beacon now instead of at its next interval
+
+use defmt::{info, warn};
+use embassy_futures::select::select;
+use embassy_stm32::{
+    can::frame::{FdEnvelope, FdFrame},
+    usart::RingBufferedUartRx,
+};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, pubsub::PubSubChannel};
+use embassy_time::{Instant, Timer};
+use embedded_io_async::Read;
+use heapless::{String, Vec};
+use south_common::{chell::Beacon, definitions::telemetry as tm, obdh::OnTMFunc};
+
+use crate::io_threads::BeaconIngress;
+
+// longest command line, a full can fd frame in hex
+const MAX_LINE_LEN: usize = 4 + 9 + 2 * 64 + 2;
+const MAX_NAME_LEN: usize = 32;
+
+// beacon names requested by the send command, one subscriber per beacon sender
+static SEND_REQUESTS: PubSubChannel<ThreadModeRawMutex, String<MAX_NAME_LEN>, 2, 6, 1> =
+    PubSubChannel::new();
+
+fn parse_hex(hex: &str) -> Option<Vec<u8, 64>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for i in (0..hex.len()).step_by(2) {
+        let byte = u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()?;
+        bytes.push(byte).ok()?;
+    }
+    Some(bytes)
+}
+
+async fn inject(ingress: &mut BeaconIngress, id: &str, data: &str) {
+    let (Ok(id), Some(data)) = (u16::from_str_radix(id, 16), parse_hex(data)) else {
+        warn!("injection: invalid frame {} {}", id, data);
+        return;
+    };
+    let Some(def) = tm::from_id(id) else {
+        warn!("injection: no telemetry definition with id {:x}", id);
+        return;
+    };
+    let Ok(frame) = FdFrame::new_standard(id, &data) else {
+        warn!("injection: invalid can id {:x}", id);
+        return;
+    };
+    info!("injection: can frame {:x} {:x}", id, data.as_slice());
+    ingress
+        .call(
+            def,
+            &FdEnvelope {
+                ts: Instant::now(),
+                frame,
+            },
+        )
+        .await;
+}
+
+async fn handle_line(ingress: &mut BeaconIngress, line: &str) {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("can"), Some(id), Some(data)) => inject(ingress, id, data).await,
+        (Some("send"), Some(name), None) => match String::try_from(name) {
+            Ok(name) => {
+                info!("injection: send {}", name.as_str());
+                SEND_REQUESTS.immediate_publisher().publish_immediate(name);
+            }
+            Err(_) => warn!("injection: beacon name too long"),
+        },
+        (None, ..) => (),
+        _ => warn!("injection: unknown command {}", line),
+    }
+}
+
+/// read commands from the debug uart
+#[embassy_executor::task]
+pub async fn injection_task(mut uart_rx: RingBufferedUartRx<'static>, mut ingress: BeaconIngress) {
+    let mut line: Vec<u8, MAX_LINE_LEN> = Vec::new();
+    // rest of a too long line is dropped up to the line end
+    let mut discard = false;
+    let mut byte = 0;
+    loop {
+        if let Err(e) = uart_rx.read_exact(core::slice::from_mut(&mut byte)).await {
+            warn!("injection: could not read debug uart: {}", e);
+            line.clear();
+            continue;
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                if !discard {
+                    match core::str::from_utf8(&line) {
+                        Ok(command) => handle_line(&mut ingress, command).await,
+                        Err(_) => warn!("injection: invalid command"),
+                    }
+                }
+                line.clear();
+                discard = false;
+            }
+            _ if discard => (),
+            _ => {
+                if line.push(byte).is_err() {
+                    warn!("injection: command too long");
+                    line.clear();
+                    discard = true;
+                }
+            }
+        }
+    }
+}
+
+/// wait for the next scheduled send of the beacon or a send command for it
+pub async fn wait_send(
+    at: Instant,
+    beacon: &Mutex<ThreadModeRawMutex, dyn Beacon<Timestamp = u64>>,
+) {
+    let Ok(mut requests) = SEND_REQUESTS.subscriber() else {
+        Timer::at(at).await;
+        return;
+    };
+    let requested = async {
+        loop {
+            let name = requests.next_message_pure().await;
+            if name == "all" || beacon.lock().await.name() == name.as_str() {
+                break;
+            }
+        }
+    };
+    select(Timer::at(at), requested).await;
+}
@@ -0,0 +1,119 @@
+use embassy_time::Duration;
+use embedded_can::Id;
+use openlst_driver::link::{CAN_STATS_BUSIEST, CAN_STATS_ID};
+
+// distinct can ids tracked, further ids are only counted in the total
+const TRACKED_IDS: usize = 48;
+// frames older than this when processed indicate the rx buffer is filling up
+pub const BACKLOG_LATENCY: Duration = Duration::from_millis(50);
+// can stats frame relayed to the ground with the lst telemetry, layout in link::CAN_STATS_ID
+pub const CAN_STATS_LEN: usize = 11 + CAN_STATS_BUSIEST * 6;
+
+#[derive(Clone, Copy, defmt::Format)]
+pub struct IdRate {
+    pub id: u32,
+    pub frames: u32,
+}
+
+/// Per can id receive counters of the current report window
+pub struct CanRxStats {
+    ids: [Option<IdRate>; TRACKED_IDS],
+    total: u32,
+    untracked: u32,
+    backlogged: u32,
+    max_latency_us: u64,
+}
+
+impl CanRxStats {
+    pub const fn new() -> Self {
+        Self {
+            ids: [None; TRACKED_IDS],
+            total: 0,
+            untracked: 0,
+            backlogged: 0,
+            max_latency_us: 0,
+        }
+    }
+
+    /// count a received frame and the time it waited in the rx buffer
+    pub fn count(&mut self, id: &Id, latency: Duration) {
+        let id = match id {
+            Id::Standard(id) => id.as_raw() as u32,
+            Id::Extended(id) => id.as_raw(),
+        };
+        self.total = self.total.wrapping_add(1);
+        if latency > BACKLOG_LATENCY {
+            self.backlogged = self.backlogged.wrapping_add(1);
+        }
+        self.max_latency_us = self.max_latency_us.max(latency.as_micros());
+
+        let slot = self
+            .ids
+            .iter_mut()
+            .find(|slot| slot.is_none_or(|rate| rate.id == id));
+        match slot {
+            Some(Some(rate)) => rate.frames = rate.frames.wrapping_add(1),
+            Some(slot) => *slot = Some(IdRate { id, frames: 1 }),
+            None => self.untracked = self.untracked.wrapping_add(1),
+        }
+    }
+
+    /// the N ids with the most frames in the current window
+    pub fn busiest<const N: usize>(&self) -> [Option<IdRate>; N] {
+        let mut busiest = [None; N];
+        for rate in self.ids.iter().flatten() {
+            // insertion into the sorted top list
+            let Some(pos) = busiest
+                .iter()
+                .position(|b: &Option<IdRate>| b.is_none_or(|b| rate.frames > b.frames))
+            else {
+                continue;
+            };
+            busiest.copy_within(pos..N - 1, pos + 1);
+            busiest[pos] = Some(*rate);
+        }
+        busiest
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+    pub fn untracked(&self) -> u32 {
+        self.untracked
+    }
+    pub fn backlogged(&self) -> u32 {
+        self.backlogged
+    }
+    pub fn max_latency_us(&self) -> u64 {
+        self.max_latency_us
+    }
+
+    /// receive rates of the window for the housekeeping downlink
+    pub fn frame(&self, window: Duration) -> [u8; CAN_STATS_LEN] {
+        let secs = window.as_secs().max(1) as u32;
+        let per_s = |frames: u32| ((frames / secs).min(u16::MAX as u32) as u16).to_le_bytes();
+        let saturate = |count: u32| (count.min(u16::MAX as u32) as u16).to_le_bytes();
+        let mut frame = [0; CAN_STATS_LEN];
+        frame[0] = CAN_STATS_ID;
+        frame[1..3].copy_from_slice(&per_s(self.total));
+        frame[3..5].copy_from_slice(&saturate(self.untracked));
+        frame[5..7].copy_from_slice(&saturate(self.backlogged));
+        let max_latency_us = self.max_latency_us.min(u32::MAX as u64) as u32;
+        frame[7..11].copy_from_slice(&max_latency_us.to_le_bytes());
+        for (rate, out) in self
+            .busiest::<CAN_STATS_BUSIEST>()
+            .iter()
+            .flatten()
+            .zip(frame[11..].chunks_exact_mut(6))
+        {
+            out[0..4].copy_from_slice(&rate.id.to_le_bytes());
+            out[4..6].copy_from_slice(&per_s(rate.frames));
+        }
+        frame
+    }
+
+    /// start a new report window
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
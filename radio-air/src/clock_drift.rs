@@ -0,0 +1,51 @@
+// offset steps below this are treated as tick jitter (two ticks at 32.768 kHz)
+const JITTER_THRESHOLD_US: i64 = 62;
+// larger drift estimates are time jumps (e.g. the first sync), not oscillator drift
+const MAX_DRIFT_PPB: i64 = 500_000;
+// weight of a new measurement in the smoothed drift estimate (1/N)
+const SMOOTHING: i64 = 4;
+
+/// Estimates the systematic drift of the local oscillator against the synchronized
+/// utc time and extrapolates the correction between time references
+pub struct DriftCorrector {
+    // local time and utc offset when the last time reference was applied
+    last_sync: Option<(u64, i64)>,
+    drift_ppb: i64,
+}
+
+impl DriftCorrector {
+    pub const fn new() -> Self {
+        Self {
+            last_sync: None,
+            drift_ppb: 0,
+        }
+    }
+
+    /// feed the current local and synchronized utc time, returns the drift corrected utc time
+    pub fn correct(&mut self, local_us: u64, utc_us: u64) -> u64 {
+        let offset = utc_us as i64 - local_us as i64;
+        let Some((last_local, last_offset)) = self.last_sync else {
+            self.last_sync = Some((local_us, offset));
+            return utc_us;
+        };
+
+        let step = offset - last_offset;
+        if step.abs() > JITTER_THRESHOLD_US {
+            // a new time reference was applied, the step is the drift accumulated since the last one
+            let elapsed = local_us.saturating_sub(last_local) as i64;
+            if elapsed > 0 {
+                let measured = step.saturating_mul(1_000_000_000) / elapsed;
+                if measured.abs() <= MAX_DRIFT_PPB {
+                    self.drift_ppb += (measured - self.drift_ppb) / SMOOTHING;
+                }
+            }
+            self.last_sync = Some((local_us, offset));
+            return utc_us;
+        }
+
+        // extrapolate from the last reference, smaller offset steps are jitter or already modeled drift
+        let since_sync = local_us.saturating_sub(last_local) as i64;
+        let correction = self.drift_ppb * since_sync / 1_000_000_000;
+        local_us.saturating_add_signed(last_offset + correction)
+    }
+}
@@ -0,0 +1,183 @@
+use core::{mem::MaybeUninit, ptr};
+
+use defmt::Format;
+use heapless::Vec;
+use openlst_driver::link::{SCHEDULE_DELAY, SCHEDULE_LIST_ID, SCHEDULE_UTC};
+
+use crate::uplink::UplinkCommand;
+
+// number of pending time-tagged commands
+pub const MAX_SCHEDULED: usize = 8;
+// source hwid(2 LE) seq(2 LE) op(1) due utc ms(8 LE)
+const ENTRY_LEN: usize = 13;
+// schedule args: kind(1) time ms(8 LE) op(1)
+const SCHEDULE_ARGS_LEN: usize = 10;
+
+// "SCHD", marks a schedule written by a previous run
+const MAGIC: u32 = 0x5343_4844;
+// magic(4) checksum(4) and one used flag per entry
+const PERSISTED_LEN: usize = 8 + MAX_SCHEDULED * (1 + ENTRY_LEN);
+
+// not zeroed by the startup code, survives soft resets but not a power cycle
+#[unsafe(link_section = ".uninit.COMMAND_SCHEDULE")]
+static mut PERSISTED: MaybeUninit<[u8; PERSISTED_LEN]> = MaybeUninit::uninit();
+
+#[derive(Format, Clone, Copy, PartialEq)]
+pub struct ScheduledCommand {
+    pub source_hwid: u16,
+    /// sequence number of the uplink command that scheduled it
+    pub seq: u16,
+    pub op: u8,
+    pub due_utc_ms: u64,
+}
+
+impl ScheduledCommand {
+    /// parse the args of a schedule command, the time is either a delay from now or a utc time.
+    /// None if the args are malformed or the delay overflows the utc time
+    pub fn parse(command: &UplinkCommand, now_utc_ms: u64) -> Option<Self> {
+        let args = command.args();
+        if args.len() != SCHEDULE_ARGS_LEN {
+            return None;
+        }
+        let time = u64::from_le_bytes(args[1..9].try_into().unwrap());
+        let due_utc_ms = match args[0] {
+            SCHEDULE_DELAY => now_utc_ms.checked_add(time)?,
+            SCHEDULE_UTC => time,
+            _ => return None,
+        };
+        Some(Self {
+            source_hwid: command.source_hwid,
+            seq: command.seq,
+            op: args[9],
+            due_utc_ms,
+        })
+    }
+    /// the command to execute once due, acked with the sequence number of the scheduling command
+    pub fn command(&self) -> UplinkCommand {
+        UplinkCommand::new(self.source_hwid, self.seq, self.op)
+    }
+    fn to_bytes(self) -> [u8; ENTRY_LEN] {
+        let mut bytes = [0; ENTRY_LEN];
+        bytes[0..2].copy_from_slice(&self.source_hwid.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[4] = self.op;
+        bytes[5..13].copy_from_slice(&self.due_utc_ms.to_le_bytes());
+        bytes
+    }
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            source_hwid: u16::from_le_bytes([bytes[0], bytes[1]]),
+            seq: u16::from_le_bytes([bytes[2], bytes[3]]),
+            op: bytes[4],
+            due_utc_ms: u64::from_le_bytes(bytes[5..13].try_into().unwrap()),
+        }
+    }
+}
+
+/// fnv-1a, detects a persisted area left uninitialized by a power cycle
+pub fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Bounded queue of time-tagged uplink commands, mirrored into ram that is kept
+/// over soft resets so a pending flight sequence survives a watchdog reset
+pub struct CommandSchedule {
+    entries: [Option<ScheduledCommand>; MAX_SCHEDULED],
+}
+
+impl CommandSchedule {
+    /// the schedule of the previous run, empty after a power cycle.
+    /// Only one schedule may exist, it owns the persisted area
+    pub fn restore() -> Self {
+        // SAFETY: the area is only accessed through the single schedule, any content is
+        // a valid byte array and checked against the checksum before use
+        let bytes = unsafe { ptr::read_volatile(&raw const PERSISTED).assume_init() };
+        let mut schedule = Self {
+            entries: [None; MAX_SCHEDULED],
+        };
+        let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let sum = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if magic == MAGIC && sum == checksum(&bytes[8..]) {
+            for (entry, bytes) in schedule
+                .entries
+                .iter_mut()
+                .zip(bytes[8..].chunks_exact(1 + ENTRY_LEN))
+            {
+                *entry = (bytes[0] != 0).then(|| ScheduledCommand::from_bytes(&bytes[1..]));
+            }
+        }
+        schedule.persist();
+        schedule
+    }
+    fn persist(&self) {
+        let mut bytes = [0; PERSISTED_LEN];
+        for (entry, out) in self
+            .entries
+            .iter()
+            .zip(bytes[8..].chunks_exact_mut(1 + ENTRY_LEN))
+        {
+            if let Some(entry) = entry {
+                out[0] = 1;
+                out[1..].copy_from_slice(&entry.to_bytes());
+            }
+        }
+        let sum = checksum(&bytes[8..]);
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&sum.to_le_bytes());
+        // SAFETY: see restore
+        unsafe { ptr::write_volatile(&raw mut PERSISTED, MaybeUninit::new(bytes)) };
+    }
+    fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+    /// queue a command, returns false if the schedule is full
+    pub fn add(&mut self, command: ScheduledCommand) -> bool {
+        let Some(free) = self.entries.iter_mut().find(|e| e.is_none()) else {
+            return false;
+        };
+        *free = Some(command);
+        self.persist();
+        true
+    }
+    /// remove the command scheduled by the given uplink command, returns false if there is none
+    pub fn cancel(&mut self, source_hwid: u16, seq: u16) -> bool {
+        let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|e| e.is_some_and(|e| e.source_hwid == source_hwid && e.seq == seq))
+        else {
+            return false;
+        };
+        *entry = None;
+        self.persist();
+        true
+    }
+    /// utc time of the next due command
+    pub fn next_due(&self) -> Option<u64> {
+        self.entries.iter().flatten().map(|e| e.due_utc_ms).min()
+    }
+    /// remove and return the earliest command that is due
+    pub fn take_due(&mut self, now_utc_ms: u64) -> Option<ScheduledCommand> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .filter(|e| e.is_some_and(|e| e.due_utc_ms <= now_utc_ms))
+            .min_by_key(|e| e.map(|e| e.due_utc_ms))?;
+        let command = entry.take();
+        self.persist();
+        command
+    }
+    /// listing of all pending commands for the downlink
+    pub fn list_frame(&self) -> Vec<u8, { 2 + MAX_SCHEDULED * ENTRY_LEN }> {
+        let mut frame = Vec::new();
+        // capacity covers all entries
+        let _ = frame.push(SCHEDULE_LIST_ID);
+        let _ = frame.push(self.len() as u8);
+        for entry in self.entries.iter().flatten() {
+            let _ = frame.extend_from_slice(&entry.to_bytes());
+        }
+        frame
+    }
+}
@@ -0,0 +1,112 @@
+use defmt::{Format, debug};
+use embassy_stm32::{
+    mode::Async,
+    usart::{self, UartTx},
+};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::Instant;
+use openlst_driver::{
+    lst_sender::{LSTSender, SenderError},
+    relay_route::{ROUTE_HEADER_LEN, Route},
+};
+
+use crate::beacon_schedule::{AirtimeBudget, Traffic, TrafficShaper};
+
+type Lst = Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>;
+
+#[derive(Format)]
+pub enum DownlinkError {
+    /// duty cycle budget or class quota used up, the frame was not sent
+    Deferred,
+    Lst(SenderError<usart::Error>),
+}
+
+/// The one way frames go on air. Every relayed frame is charged to the duty cycle
+/// budget and the quota of its class before it is handed to the lst
+pub struct Downlink {
+    lst: &'static Lst,
+    airtime: &'static Mutex<ThreadModeRawMutex, AirtimeBudget>,
+    shaper: &'static Mutex<ThreadModeRawMutex, TrafficShaper>,
+}
+
+impl Downlink {
+    pub const fn new(
+        lst: &'static Lst,
+        airtime: &'static Mutex<ThreadModeRawMutex, AirtimeBudget>,
+        shaper: &'static Mutex<ThreadModeRawMutex, TrafficShaper>,
+    ) -> Self {
+        Self {
+            lst,
+            airtime,
+            shaper,
+        }
+    }
+
+    /// the lst for telecommands to the local radio, they do not go on air
+    pub fn lst(&self) -> &'static Lst {
+        self.lst
+    }
+
+    /// charge a frame of len bytes, false if it has to be held back
+    async fn charge(&self, traffic: Traffic, len: usize) -> bool {
+        let local_us = Instant::now().as_micros();
+        let class = traffic.class();
+        let mut shaper = self.shaper.lock().await;
+        if traffic.is_shaped() && !shaper.allows(local_us, class, len) {
+            debug!("{} over quota", traffic);
+            return false;
+        }
+        if !self
+            .airtime
+            .lock()
+            .await
+            .transmit(local_us, len, traffic.is_critical())
+        {
+            debug!("{} over the duty cycle budget", traffic);
+            return false;
+        }
+        shaper.consume(class, len);
+        true
+    }
+
+    pub async fn send(&self, traffic: Traffic, frame: &[u8]) -> Result<(), DownlinkError> {
+        if !self.charge(traffic, frame.len()).await {
+            return Err(DownlinkError::Deferred);
+        }
+        self.lst
+            .lock()
+            .await
+            .relay(frame)
+            .await
+            .map_err(DownlinkError::Lst)
+    }
+
+    /// send a frame built only once the lst is free, for frames stamped with the local time
+    pub async fn send_stamped<const N: usize>(
+        &self,
+        traffic: Traffic,
+        stamp: impl FnOnce() -> [u8; N],
+    ) -> Result<(), DownlinkError> {
+        if !self.charge(traffic, N).await {
+            return Err(DownlinkError::Deferred);
+        }
+        let mut lst = self.lst.lock().await;
+        lst.relay(&stamp()).await.map_err(DownlinkError::Lst)
+    }
+
+    /// retransmit a routed frame of another radio towards its destination
+    pub async fn forward(&self, route: &Route, frame: &[u8]) -> Result<(), DownlinkError> {
+        if !self
+            .charge(Traffic::Housekeeping, ROUTE_HEADER_LEN + frame.len())
+            .await
+        {
+            return Err(DownlinkError::Deferred);
+        }
+        self.lst
+            .lock()
+            .await
+            .relay_routed(route, frame)
+            .await
+            .map_err(DownlinkError::Lst)
+    }
+}
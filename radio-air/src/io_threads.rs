@@ -1,70 +1,140 @@
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_futures::select::{Either3, select3};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, signal::Signal};
 
 use defmt::*;
-use embassy_stm32::{
-    can::frame::FdEnvelope,
-    crc::Crc,
-    mode::Async,
-    usart::{RingBufferedUartRx, UartTx},
-};
-use embassy_time::{Duration, Ticker, with_timeout};
+use embassy_stm32::{can::frame::FdEnvelope, crc::Crc, mode::Async, uid, usart::UartTx};
+use embassy_time::{Delay, Duration, Instant, Ticker, Timer, with_timeout};
 use openlst_driver::{
-    lst_receiver::{LSTMessage, LSTReceiver, LSTTelemetry},
+    link,
+    lst_control::{read_version, reboot_radio},
+    lst_receiver::{LSTMessage, LSTTelemetry},
     lst_sender::{LSTCmd, LSTSender},
+    relay_route::{RelayRouter, Route, Routing},
 };
 use south_common::{
-    beacons::LSTBeacon,
+    beacons::{HighRateUpperSensorBeacon, LSTBeacon, LowRateUpperSensorBeacon},
     chell::{Beacon, BeaconOperationError, ChellDefinition},
     definitions::telemetry::lst as tm,
     obdh::OnTMFunc,
     types::LSTCommand,
 };
 
+#[cfg(feature = "can-injection")]
+use crate::can_injection;
 use crate::{
-    LstCanReceiver, LstCanSender, LstComChannels, LstTCReceiver, LstChellUnion, LstTMSender,
+    LstCanReceiver, LstCanSender, LstChellUnion, LstComChannels, LstTMSender, beacon_registry,
+    beacon_schedule::{
+        AirtimeBudget, BeaconClass, BeaconScheduler, ScheduleConfig, Traffic, TrafficShaper,
+    },
+    blackbox::{self, Blackbox, BlackboxRecord},
+    burst::{self, Trigger},
+    can_stats::CanRxStats,
+    clock_drift::DriftCorrector,
+    command_schedule::{CommandSchedule, ScheduledCommand},
+    downlink::{Downlink, DownlinkError},
+    lst_inbox::LstInbox,
+    lst_uart, met,
+    mission_phase::{self, Phase, PhaseDetector, PhaseSensors, PhaseSet, Reading, Sample},
+    payload::{self, Payload},
+    profiling::{self, profiled},
+    telemetry_filter,
+    terminal_phase::{self, DescentDetector},
+    time_correlation,
+    uplink::{self, AckStatus, CommandDedup, UplinkCommand},
 };
 
+#[derive(Clone)]
 pub struct BeaconIngress {
     beacons: &'static [&'static Mutex<ThreadModeRawMutex, dyn Beacon<Timestamp = u64>>],
+    stats: &'static Mutex<ThreadModeRawMutex, CanRxStats>,
+    phase_sensors: &'static PhaseSensors,
 }
 impl BeaconIngress {
     pub fn new(
         beacons: &'static [&'static Mutex<ThreadModeRawMutex, dyn Beacon<Timestamp = u64>>],
+        stats: &'static Mutex<ThreadModeRawMutex, CanRxStats>,
+        phase_sensors: &'static PhaseSensors,
     ) -> Self {
-        Self { beacons }
+        Self {
+            beacons,
+            stats,
+            phase_sensors,
+        }
     }
 }
 impl OnTMFunc for BeaconIngress {
     async fn call(&mut self, def: &dyn ChellDefinition, envelope: &FdEnvelope) {
-        for beacon in self.beacons {
-            if let Err(e) = beacon.lock().await.insert_slice(def, envelope.frame.data()) {
-                match e {
-                    BeaconOperationError::DefNotInBeacon => (),
-                    BeaconOperationError::OutOfMemory => {
-                        error!("received incomplete value: {}", def.address());
-                    }
+        let latency = Instant::now().saturating_duration_since(envelope.ts);
+        let id = envelope.frame.header().id();
+        self.stats.lock().await.count(id, latency);
+        let mut inserted = false;
+        for (i, beacon) in self.beacons.iter().enumerate() {
+            if !telemetry_filter::allows(i, id) {
+                continue;
+            }
+            match beacon.lock().await.insert_slice(def, envelope.frame.data()) {
+                Ok(()) => inserted = true,
+                Err(BeaconOperationError::DefNotInBeacon) => (),
+                Err(BeaconOperationError::OutOfMemory) => {
+                    error!("received incomplete value: {}", def.address());
                 }
             }
         }
+        if inserted {
+            self.phase_sensors.feed(def, envelope.ts.as_micros());
+        }
     }
 }
 
-/// send a beacon to the rocketlst with a specific intervall
+/// send a beacon to the rocketlst with a specific intervall,
+/// switching to the terminal intervall once the terminal phase is entered.
+/// Beacons with a burst intervall are sent at it during a burst, in all phases and marked.
+/// The intervall is stretched to the rate allocated to the payload of the beacon
+/// and to the rate of the mission phase, outside of its phases the beacon is not sent.
+/// Beacons over the quota of their class are deferred to their next interval, as are non
+/// critical beacons while the duty cycle budget is used up.
+/// The beacon registry entry at index disables the beacon or overrides its intervall
 #[embassy_executor::task(pool_size = 6)]
 pub async fn lst_sender_thread(
     send_intervall: Duration,
+    terminal_intervall: Duration,
+    burst_intervall: Option<Duration>,
     com_channels: &'static LstComChannels,
     beacon: &'static Mutex<ThreadModeRawMutex, dyn Beacon<Timestamp = u64>>,
+    payload: &'static Payload,
+    phases: PhaseSet,
     crc: &'static Mutex<ThreadModeRawMutex, Crc<'static>>,
-    lst: &'static Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>,
+    downlink: &'static Downlink,
+    drift: &'static Mutex<ThreadModeRawMutex, DriftCorrector>,
+    schedule: &'static ScheduleConfig,
+    class: BeaconClass,
+    index: usize,
 ) {
-    let mut ticker = Ticker::every(send_intervall);
+    // seed the jitter from the device id, so identical vehicles do not jitter in lockstep
+    let seed = uid::uid()
+        .chunks(4)
+        .fold(send_intervall.as_ticks() as u32, |seed, word| {
+            seed ^ u32::from_le_bytes(word.try_into().unwrap())
+        });
+    let mut scheduler = BeaconScheduler::new(schedule, seed);
+    let first = scheduler.first_send(Instant::now().as_micros(), com_channels.get_utc_us());
+    Timer::at(Instant::from_micros(first)).await;
     loop {
-        {
+        let bursting = burst_intervall.and(burst::active());
+        profiled(&profiling::BEACON_TX, async {
             let mut beacon = beacon.lock().await;
-            let timestamp = com_channels.get_utc_us();
+            let local_us = Instant::now().as_micros();
+            let timestamp = drift
+                .lock()
+                .await
+                .correct(local_us, com_channels.get_utc_us());
             beacon.set_timestamp(timestamp);
 
+            let in_phase = bursting.is_some() || phases.contains(mission_phase::current());
+            if !payload.is_enabled() || !beacon_registry::enabled(index) || !in_phase {
+                beacon.flush();
+                return;
+            }
             debug!("sending beacon: {}", beacon.name());
 
             let bytes = {
@@ -77,28 +147,505 @@ pub async fn lst_sender_thread(
                 beacon.to_bytes(&mut crc_func)
             };
 
-            if let Err(e) = lst.lock().await.relay(bytes).await {
-                error!("could not send via lsp: {}", e);
+            let mut frame: heapless::Vec<u8, 255> = heapless::Vec::new();
+            if let Some(marker) = met::marker(timestamp) {
+                let _ = frame.extend_from_slice(&marker);
+            }
+            if let Some(trigger) = bursting {
+                let _ = frame.extend_from_slice(&burst::marker(trigger));
+            }
+            if let Some(header) = payload.header() {
+                // always fits into the empty frame
+                let _ = frame.extend_from_slice(&header);
+            }
+            if frame.extend_from_slice(bytes).is_err() {
+                error!("beacon {} too long for the payload header", beacon.name());
+            } else {
+                match downlink.send(Traffic::Beacon(class), &frame).await {
+                    Ok(()) => {}
+                    // quota or duty cycle budget used up, keep the values for the next interval
+                    Err(DownlinkError::Deferred) => {
+                        debug!("deferring beacon: {}", beacon.name());
+                        return;
+                    }
+                    Err(e) => error!("could not send via lsp: {}", e),
+                }
             }
             beacon.flush();
+        })
+        .await;
+        let nominal = if terminal_phase::is_active() {
+            terminal_intervall
+        } else {
+            beacon_registry::interval(index, send_intervall)
+        };
+        let interval = match burst_intervall.filter(|_| burst::active().is_some()) {
+            Some(burst_intervall) => burst_intervall,
+            None => mission_phase::current().interval(payload.interval(nominal)),
+        };
+        let next = scheduler.next_send(
+            interval,
+            Instant::now().as_micros(),
+            com_channels.get_utc_us(),
+        );
+        #[cfg(feature = "can-injection")]
+        can_injection::wait_send(Instant::from_micros(next), beacon).await;
+        #[cfg(not(feature = "can-injection"))]
+        Timer::at(Instant::from_micros(next)).await;
+    }
+}
+
+/// relay the current mission phase to the ground
+async fn downlink_phase(downlink: &Downlink) {
+    let frame = mission_phase::current().frame();
+    if let Err(e) = downlink.send(Traffic::Housekeeping, &frame).await {
+        error!("could not downlink mission phase: {}", e);
+    }
+}
+
+/// relay the flight state of the terminal phase to the ground
+async fn downlink_state(downlink: &Downlink, traffic: Traffic, altitude: f64, ecef: [f32; 3]) {
+    let frame = terminal_phase::state_frame(mission_phase::current(), altitude, ecef);
+    if let Err(e) = downlink.send(traffic, &frame).await {
+        error!("could not downlink flight state: {}", e);
+    }
+}
+
+/// run the phase detector on the barometer and accelerometer values of the upper sensor
+/// beacon, each time the ingress inserted a new one
+#[embassy_executor::task]
+pub async fn mission_phase_task(
+    sensor_beacon: &'static Mutex<ThreadModeRawMutex, HighRateUpperSensorBeacon>,
+    downlink: &'static Downlink,
+) {
+    let mut detector = PhaseDetector::new();
+    loop {
+        let (reading, time_us) = mission_phase::next_reading().await;
+        let sample = {
+            let beacon = sensor_beacon.lock().await;
+            match reading {
+                Reading::Pressure => beacon.pressure.map(|pa| Sample::Pressure {
+                    time_us,
+                    pa: pa as f32,
+                }),
+                Reading::Accel => beacon
+                    .acceleration
+                    .map(|a| Sample::accel(a.x as f32, a.y as f32, a.z as f32)),
+            }
+        };
+        // flushed by a beacon send since the value was inserted
+        let Some(sample) = sample else {
+            continue;
+        };
+        if let Some(phase) = detector.update(sample) {
+            info!("entering mission phase {} on {}", phase, sample);
+            burst::on_phase(phase);
+            downlink_phase(downlink).await;
         }
+    }
+}
+
+/// watch the gps altitude for the descent into the terminal phase. Once the terminal phase is entered the flight state and the most recent
+/// blackbox window are downlinked, then the flight state at the state interval
+#[embassy_executor::task]
+pub async fn terminal_phase_task(
+    gps_beacon: &'static Mutex<ThreadModeRawMutex, LowRateUpperSensorBeacon>,
+    downlink: &'static Downlink,
+    blackbox: &'static Mutex<ThreadModeRawMutex, Blackbox>,
+) {
+    // faster than the beacon interval, the position is flushed after each send
+    const GPS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+    // flight state repetition in the terminal phase, a safety beacon within its quota
+    const STATE_INTERVAL: Duration = Duration::from_secs(1);
+    let mut last_state: Option<Instant> = None;
+    let mut detector = DescentDetector::new();
+    let mut ticker = Ticker::every(GPS_POLL_INTERVAL);
+    loop {
         ticker.next().await;
+        let Some(pos) = gps_beacon.lock().await.gps_pos else {
+            continue;
+        };
+        let altitude = terminal_phase::ecef_altitude(pos.x as f64, pos.y as f64, pos.z as f64);
+        let ecef = [pos.x as f32, pos.y as f32, pos.z as f32];
+        // terminal phase is final, no further detection needed
+        if terminal_phase::is_active() {
+            if last_state.is_none_or(|at| at.elapsed() >= STATE_INTERVAL) {
+                last_state = Some(Instant::now());
+                let traffic = Traffic::Beacon(BeaconClass::Safety);
+                downlink_state(downlink, traffic, altitude, ecef).await;
+            }
+            continue;
+        }
+        if !detector.update(altitude) {
+            continue;
+        }
+
+        warn!("entering terminal phase at {} m", altitude);
+        last_state = Some(Instant::now());
+        downlink_state(downlink, Traffic::Response, altitude, ecef).await;
+        // copied out, the link task keeps recording while the window is sent
+        let records: heapless::Vec<BlackboxRecord, { blackbox::RECORDS }> =
+            blackbox.lock().await.records().copied().collect();
+        for record in &records {
+            if let Err(e) = downlink.send(Traffic::Response, &record.to_bytes()).await {
+                error!("could not downlink blackbox record: {}", e);
+            }
+        }
+    }
+}
+
+async fn reboot_lst(
+    lst: &Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>,
+    lst_inbox: &mut LstInbox,
+) {
+    const LST_BOOT_TIMEOUT_MS: u32 = 5000;
+    let mut lst = lst.lock().await;
+    match reboot_radio(&mut lst, lst_inbox, &mut Delay, LST_BOOT_TIMEOUT_MS).await {
+        Ok(boot) => info!("lst rebooted: {}", boot),
+        Err(e) => error!("could not reboot: {}", e),
+    }
+}
+
+/// read the version of the flight lst and relay it to the ground, the ack follows it
+async fn relay_version(downlink: &Downlink, lst_inbox: &mut LstInbox, command: &UplinkCommand) {
+    const LST_VERSION_TIMEOUT_MS: u32 = 1000;
+    let version = {
+        let mut lst = downlink.lst().lock().await;
+        read_version(&mut lst, lst_inbox, &mut Delay, LST_VERSION_TIMEOUT_MS).await
+    };
+    let status = match version {
+        Ok(version) => {
+            let frame = uplink::version_frame(command.seq, &version);
+            match downlink.send(Traffic::Response, &frame).await {
+                Ok(()) => AckStatus::Executed,
+                Err(e) => {
+                    error!("could not downlink lst version: {}", e);
+                    AckStatus::Failed
+                }
+            }
+        }
+        Err(e) => {
+            error!("lst did not report its version: {}", e);
+            AckStatus::Failed
+        }
+    };
+    ack_uplink(downlink, command, status).await;
+}
+
+/// relay an acknowledgement of an uplinked command back to the ground
+async fn ack_uplink(downlink: &Downlink, command: &UplinkCommand, status: AckStatus) {
+    if let Err(e) = downlink.send(Traffic::Response, &command.ack(status)).await {
+        error!("could not ack uplink command {}: {}", command.seq, e);
     }
 }
 
-async fn wait_for_telem(lst_recv: &mut LSTReceiver<RingBufferedUartRx<'static>>) -> LSTTelemetry {
+/// relay the valid and the corrupted crc test beacon
+async fn send_test_beacons(
+    downlink: &Downlink,
+    crc: &Mutex<ThreadModeRawMutex, Crc<'static>>,
+    seq: u16,
+) {
+    for valid in [true, false] {
+        let frame = {
+            let mut crc = crc.lock().await;
+            crc.reset();
+            uplink::test_beacon(seq, valid, &mut |bytes: &[u8]| {
+                crc.feed_bytes(bytes);
+                crc.read() as u16
+            })
+        };
+        if let Err(e) = downlink.send(Traffic::Response, &frame).await {
+            error!("could not send crc test beacon: {}", e);
+        }
+    }
+}
+
+/// execute a command that takes effect immediately and ack it
+async fn execute(
+    downlink: &Downlink,
+    lst_inbox: &mut LstInbox,
+    crc: &Mutex<ThreadModeRawMutex, Crc<'static>>,
+    command: &UplinkCommand,
+) {
+    match command.op {
+        link::OP_PING => ack_uplink(downlink, command, AckStatus::Executed).await,
+        link::OP_REBOOT_LST => {
+            // acked before the reboot, the lst can not relay while it boots
+            ack_uplink(downlink, command, AckStatus::Executed).await;
+            reboot_lst(downlink.lst(), lst_inbox).await;
+        }
+        link::OP_LST_VERSION => relay_version(downlink, lst_inbox, command).await,
+        link::OP_CRC_SELF_TEST => {
+            ack_uplink(downlink, command, AckStatus::Executed).await;
+            send_test_beacons(downlink, crc, command.seq).await;
+        }
+        link::OP_HIGH_RATE => {
+            terminal_phase::activate();
+            ack_uplink(downlink, command, AckStatus::Executed).await;
+        }
+        link::OP_BURST => {
+            let duration = match *command.args() {
+                [lo, hi] => Duration::from_secs(u16::from_le_bytes([lo, hi]) as u64),
+                _ => burst::DEFAULT_DURATION,
+            };
+            burst::start(Trigger::Uplink, duration);
+            ack_uplink(downlink, command, AckStatus::Executed).await;
+        }
+        _ => ack_uplink(downlink, command, AckStatus::UnknownOp).await,
+    }
+}
+
+/// retransmit a routed frame towards its destination
+async fn forward(downlink: &Downlink, route: &Route, frame: &[u8]) {
+    debug!("forwarding frame to {:x}", route.destination);
+    if let Err(e) = downlink.forward(route, frame).await {
+        error!("could not forward frame: {}", e);
+    }
+}
+
+/// handles the frames of the lst: forwards telemetry replies, dispatches uplinked
+/// commands, runs time-tagged commands when due and executes lst telecommands.
+/// Routed frames addressed to another radio are retransmitted as a repeater
+#[embassy_executor::task]
+pub async fn lst_link_task(
+    downlink: &'static Downlink,
+    mut lst_inbox: LstInbox,
+    com_channels: &'static LstComChannels,
+    telem: &'static Signal<ThreadModeRawMutex, LSTTelemetry>,
+    blackbox: &'static Mutex<ThreadModeRawMutex, Blackbox>,
+    crc: &'static Mutex<ThreadModeRawMutex, Crc<'static>>,
+    route_node: u16,
+) {
+    // time for the lst to transmit the ack of a save before the reset
+    const SAVE_REBOOT_DELAY: Duration = Duration::from_millis(500);
+    let tc_receiver = com_channels.get_tc_receiver();
+    let mut router = RelayRouter::new(route_node);
+    let utc_ms = || com_channels.get_utc_us() / 1000;
+    let mut dedup = CommandDedup::new();
+    let mut schedule = CommandSchedule::restore();
+    if let Some(due) = schedule.next_due() {
+        info!("restored command schedule, next due at {} ms", due);
+    }
     loop {
-        match lst_recv.receive().await {
-            Ok(msg) => match msg {
-                LSTMessage::Telem(tm) => return tm,
-                LSTMessage::Ack => debug!("ack"),
-                LSTMessage::Nack => debug!("nack"),
-                LSTMessage::Unknown(a, b) => debug!("unknown, cmd: {}, data: {}", a, b),
-                LSTMessage::Relay(_) => debug!("relay"),
-            },
-            Err(e) => {
-                error!("could not receive from lst: {}", e);
+        let next_due = schedule.next_due();
+        let wait_due = async {
+            match next_due {
+                Some(due) => Timer::after_millis(due.saturating_sub(utc_ms())).await,
+                None => core::future::pending().await,
+            }
+        };
+        let received = match select3(lst_inbox.receive(), tc_receiver.receive(), wait_due).await {
+            Either3::First(received) => received,
+            Either3::Second(LSTCommand::Reboot) => {
+                reboot_lst(downlink.lst(), &mut lst_inbox).await;
+                continue;
+            }
+            Either3::Third(()) => {
+                while let Some(scheduled) = schedule.take_due(utc_ms()) {
+                    info!("executing scheduled command {}", scheduled);
+                    execute(downlink, &mut lst_inbox, crc, &scheduled.command()).await;
+                }
+                continue;
+            }
+        };
+        let command = match received.as_message() {
+            LSTMessage::Telem(tm) => {
+                telem.signal(tm);
+                None
+            }
+            LSTMessage::Relay(frame) => {
+                let frame = match Route::parse(frame) {
+                    Some((route, inner)) => match router.route(route, inner) {
+                        Routing::Deliver(inner) => inner,
+                        Routing::Forward(next_hop, inner) => {
+                            forward(downlink, &next_hop, inner).await;
+                            continue;
+                        }
+                        Routing::Drop => continue,
+                    },
+                    None => frame,
+                };
+                let command = UplinkCommand::parse(frame);
+                if command.is_none() {
+                    if frame.first() == Some(&link::RAW_UPLINK_ID) {
+                        info!("raw uplink: {:x}", frame);
+                    } else {
+                        debug!("relay");
+                    }
+                }
+                command
+            }
+            LSTMessage::Ack => {
+                debug!("ack");
+                None
+            }
+            LSTMessage::Nack => {
+                debug!("nack");
+                None
+            }
+            LSTMessage::BootloaderAck(_) | LSTMessage::BootloaderNack => {
+                debug!("bootloader reply");
+                None
+            }
+            LSTMessage::Version(v) => {
+                debug!("version: {}", v);
+                None
+            }
+            LSTMessage::Channels(table) => {
+                debug!("channels: {}", table);
+                None
+            }
+            LSTMessage::Unknown(a, b) => {
+                debug!("unknown, cmd: {}, data: {}", a, b);
+                None
+            }
+            LSTMessage::Captured(reason, frame) => {
+                warn!("captured lst frame ({}): {:x}", reason, frame);
+                let mut blackbox = blackbox.lock().await;
+                if !blackbox.record(Instant::now().as_micros(), reason, frame) {
+                    debug!("blackbox rate limited, {} dropped", blackbox.dropped());
+                }
+                None
+            }
+        };
+        let Some(command) = command else {
+            continue;
+        };
+
+        if dedup.is_duplicate(&command) {
+            // the ground did not get the first ack, ack again without executing
+            info!("duplicate uplink command {}", command);
+            ack_uplink(downlink, &command, AckStatus::Duplicate).await;
+            continue;
+        }
+        info!("uplink command {}", command);
+        match command.op {
+            link::OP_SCHEDULE => {
+                let status = match ScheduledCommand::parse(&command, utc_ms()) {
+                    Some(scheduled) if UplinkCommand::is_schedulable(scheduled.op) => {
+                        if schedule.add(scheduled) {
+                            info!("scheduled command {}", scheduled);
+                            AckStatus::Executed
+                        } else {
+                            warn!("command schedule full");
+                            AckStatus::Failed
+                        }
+                    }
+                    Some(_) => AckStatus::UnknownOp,
+                    None => AckStatus::Failed,
+                };
+                ack_uplink(downlink, &command, status).await;
+            }
+            link::OP_LIST_SCHEDULE => {
+                ack_uplink(downlink, &command, AckStatus::Executed).await;
+                let list = schedule.list_frame();
+                if let Err(e) = downlink.send(Traffic::Response, &list).await {
+                    error!("could not send command schedule: {}", e);
+                }
+            }
+            link::OP_CANCEL_SCHEDULED => {
+                let cancelled = match command.args() {
+                    [lo, hi] => {
+                        schedule.cancel(command.source_hwid, u16::from_le_bytes([*lo, *hi]))
+                    }
+                    _ => false,
+                };
+                let status = if cancelled {
+                    AckStatus::Executed
+                } else {
+                    AckStatus::Failed
+                };
+                ack_uplink(downlink, &command, status).await;
+            }
+            link::OP_PAYLOAD_ENABLE | link::OP_PAYLOAD_RATE => {
+                let status = match *command.args() {
+                    [id, value] => match payload::find(id) {
+                        Some(p) if command.op == link::OP_PAYLOAD_ENABLE => {
+                            info!("payload {} enabled: {}", p.name, value != 0);
+                            p.set_enabled(value != 0);
+                            AckStatus::Executed
+                        }
+                        Some(p) => {
+                            info!("payload {} rate: {}%", p.name, value);
+                            p.set_rate_percent(value);
+                            AckStatus::Executed
+                        }
+                        None => AckStatus::Failed,
+                    },
+                    _ => AckStatus::Failed,
+                };
+                ack_uplink(downlink, &command, status).await;
+            }
+            link::OP_TELEMETRY_FILTER => {
+                let status = if telemetry_filter::apply(command.args()) {
+                    info!("telemetry filter: {:x}", command.args());
+                    AckStatus::Executed
+                } else {
+                    AckStatus::Failed
+                };
+                ack_uplink(downlink, &command, status).await;
+            }
+            link::OP_BEACON_CONFIG => {
+                let status = if beacon_registry::apply(command.args()) {
+                    info!("beacon config: {:x}", command.args());
+                    AckStatus::Executed
+                } else {
+                    AckStatus::Failed
+                };
+                ack_uplink(downlink, &command, status).await;
             }
+            link::OP_BEACON_SAVE => {
+                // erasing a 128 KiB sector blocks far longer than the watchdog timeout, so the
+                // flash is written during the next boot before the watchdog is unleashed. The
+                // reboot loses beacons, only done on the pad and with the confirmation
+                if command.args() != link::BEACON_SAVE_CONFIRM {
+                    warn!("beacon registry save without confirmation");
+                    ack_uplink(downlink, &command, AckStatus::Failed).await;
+                    continue;
+                }
+                if mission_phase::current() != Phase::Pad {
+                    warn!("beacon registry only saved on the pad");
+                    ack_uplink(downlink, &command, AckStatus::Failed).await;
+                    continue;
+                }
+                beacon_registry::request_save();
+                ack_uplink(downlink, &command, AckStatus::Executed).await;
+                Timer::after(SAVE_REBOOT_DELAY).await;
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+            link::OP_SET_T0 => {
+                let t0_ms = match *command.args() {
+                    [] => Some(None),
+                    [a, b, c, d, e, f, g, h] => {
+                        Some(Some(u64::from_le_bytes([a, b, c, d, e, f, g, h])))
+                    }
+                    _ => None,
+                };
+                let status = match t0_ms {
+                    Some(t0_ms) => {
+                        info!("T-0 set to {} ms", t0_ms);
+                        met::set_t0(t0_ms);
+                        AckStatus::Executed
+                    }
+                    None => AckStatus::Failed,
+                };
+                ack_uplink(downlink, &command, status).await;
+            }
+            link::OP_SET_PHASE => {
+                let phase = match *command.args() {
+                    [phase] => Phase::from_u8(phase),
+                    _ => None,
+                };
+                let Some(phase) = phase else {
+                    ack_uplink(downlink, &command, AckStatus::Failed).await;
+                    continue;
+                };
+                info!("mission phase set to {}", phase);
+                mission_phase::set(phase);
+                ack_uplink(downlink, &command, AckStatus::Executed).await;
+                downlink_phase(downlink).await;
+            }
+            _ => execute(downlink, &mut lst_inbox, crc, &command).await,
         }
     }
 }
@@ -107,20 +654,26 @@ async fn wait_for_telem(lst_recv: &mut LSTReceiver<RingBufferedUartRx<'static>>)
 #[embassy_executor::task]
 pub async fn lst_telemetry_thread(
     lst_beacon: &'static Mutex<ThreadModeRawMutex, LSTBeacon>,
-    lst: &'static Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>,
-    mut lst_recv: LSTReceiver<RingBufferedUartRx<'static>>,
+    downlink: &'static Downlink,
+    telem: &'static Signal<ThreadModeRawMutex, LSTTelemetry>,
     tm_sender: LstTMSender,
+    airtime: &'static Mutex<ThreadModeRawMutex, AirtimeBudget>,
+    shaper: &'static Mutex<ThreadModeRawMutex, TrafficShaper>,
 ) {
     const LST_TM_INTERVAL: Duration = Duration::from_secs(10);
     const LST_TM_TIMEOUT: Duration = Duration::from_millis(1000);
     let mut ticker = Ticker::every(LST_TM_INTERVAL);
     loop {
-        lst.lock()
+        // drop a late reply to the previous request
+        telem.reset();
+        downlink
+            .lst()
+            .lock()
             .await
             .cmd(LSTCmd::GetTelem)
             .await
             .unwrap_or_else(|e| error!("could not send cmd to lst: {}", e));
-        if let Ok(lst_tm) = with_timeout(LST_TM_TIMEOUT, wait_for_telem(&mut lst_recv)).await {
+        if let Ok(lst_tm) = with_timeout(LST_TM_TIMEOUT, telem.wait()).await {
             debug!("received lst telem msg: {}", lst_tm);
             let mut lst_beacon = lst_beacon.lock().await;
 
@@ -145,32 +698,113 @@ pub async fn lst_telemetry_thread(
         } else {
             error!("lst did not answer");
         }
+        downlink_phase(downlink).await;
+        let health = lst_uart::health_frame();
+        if let Err(e) = downlink.send(Traffic::Housekeeping, &health).await {
+            error!("could not downlink lst uart health: {}", e);
+        }
+        let duty_cycle = airtime.lock().await.frame(Instant::now().as_micros());
+        if let Err(e) = downlink.send(Traffic::Housekeeping, &duty_cycle).await {
+            error!("could not downlink duty cycle budget: {}", e);
+        }
+        let quotas = shaper.lock().await.frame(Instant::now().as_micros());
+        if let Err(e) = downlink.send(Traffic::Housekeeping, &quotas).await {
+            error!("could not downlink quota utilization: {}", e);
+        }
+        // stamp the local time only once the lst is free, right before the send
+        if let Err(e) = downlink
+            .send_stamped(Traffic::Housekeeping, time_correlation::frame)
+            .await
+        {
+            error!("could not downlink time correlation: {}", e);
+        }
         ticker.next().await;
     }
 }
 
+/// periodically report the busiest can ids and rx backlog, logged and downlinked
 #[embassy_executor::task]
-pub async fn command_execution_task(
-    lst: &'static Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>,
-    tc_receiver: LstTCReceiver,
+pub async fn can_stats_task(
+    stats: &'static Mutex<ThreadModeRawMutex, CanRxStats>,
+    downlink: &'static Downlink,
 ) {
+    const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+    let mut ticker = Ticker::every(REPORT_INTERVAL);
     loop {
-        match tc_receiver.receive().await {
-            LSTCommand::Reboot => {
-                if let Err(e) = lst.lock().await.cmd(LSTCmd::Reboot).await {
-                    error!("could not reboot: {}", e);
-                }
-            }
+        ticker.next().await;
+        let mut stats = stats.lock().await;
+        let secs = REPORT_INTERVAL.as_secs() as u32;
+        info!(
+            "can rx: {} frames/s, {} untracked, max latency {} us",
+            stats.total() / secs,
+            stats.untracked(),
+            stats.max_latency_us()
+        );
+        for rate in stats.busiest::<5>().iter().flatten() {
+            info!("can id {:x}: {} frames/s", rate.id, rate.frames / secs);
+        }
+        if stats.backlogged() > 0 {
+            warn!(
+                "can rx backlog: {} frames waited longer than {} ms",
+                stats.backlogged(),
+                crate::can_stats::BACKLOG_LATENCY.as_millis()
+            );
+        }
+        let frame = stats.frame(REPORT_INTERVAL);
+        stats.reset();
+        drop(stats);
+        if let Err(e) = downlink.send(Traffic::Housekeeping, &frame).await {
+            error!("could not downlink can stats: {}", e);
+        }
+    }
+}
+
+/// periodically report the cpu load of the profiled tasks and the stack high water mark,
+/// logged and downlinked
+#[embassy_executor::task]
+pub async fn profiling_task(downlink: &'static Downlink) {
+    const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+    // usage above which a report is raised to a warning
+    const WARN_PERCENT: u64 = 80;
+    let mut ticker = Ticker::every(REPORT_INTERVAL);
+    loop {
+        ticker.next().await;
+        let window_us = REPORT_INTERVAL.as_micros();
+        let mut total_us = 0;
+        let mut busy_permille = [0; link::PROFILED_TASKS.len()];
+        for (task, permille) in profiling::TASKS.iter().zip(&mut busy_permille) {
+            let busy_us = task.take_busy_us() as u64;
+            total_us += busy_us;
+            *permille = (busy_us * 1000 / window_us) as u16;
+            debug!("task {}: {} us busy", task.name, busy_us);
+        }
+        let cpu_percent = total_us * 100 / window_us;
+        let (stack_used, stack_size) = profiling::stack_usage();
+        let stack_percent = (stack_used * 100 / stack_size) as u64;
+        if cpu_percent > WARN_PERCENT || stack_percent > WARN_PERCENT {
+            warn!(
+                "cpu load {}%, stack {}/{} bytes",
+                cpu_percent, stack_used, stack_size
+            );
+        } else {
+            info!(
+                "cpu load {}%, stack {}/{} bytes",
+                cpu_percent, stack_used, stack_size
+            );
+        }
+        let frame = profiling::frame(&busy_permille);
+        if let Err(e) = downlink.send(Traffic::Housekeeping, &frame).await {
+            error!("could not downlink profiling: {}", e);
         }
     }
 }
 
 #[embassy_executor::task]
 pub async fn can_receiver_task(mut can_receiver: LstCanReceiver) -> ! {
-    can_receiver.run().await
+    profiled(&profiling::CAN_RX, can_receiver.run()).await
 }
 
 #[embassy_executor::task]
 pub async fn can_sender_task(mut can_sender: LstCanSender) -> ! {
-    can_sender.run().await
+    profiled(&profiling::CAN_TX, can_sender.run()).await
 }
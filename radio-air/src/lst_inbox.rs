@@ -0,0 +1,86 @@
+use core::convert::Infallible;
+
+use defmt::{error, warn};
+use embassy_stm32::usart::RingBufferedUartRx;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use heapless::Deque;
+use openlst_driver::lst_receiver::{
+    LSTMessage, LSTReceiver, MessageSource, OwnedLSTMessage, ReceiverError,
+};
+
+use crate::{
+    lst_uart,
+    profiling::{self, profiled},
+};
+
+// complete frames on their way from the receive task to the link task
+const QUEUE_LEN: usize = 4;
+// relay frames held back while a lst telecommand waits for its reply
+const DEFERRED_LEN: usize = 4;
+
+static MESSAGES: Channel<ThreadModeRawMutex, OwnedLSTMessage, QUEUE_LEN> = Channel::new();
+
+/// owns the lst receiver. Receiving a frame is not cancel safe, so it only runs here
+/// and complete frames are handed on to the inbox
+#[embassy_executor::task]
+pub async fn lst_receive_task(mut lst_rx: LSTReceiver<RingBufferedUartRx<'static>>) {
+    loop {
+        match profiled(&profiling::LST_LINK, lst_rx.receive()).await {
+            Ok(msg) => {
+                lst_uart::received();
+                MESSAGES.send(msg.into_owned()).await;
+            }
+            Err(e) => {
+                error!("could not receive from lst: {}", e);
+                lst_uart::error(&mut lst_rx, &e);
+            }
+        }
+    }
+}
+
+/// frames of the receive task, can be selected against other futures without losing one
+pub struct LstInbox {
+    deferred: Deque<OwnedLSTMessage, DEFERRED_LEN>,
+    current: Option<OwnedLSTMessage>,
+}
+
+impl LstInbox {
+    pub const fn new() -> Self {
+        Self {
+            deferred: Deque::new(),
+            current: None,
+        }
+    }
+
+    /// next frame, relay frames held back during a telecommand first
+    pub async fn receive(&mut self) -> OwnedLSTMessage {
+        match self.deferred.pop_front() {
+            Some(msg) => msg,
+            None => MESSAGES.receive().await,
+        }
+    }
+
+    fn defer(&mut self, msg: OwnedLSTMessage) {
+        if self.deferred.is_full() {
+            self.deferred.pop_front();
+            warn!("dropped a held back relay frame");
+        }
+        let _ = self.deferred.push_back(msg);
+    }
+}
+
+/// replies for the lst telecommands, relay frames are kept for the link task
+impl MessageSource for LstInbox {
+    type Error = Infallible;
+
+    async fn next_message(&mut self) -> Result<LSTMessage<'_>, ReceiverError<Infallible>> {
+        loop {
+            let msg = MESSAGES.receive().await;
+            if matches!(msg, OwnedLSTMessage::Relay(_)) {
+                self.defer(msg);
+                continue;
+            }
+            return Ok(self.current.insert(msg).as_message());
+        }
+    }
+}
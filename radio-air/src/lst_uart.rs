@@ -0,0 +1,54 @@
+use core::cell::RefCell;
+
+use defmt::warn;
+use embassy_stm32::usart::{self, RingBufferedUartRx};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embedded_io_async::ReadExactError;
+use openlst_driver::{
+    link::UART_HEALTH_ID,
+    lst_receiver::{LSTReceiver, ReceiverError},
+    uart_recovery::{UartErrorClass, UartHealth, UartRecovery},
+};
+
+static RECOVERY: Mutex<ThreadModeRawMutex, RefCell<UartRecovery>> =
+    Mutex::new(RefCell::new(UartRecovery::new()));
+
+fn classify(e: &usart::Error) -> UartErrorClass {
+    match e {
+        usart::Error::Framing => UartErrorClass::Framing,
+        usart::Error::Noise => UartErrorClass::Noise,
+        usart::Error::Overrun => UartErrorClass::Overrun,
+        _ => UartErrorClass::Other,
+    }
+}
+
+/// a frame was received from the lst
+pub fn received() {
+    RECOVERY.lock(|recovery| recovery.borrow_mut().received());
+}
+
+/// count a receive error, flushes and restarts the dma ring buffer once the
+/// uart errors persist
+pub fn error(
+    lst_rx: &mut LSTReceiver<RingBufferedUartRx<'static>>,
+    e: &ReceiverError<usart::Error>,
+) {
+    let class = match e {
+        ReceiverError::ReadError(ReadExactError::Other(e)) => classify(e),
+        ReceiverError::ReadError(ReadExactError::UnexpectedEof) => UartErrorClass::Other,
+        // the uart itself is fine
+        ReceiverError::ParseError(_) | ReceiverError::MsgTooShort => return,
+    };
+    if RECOVERY.lock(|recovery| recovery.borrow_mut().error(class)) {
+        warn!("persistent lst uart errors ({}), reinitializing", class);
+        lst_rx.uart_mut().start_uart();
+    }
+}
+
+/// error counters for the housekeeping downlink
+pub fn health_frame() -> [u8; 1 + UartHealth::LEN] {
+    let health = RECOVERY.lock(|recovery| recovery.borrow().health());
+    let mut frame = [UART_HEALTH_ID; 1 + UartHealth::LEN];
+    frame[1..].copy_from_slice(&health.to_bytes());
+    frame
+}
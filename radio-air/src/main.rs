@@ -1,9 +1,29 @@
 #![no_std]
 #![no_main]
 
+mod beacon_registry;
+mod beacon_schedule;
+mod blackbox;
+mod burst;
+#[cfg(feature = "can-injection")]
+mod can_injection;
+mod can_stats;
+mod clock_drift;
+mod command_schedule;
+mod downlink;
 mod io_threads;
-
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+mod lst_inbox;
+mod lst_uart;
+mod met;
+mod mission_phase;
+mod payload;
+mod profiling;
+mod telemetry_filter;
+mod terminal_phase;
+mod time_correlation;
+mod uplink;
+
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, signal::Signal};
 
 use defmt::info;
 use embassy_executor::Spawner;
@@ -13,12 +33,13 @@ use embassy_stm32::{
     crc::{self, Crc},
     dma,
     exti::{self, ExtiInput},
+    flash::Flash,
     gpio::{Level, Output, Pull, Speed},
     interrupt::typelevel::EXTI15_10,
     mode::Async,
     peripherals::*,
     rcc,
-    usart::{self, Uart, UartTx},
+    usart::{self, Uart, UartRx, UartTx},
     wdg::IndependentWatchdog,
 };
 use embassy_time::{Duration, Timer};
@@ -39,13 +60,29 @@ use south_common::beacons::{
 #[cfg(feature = "secondary")]
 use south_common::beacons::SecondaryLstBeacon;
 
+use param_store::ParamStore;
+
+use crate::beacon_schedule::{
+    AirtimeBudget, BeaconClass, ClassQuota, DutyCycleConfig, ScheduleConfig, ScheduleMode,
+    TrafficShaper,
+};
+use crate::blackbox::Blackbox;
+use crate::can_stats::CanRxStats;
+use crate::clock_drift::DriftCorrector;
+use crate::downlink::Downlink;
 use crate::io_threads::BeaconIngress;
+use crate::lst_inbox::LstInbox;
+#[cfg(feature = "primary")]
+use crate::mission_phase::Phase;
+use crate::mission_phase::PhaseSensors;
+use crate::mission_phase::PhaseSet;
 
 use {defmt_rtt as _, panic_probe as _};
 
 use static_cell::StaticCell;
 
-use openlst_driver::lst_receiver::LSTReceiver;
+use openlst_driver::link;
+use openlst_driver::lst_receiver::{LSTReceiver, LSTTelemetry};
 use openlst_driver::lst_sender::LSTSender;
 
 // General setup stuff
@@ -69,6 +106,80 @@ const PYRO_BEACON_INTERVAL: Duration = Duration::from_secs(2);
 #[cfg(feature = "secondary")]
 const SECONDARY_LST_BEACON_INTERVAL: Duration = Duration::from_secs(3);
 
+// beacon intervals after the descent below the terminal altitude
+#[cfg(feature = "primary")]
+const TERMINAL_HIGH_RATE_UPPER_BEACON_INTERVAL: Duration = Duration::from_millis(50);
+#[cfg(feature = "primary")]
+const TERMINAL_LOW_RATE_UPPER_BEACON_INTERVAL: Duration = Duration::from_millis(200);
+#[cfg(feature = "primary")]
+const TERMINAL_PYRO_BEACON_INTERVAL: Duration = Duration::from_millis(500);
+
+// beacon interval during a burst capture around the launch, apogee or on command
+#[cfg(feature = "primary")]
+const BURST_HIGH_RATE_UPPER_BEACON_INTERVAL: Duration = Duration::from_millis(20);
+
+// mission phases the beacons are sent in
+#[cfg(feature = "primary")]
+const IN_FLIGHT: PhaseSet = PhaseSet::of(&[Phase::Ascent, Phase::Coast, Phase::Descent]);
+#[cfg(feature = "primary")]
+const UNTIL_LANDING: PhaseSet =
+    PhaseSet::of(&[Phase::Pad, Phase::Ascent, Phase::Coast, Phase::Descent]);
+
+// beacon transmit scheduling, the secondary vehicle is offset to interleave on a shared channel.
+// Slotting aligns transmissions to the synchronized time, e.g. for a TDMA with the ground uplink
+const BEACON_SLOTTING: bool = false;
+const BEACON_OFFSET: Duration =
+    Duration::from_millis(if cfg!(feature = "primary") { 0 } else { 50 });
+static BEACON_SCHEDULE: ScheduleConfig = ScheduleConfig {
+    mode: if BEACON_SLOTTING {
+        ScheduleMode::Slotted {
+            frame: Duration::from_millis(100),
+            slot_offset: BEACON_OFFSET,
+        }
+    } else {
+        ScheduleMode::Free {
+            phase: BEACON_OFFSET,
+        }
+    },
+    jitter: Duration::from_millis(20),
+};
+// license exempt transmit limit of the band, shared by all beacons of this vehicle.
+// Critical beacons are still sent once it is used up, the others wait for budget
+static DUTY_CYCLE: DutyCycleConfig = DutyCycleConfig {
+    // rf data rate configured in the lst firmware
+    data_rate_bps: 7_415,
+    window: Duration::from_secs(60 * 60),
+    limit_permille: 100,
+};
+static AIRTIME: Mutex<ThreadModeRawMutex, AirtimeBudget> =
+    Mutex::new(AirtimeBudget::new(&DUTY_CYCLE));
+// downlink byte quotas per beacon class, indexed by BeaconClass. Together below the
+// ~900 B/s of the lst data rate, so the safety beacons always find room
+static CLASS_QUOTAS: [ClassQuota; 3] = [
+    // safety
+    ClassQuota {
+        rate: 150,
+        burst: 600,
+    },
+    // housekeeping
+    ClassQuota {
+        rate: 100,
+        burst: 400,
+    },
+    // science
+    ClassQuota {
+        rate: 550,
+        burst: 1100,
+    },
+];
+static SHAPER: Mutex<ThreadModeRawMutex, TrafficShaper> =
+    Mutex::new(TrafficShaper::new(&CLASS_QUOTAS));
+
+// flash parameter area, the last two 128 KiB sectors, kept out of FLASH in memory.x
+const PARAM_SLOT_A: u32 = 0x000C_0000;
+const PARAM_SLOT_B: u32 = 0x000E_0000;
+const PARAM_SLOT_LEN: u32 = 0x0002_0000;
+
 const WATCHDOG_TIMEOUT_US: u32 = 300_000;
 const WATCHDOG_PETTING_INTERVAL_US: u32 = WATCHDOG_TIMEOUT_US / 2;
 
@@ -97,6 +208,25 @@ static BL: StaticCell<
 static LST: StaticCell<Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>> =
     StaticCell::new();
 static CRC: StaticCell<Mutex<ThreadModeRawMutex, Crc>> = StaticCell::new();
+static DOWNLINK: StaticCell<Downlink> = StaticCell::new();
+
+// Oscillator drift correction for beacon timestamps
+static DRIFT: Mutex<ThreadModeRawMutex, DriftCorrector> = Mutex::new(DriftCorrector::new());
+
+// Lst telemetry replies, forwarded from the lst link
+static LST_TELEM: Signal<ThreadModeRawMutex, LSTTelemetry> = Signal::new();
+
+// Rejected lst frames for post-flight analysis, kept over soft resets
+static BLACKBOX: Mutex<ThreadModeRawMutex, Blackbox> = Mutex::new(Blackbox::new());
+
+// Can receive statistics
+static CAN_STATS: Mutex<ThreadModeRawMutex, CanRxStats> = Mutex::new(CanRxStats::new());
+
+// barometer and accelerometer telemetry of the upper sensor board driving the mission phase
+static PHASE_SENSORS: PhaseSensors = PhaseSensors {
+    pressure: &tm::upper_sensor::Pressure,
+    accel: &tm::upper_sensor::Acceleration,
+};
 
 // Static can buffer
 const C_RX_BUF_SIZE: usize = 512;
@@ -109,6 +239,10 @@ static C_TX_BUF: StaticCell<TxFdBuf<C_TX_BUF_SIZE>> = StaticCell::new();
 const S_RX_BUF_SIZE: usize = 1024;
 static S_RX_BUF: StaticCell<[u8; S_RX_BUF_SIZE]> = StaticCell::new();
 
+// Debug uart buffer for bench test commands
+#[cfg(feature = "can-injection")]
+static DEBUG_RX_BUF: StaticCell<[u8; 256]> = StaticCell::new();
+
 // Obdh types
 gen_obdh_types!(Lst, tm::lst, BeaconIngress, LSTCommand);
 
@@ -125,6 +259,10 @@ bind_interrupts!(struct Irqs {
     FDCAN2_IT1 => can::IT1InterruptHandler<FDCAN2>;
 
     USART2 => usart::InterruptHandler<USART2>;
+    #[cfg(feature = "can-injection")]
+    USART3 => usart::InterruptHandler<USART3>;
+    #[cfg(feature = "can-injection")]
+    DMA1_STREAM3 => dma::InterruptHandler<DMA1_CH3>;
     //USART3 => usart::InterruptHandler<USART3>;
     DMA1_STREAM1 => dma::InterruptHandler<DMA1_CH1>;
     DMA1_STREAM2 => dma::InterruptHandler<DMA1_CH2>;
@@ -189,6 +327,8 @@ fn get_crc_config() -> crc::Config {
 /// program entry
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
+    profiling::paint_stack();
+
     let mut config = Config::default();
     config.rcc = get_rcc_config();
     let p = embassy_stm32::init(config);
@@ -198,6 +338,18 @@ async fn main(spawner: Spawner) {
 
     info!("Launching: FW version={} hash={}", FW_VERSION, FW_HASH);
 
+    // the active beacon set, a pending save erases flash and has to finish before the watchdog runs
+    let mut params = ParamStore::new(
+        Flash::new_blocking(p.FLASH),
+        PARAM_SLOT_A,
+        PARAM_SLOT_B,
+        PARAM_SLOT_LEN,
+    );
+    beacon_registry::restore(&mut params);
+    met::restore();
+    mission_phase::restore();
+    BLACKBOX.lock().await.restore();
+
     // unleash independent watchdog
     let mut watchdog = IndependentWatchdog::new(p.IWDG1, WATCHDOG_TIMEOUT_US);
     watchdog.unleash();
@@ -231,7 +383,10 @@ async fn main(spawner: Spawner) {
     // .split();
 
     let lst_tx = LST.init(Mutex::new(LSTSender::new(uart_tx, OPENLST_HWID)));
-    let lst_rx = LSTReceiver::new(uart_rx.into_ring_buffered(S_RX_BUF.init([0; _])));
+    let downlink = DOWNLINK.init(Downlink::new(lst_tx, &AIRTIME, &SHAPER));
+    let mut lst_rx = LSTReceiver::new(uart_rx.into_ring_buffered(S_RX_BUF.init([0; _])));
+    // surface unexpected frames to make protocol mismatches visible
+    lst_rx.set_promiscuous(true);
 
     // lst feedback pins
     let cc_rx_pin = ExtiInput::new(p.PD14, p.EXTI14, Pull::None, Irqs);
@@ -283,11 +438,10 @@ async fn main(spawner: Spawner) {
     );
 
     // Setup can sender and receiver runners
-    let can_receiver = LstCanReceiver::new(
-        can_instance.reader(),
-        &COM_CHANNELS,
-        BeaconIngress::new(receivable_beacons),
-    );
+    let ingress = BeaconIngress::new(receivable_beacons, &CAN_STATS, &PHASE_SENSORS);
+    #[cfg(feature = "can-injection")]
+    let injection_ingress = ingress.clone();
+    let can_receiver = LstCanReceiver::new(can_instance.reader(), &COM_CHANNELS, ingress);
     let can_sender = LstCanSender::new(can_instance.writer(), &COM_CHANNELS);
 
     // set can standby pin to low
@@ -298,12 +452,43 @@ async fn main(spawner: Spawner) {
     spawner.spawn(petter(watchdog).unwrap());
     spawner.spawn(io_threads::can_receiver_task(can_receiver).unwrap());
     spawner.spawn(io_threads::can_sender_task(can_sender).unwrap());
-    spawner
-        .spawn(io_threads::command_execution_task(lst_tx, COM_CHANNELS.get_tc_receiver()).unwrap());
+    spawner.spawn(io_threads::can_stats_task(&CAN_STATS, downlink).unwrap());
+    spawner.spawn(io_threads::profiling_task(downlink).unwrap());
+
+    // bench test commands on the debug uart
+    #[cfg(feature = "can-injection")]
+    {
+        let mut debug_config = usart::Config::default();
+        debug_config.baudrate = 115200;
+        let debug_rx = UartRx::new(p.USART3, p.PD9, p.DMA1_CH3, Irqs, debug_config)
+            .unwrap()
+            .into_ring_buffered(DEBUG_RX_BUF.init([0; _]));
+        spawner.spawn(can_injection::injection_task(debug_rx, injection_ingress).unwrap());
+    }
+    spawner.spawn(lst_inbox::lst_receive_task(lst_rx).unwrap());
+    spawner.spawn(
+        io_threads::lst_link_task(
+            downlink,
+            LstInbox::new(),
+            &COM_CHANNELS,
+            &LST_TELEM,
+            &BLACKBOX,
+            crc,
+            link::ROUTE_NODE_AIR,
+        )
+        .unwrap(),
+    );
     #[cfg(feature = "primary")]
     spawner.spawn(
-        io_threads::lst_telemetry_thread(&LST_BCN, lst_tx, lst_rx, COM_CHANNELS.get_tm_sender())
-            .unwrap(),
+        io_threads::lst_telemetry_thread(
+            &LST_BCN,
+            downlink,
+            &LST_TELEM,
+            COM_CHANNELS.get_tm_sender(),
+            &AIRTIME,
+            &SHAPER,
+        )
+        .unwrap(),
     );
 
     spawner.spawn(cc_mode(cc_rx_pin, cc_rx_led).unwrap());
@@ -312,32 +497,53 @@ async fn main(spawner: Spawner) {
     // LST sender startup
     Timer::after_millis(STARTUP_DELAY).await;
 
+    // each beacon is sent within the quota of its class, safety beacons are sent even
+    // when the duty cycle budget is used up.
+    // The position in the list is the index of the beacon in the beacon registry
     macro_rules! spawn_beacons {
-        ($(($beacon:ident, $interval:expr),)*) => { $(
+        ($(($beacon:ident, $payload:ident, $phases:expr, $interval:expr, $terminal_interval:expr, $burst_interval:expr, $class:ident),)*) => {
+            let mut index = 0;
+            $(
             spawner.spawn(
                 io_threads::lst_sender_thread(
                     $interval,
+                    $terminal_interval,
+                    $burst_interval,
                     &COM_CHANNELS,
                     &$beacon,
+                    &payload::$payload,
+                    $phases,
                     crc,
-                    lst_tx,
+                    downlink,
+                    &DRIFT,
+                    &BEACON_SCHEDULE,
+                    BeaconClass::$class,
+                    index,
                 )
                 .unwrap(),
             );
-        )* };
+            index += 1;
+            )*
+            assert!(index <= beacon_registry::MAX_BEACONS);
+        };
     }
     #[cfg(feature = "primary")]
     spawn_beacons!(
-        (LST_BCN, LST_BEACON_INTERVAL),
-        (EPS_BCN, EPS_BEACON_INTERVAL),
-        (HIGH_R_UPP_SENS_BCN, HIGH_RATE_UPPER_BEACON_INTERVAL),
-        (LOW_R_UPP_SENS_BCN, LOW_RATE_UPPER_BEACON_INTERVAL),
-        (LOW_SENS_BCN, LOWER_SENSOR_BEACON_INTERVAL),
-        (PYRO_BCN, PYRO_BEACON_INTERVAL),
+        (LST_BCN, BUS, PhaseSet::ALL, LST_BEACON_INTERVAL, LST_BEACON_INTERVAL, None, Safety),
+        (EPS_BCN, BUS, PhaseSet::ALL, EPS_BEACON_INTERVAL, EPS_BEACON_INTERVAL, None, Housekeeping),
+        (HIGH_R_UPP_SENS_BCN, UPPER_SENSORS, IN_FLIGHT, HIGH_RATE_UPPER_BEACON_INTERVAL, TERMINAL_HIGH_RATE_UPPER_BEACON_INTERVAL, Some(BURST_HIGH_RATE_UPPER_BEACON_INTERVAL), Science),
+        // the gps position is needed to find the vehicle in recovery
+        (LOW_R_UPP_SENS_BCN, UPPER_SENSORS, PhaseSet::ALL, LOW_RATE_UPPER_BEACON_INTERVAL, TERMINAL_LOW_RATE_UPPER_BEACON_INTERVAL, None, Safety),
+        (LOW_SENS_BCN, LOWER_SENSORS, UNTIL_LANDING, LOWER_SENSOR_BEACON_INTERVAL, LOWER_SENSOR_BEACON_INTERVAL, None, Science),
+        (PYRO_BCN, BUS, UNTIL_LANDING, PYRO_BEACON_INTERVAL, TERMINAL_PYRO_BEACON_INTERVAL, None, Safety),
     );
+    #[cfg(feature = "primary")]
+    spawner.spawn(io_threads::mission_phase_task(&HIGH_R_UPP_SENS_BCN, downlink).unwrap());
+    spawner
+        .spawn(io_threads::terminal_phase_task(&LOW_R_UPP_SENS_BCN, downlink, &BLACKBOX).unwrap());
     #[cfg(feature = "secondary")]
     spawn_beacons!(
-        (SEC_BCN, SECONDARY_LST_BEACON_INTERVAL),
+        (SEC_BCN, BUS, PhaseSet::ALL, SECONDARY_LST_BEACON_INTERVAL, SECONDARY_LST_BEACON_INTERVAL, None, Safety),
     );
 
     core::future::pending::<()>().await;
@@ -0,0 +1,69 @@
+use core::{cell::Cell, mem::MaybeUninit, ptr};
+
+use defmt::info;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use openlst_driver::link::MET_ID;
+
+use crate::command_schedule::checksum;
+
+// marker in front of a beacon frame once T-0 is set, the met is negative during the countdown
+pub const MET_MARKER_LEN: usize = 5;
+
+// "MET0", marks a T-0 set in a previous run
+const MAGIC: u32 = 0x4D45_5430;
+// magic(4) checksum(4) T-0 utc ms(8 LE)
+const PERSISTED_LEN: usize = 16;
+
+// not zeroed by the startup code, a watchdog reset in flight keeps the timebase
+#[unsafe(link_section = ".uninit.MET")]
+static mut PERSISTED: MaybeUninit<[u8; PERSISTED_LEN]> = MaybeUninit::uninit();
+
+// T-0 as synchronized utc ms, None until set by command
+static T0: Mutex<ThreadModeRawMutex, Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+fn persist(t0_ms: Option<u64>) {
+    let mut bytes = [0; PERSISTED_LEN];
+    if let Some(t0_ms) = t0_ms {
+        bytes[8..].copy_from_slice(&t0_ms.to_le_bytes());
+        let sum = checksum(&bytes[8..]);
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&sum.to_le_bytes());
+    }
+    // SAFETY: the area is only accessed from this module in thread mode, any content
+    // is a valid byte array and checked against the checksum before use
+    unsafe { ptr::write_volatile(&raw mut PERSISTED, MaybeUninit::new(bytes)) };
+}
+
+/// take over the T-0 of the previous run, none after a power cycle
+pub fn restore() {
+    // SAFETY: see persist
+    let bytes = unsafe { ptr::read_volatile(&raw const PERSISTED).assume_init() };
+    let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+    let sum = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let t0_ms = (magic == MAGIC && sum == checksum(&bytes[8..]))
+        .then(|| u64::from_le_bytes(bytes[8..].try_into().unwrap()));
+    if let Some(t0_ms) = t0_ms {
+        info!("restored T-0 at {} ms", t0_ms);
+    }
+    T0.lock(|t0| t0.set(t0_ms));
+    persist(t0_ms);
+}
+
+/// set T-0 as synchronized utc ms, None clears it
+pub fn set_t0(t0_ms: Option<u64>) {
+    T0.lock(|t0| t0.set(t0_ms));
+    persist(t0_ms);
+}
+
+/// mission elapsed time at the synchronized utc time, None before T-0 is set
+pub fn elapsed_ms(utc_us: u64) -> Option<i32> {
+    let t0_ms = T0.lock(|t0| t0.get())?;
+    let met_ms = (utc_us / 1000) as i64 - t0_ms as i64;
+    Some(met_ms.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+}
+
+/// marker stamping a beacon frame with the mission elapsed time
+pub fn marker(utc_us: u64) -> Option<[u8; MET_MARKER_LEN]> {
+    let met_ms = elapsed_ms(utc_us)?.to_le_bytes();
+    Some([MET_ID, met_ms[0], met_ms[1], met_ms[2], met_ms[3]])
+}
@@ -0,0 +1,295 @@
+use core::{
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use defmt::{Format, info};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use embassy_time::Duration;
+use libm::{powf, sqrtf};
+use openlst_driver::link::PHASE_ID;
+use south_common::chell::ChellDefinition;
+
+use crate::command_schedule::checksum;
+
+// climb above the first altitude sample that marks the launch
+const LAUNCH_HEIGHT_M: f64 = 50.0;
+// specific force of the burning motor, about 3 g
+const LAUNCH_ACCEL_MS2: f32 = 30.0;
+// below gravity only drag decelerates the vehicle, the motor burned out
+const BURNOUT_ACCEL_MS2: f32 = 9.0;
+// standard atmosphere at sea level, the altitudes are only compared to the pad
+const SEA_LEVEL_PA: f32 = 101_325.0;
+// vertical speed below which the vehicle is considered at rest after landing
+const REST_SPEED_MS: f64 = 2.0;
+// consecutive samples confirming a transition, filters sensor noise
+const CONFIRM_SAMPLES: u8 = 3;
+// samples at rest before recovery, the vehicle may hang in a tree for a moment
+const REST_SAMPLES: u8 = 10;
+
+// "PHS0", marks a phase of a previous run
+const MAGIC: u32 = 0x5048_5330;
+// magic(4) checksum(4) phase(1)
+const PERSISTED_LEN: usize = 9;
+
+// not zeroed by the startup code, a watchdog reset in flight does not fall back to the pad
+#[unsafe(link_section = ".uninit.PHASE")]
+static mut PERSISTED: MaybeUninit<[u8; PERSISTED_LEN]> = MaybeUninit::uninit();
+
+#[derive(Format, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Phase {
+    Pad = 0,
+    Ascent = 1,
+    Coast = 2,
+    Descent = 3,
+    Recovery = 4,
+}
+
+impl Phase {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Phase::Pad,
+            1 => Phase::Ascent,
+            2 => Phase::Coast,
+            3 => Phase::Descent,
+            4 => Phase::Recovery,
+            _ => return None,
+        })
+    }
+    /// share of the nominal beacon rate used in this phase in percent
+    fn rate_percent(self) -> u32 {
+        match self {
+            Phase::Pad => 50,
+            Phase::Ascent | Phase::Coast | Phase::Descent => 100,
+            // only enough to locate the vehicle, saves the battery for the search
+            Phase::Recovery => 20,
+        }
+    }
+    /// beacon interval of this phase
+    pub fn interval(self, nominal: Duration) -> Duration {
+        nominal * 100 / self.rate_percent()
+    }
+    pub fn frame(self) -> [u8; 2] {
+        [PHASE_ID, self as u8]
+    }
+}
+
+/// set of phases a beacon is sent in
+#[derive(Clone, Copy)]
+pub struct PhaseSet(u8);
+
+impl PhaseSet {
+    pub const ALL: Self = Self(0x1F);
+    pub const fn of(phases: &[Phase]) -> Self {
+        let mut mask = 0;
+        let mut i = 0;
+        while i < phases.len() {
+            mask |= 1 << phases[i] as u8;
+            i += 1;
+        }
+        Self(mask)
+    }
+    pub fn contains(self, phase: Phase) -> bool {
+        self.0 & (1 << phase as u8) != 0
+    }
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(Phase::Pad as u8);
+
+fn persist(phase: Phase) {
+    let mut bytes = [0; PERSISTED_LEN];
+    bytes[8] = phase as u8;
+    let sum = checksum(&bytes[8..]);
+    bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    bytes[4..8].copy_from_slice(&sum.to_le_bytes());
+    // SAFETY: the area is only accessed from this module in thread mode, any content
+    // is a valid byte array and checked against the checksum before use
+    unsafe { ptr::write_volatile(&raw mut PERSISTED, MaybeUninit::new(bytes)) };
+}
+
+/// take over the phase of the previous run, the pad after a power cycle
+pub fn restore() {
+    // SAFETY: see persist
+    let bytes = unsafe { ptr::read_volatile(&raw const PERSISTED).assume_init() };
+    let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+    let sum = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let phase = (magic == MAGIC && sum == checksum(&bytes[8..]))
+        .then(|| Phase::from_u8(bytes[8]))
+        .flatten();
+    if let Some(phase) = phase {
+        info!("restored mission phase {}", phase);
+    }
+    let phase = phase.unwrap_or(Phase::Pad);
+    CURRENT.store(phase as u8, Ordering::Relaxed);
+    persist(phase);
+}
+
+pub fn current() -> Phase {
+    Phase::from_u8(CURRENT.load(Ordering::Relaxed)).unwrap_or(Phase::Pad)
+}
+
+/// switch the phase, returns true if it changed
+pub fn set(phase: Phase) -> bool {
+    persist(phase);
+    CURRENT.swap(phase as u8, Ordering::Relaxed) != phase as u8
+}
+
+/// reading of a phase sensor on the can bus
+#[derive(Format, Clone, Copy)]
+pub enum Sample {
+    /// static pressure at the local receive time
+    Pressure { time_us: u64, pa: f32 },
+    /// magnitude of the measured specific force
+    Accel { ms2: f32 },
+}
+
+impl Sample {
+    /// acceleration sample from the measured specific force in m/s²
+    pub fn accel(x: f32, y: f32, z: f32) -> Self {
+        Sample::Accel {
+            ms2: sqrtf(x * x + y * y + z * z),
+        }
+    }
+}
+
+/// phase sensor telemetry that was inserted into the beacons
+#[derive(Format, Clone, Copy)]
+pub enum Reading {
+    Pressure,
+    Accel,
+}
+
+// readings from the can receiver to the phase task with their receive time, dropped
+// while the task lags behind
+static READINGS: Channel<ThreadModeRawMutex, (Reading, u64), 8> = Channel::new();
+
+/// telemetry definitions the phase detector reads, the static pressure and the acceleration
+pub struct PhaseSensors {
+    pub pressure: &'static (dyn ChellDefinition + Sync),
+    pub accel: &'static (dyn ChellDefinition + Sync),
+}
+
+impl PhaseSensors {
+    /// queue a value the ingress inserted into the beacons if it is one of the sensors,
+    /// the phase task takes it from the sensor beacon, decoded through its definition
+    pub fn feed(&self, def: &dyn ChellDefinition, time_us: u64) {
+        let reading = if def.address() == self.pressure.address() {
+            Reading::Pressure
+        } else if def.address() == self.accel.address() {
+            Reading::Accel
+        } else {
+            return;
+        };
+        let _ = READINGS.try_send((reading, time_us));
+    }
+}
+
+/// next sensor reading for the detector and its receive time
+pub async fn next_reading() -> (Reading, u64) {
+    READINGS.receive().await
+}
+
+/// barometric height of a static pressure in the standard atmosphere
+fn pressure_altitude(pa: f32) -> f64 {
+    (44_330.0 * (1.0 - powf(pa / SEA_LEVEL_PA, 1.0 / 5.255))) as f64
+}
+
+/// Detects the flight phase transitions from the barometer and accelerometer telemetry.
+/// The launch and the burnout are taken from the acceleration, with the barometric
+/// altitude as fallback, the apogee and the landing from the barometric altitude.
+/// Only moves forward from the current phase, an uplinked phase is taken as the new
+/// starting point
+pub struct PhaseDetector {
+    pad_alt: Option<f64>,
+    last: Option<(u64, f64)>,
+    last_speed: Option<f64>,
+    confirmed: u8,
+    accel_confirmed: u8,
+}
+
+impl PhaseDetector {
+    pub const fn new() -> Self {
+        Self {
+            pad_alt: None,
+            last: None,
+            last_speed: None,
+            confirmed: 0,
+            accel_confirmed: 0,
+        }
+    }
+
+    /// count consecutive samples meeting a condition, true once confirmed
+    fn confirm(&mut self, condition: bool, samples: u8) -> bool {
+        self.confirmed = if condition {
+            self.confirmed.saturating_add(1)
+        } else {
+            0
+        };
+        self.confirmed >= samples
+    }
+
+    /// feed a sensor reading, returns the new phase on a transition
+    pub fn update(&mut self, sample: Sample) -> Option<Phase> {
+        match sample {
+            Sample::Pressure { time_us, pa } => {
+                self.update_altitude(time_us, pressure_altitude(pa))
+            }
+            Sample::Accel { ms2 } => self.update_accel(ms2),
+        }
+    }
+
+    fn update_accel(&mut self, ms2: f32) -> Option<Phase> {
+        let (next, condition) = match current() {
+            Phase::Pad => (Phase::Ascent, ms2 > LAUNCH_ACCEL_MS2),
+            Phase::Ascent => (Phase::Coast, ms2 < BURNOUT_ACCEL_MS2),
+            _ => return None,
+        };
+        self.accel_confirmed = if condition {
+            self.accel_confirmed.saturating_add(1)
+        } else {
+            0
+        };
+        if self.accel_confirmed < CONFIRM_SAMPLES {
+            return None;
+        }
+        self.accel_confirmed = 0;
+        self.confirmed = 0;
+        set(next).then_some(next)
+    }
+
+    fn update_altitude(&mut self, time_us: u64, alt: f64) -> Option<Phase> {
+        let pad_alt = *self.pad_alt.get_or_insert(alt);
+        let Some((last_us, last_alt)) = self.last else {
+            self.last = Some((time_us, alt));
+            return None;
+        };
+        self.last = Some((time_us, alt));
+        let speed = (alt - last_alt) * 1e6 / time_us.saturating_sub(last_us).max(1) as f64;
+        let slowing = self.last_speed.is_some_and(|last| speed < last);
+        self.last_speed = Some(speed);
+
+        let next = match current() {
+            Phase::Pad => Phase::Ascent,
+            Phase::Ascent => Phase::Coast,
+            Phase::Coast => Phase::Descent,
+            Phase::Descent => Phase::Recovery,
+            Phase::Recovery => return None,
+        };
+        let detected = match next {
+            Phase::Ascent => self.confirm(alt - pad_alt > LAUNCH_HEIGHT_M, 1),
+            // burnout, still climbing but decelerating
+            Phase::Coast => self.confirm(speed > 0.0 && slowing, CONFIRM_SAMPLES),
+            // apogee
+            Phase::Descent => self.confirm(speed < 0.0, CONFIRM_SAMPLES),
+            _ => self.confirm(speed.abs() < REST_SPEED_MS, REST_SAMPLES),
+        };
+        if !detected {
+            return None;
+        }
+        self.confirmed = 0;
+        self.accel_confirmed = 0;
+        set(next).then_some(next)
+    }
+}
@@ -0,0 +1,54 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embassy_time::Duration;
+use openlst_driver::link::PAYLOAD_HEADER_ID;
+
+/// source of beacon data on the tmtc board, beacons of the board itself are sent
+/// without payload header so the ground parses them as before
+pub struct Payload {
+    pub id: u8,
+    pub name: &'static str,
+    enabled: AtomicBool,
+    // share of the nominal beacon rate in percent
+    rate_percent: AtomicU8,
+}
+
+impl Payload {
+    pub const fn new(id: u8, name: &'static str) -> Self {
+        Self {
+            id,
+            name,
+            enabled: AtomicBool::new(true),
+            rate_percent: AtomicU8::new(100),
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+    /// allocate a share of the nominal rate, clamped to 1..=100 percent
+    pub fn set_rate_percent(&self, percent: u8) {
+        self.rate_percent
+            .store(percent.clamp(1, 100), Ordering::Relaxed);
+    }
+    /// nominal beacon interval stretched to the allocated rate
+    pub fn interval(&self, nominal: Duration) -> Duration {
+        nominal * 100 / self.rate_percent.load(Ordering::Relaxed) as u32
+    }
+    /// header in front of the beacon, None for the board itself
+    pub fn header(&self) -> Option<[u8; 2]> {
+        (self.id != BUS.id).then_some([PAYLOAD_HEADER_ID, self.id])
+    }
+}
+
+pub static BUS: Payload = Payload::new(0, "bus");
+pub static UPPER_SENSORS: Payload = Payload::new(1, "upper sensors");
+pub static LOWER_SENSORS: Payload = Payload::new(2, "lower sensors");
+
+static PAYLOADS: [&Payload; 3] = [&BUS, &UPPER_SENSORS, &LOWER_SENSORS];
+
+pub fn find(id: u8) -> Option<&'static Payload> {
+    PAYLOADS.iter().find(|p| p.id == id).copied()
+}
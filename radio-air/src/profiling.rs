@@ -0,0 +1,110 @@
+use core::{
+    future::{Future, poll_fn},
+    pin::pin,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use embassy_time::Instant;
+use openlst_driver::link::{PROFILED_TASKS, PROFILING_ID};
+
+// pattern written to the unused stack at startup, overwritten words mark the high water
+const STACK_PAINT: u32 = 0x5A5A_5A5A;
+// distance kept to the stack pointer while painting, covers the painting function itself
+const PAINT_MARGIN: usize = 256;
+
+unsafe extern "C" {
+    // provided by cortex-m-rt, the stack grows down from _stack_start towards __sheap
+    static _stack_start: u32;
+    static __sheap: u32;
+}
+
+/// poll time of a profiled task, accumulated until the next report
+pub struct TaskLoad {
+    pub name: &'static str,
+    busy_us: AtomicU32,
+}
+
+impl TaskLoad {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            busy_us: AtomicU32::new(0),
+        }
+    }
+    /// busy time since the last call
+    pub fn take_busy_us(&self) -> u32 {
+        self.busy_us.swap(0, Ordering::Relaxed)
+    }
+}
+
+pub static BEACON_TX: TaskLoad = TaskLoad::new(PROFILED_TASKS[0]);
+pub static CAN_RX: TaskLoad = TaskLoad::new(PROFILED_TASKS[1]);
+pub static CAN_TX: TaskLoad = TaskLoad::new(PROFILED_TASKS[2]);
+pub static LST_LINK: TaskLoad = TaskLoad::new(PROFILED_TASKS[3]);
+
+// in the order of link::PROFILED_TASKS, the ground names the loads by it
+pub static TASKS: [&TaskLoad; 4] = [&BEACON_TX, &CAN_RX, &CAN_TX, &LST_LINK];
+
+// profiling frame relayed to the ground with the lst telemetry, layout in link::PROFILING_ID
+pub const PROFILING_LEN: usize = 9 + PROFILED_TASKS.len() * 2;
+
+/// run a future and add the time spent in its polls to the task load
+pub async fn profiled<F: Future>(load: &TaskLoad, fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    poll_fn(|cx| {
+        let start = Instant::now();
+        let poll = fut.as_mut().poll(cx);
+        let busy = Instant::now().saturating_duration_since(start);
+        load.busy_us
+            .fetch_add(busy.as_micros() as u32, Ordering::Relaxed);
+        poll
+    })
+    .await
+}
+
+fn stack_bounds() -> (usize, usize) {
+    // SAFETY: only the addresses of the linker symbols are used
+    unsafe {
+        (
+            &raw const __sheap as usize,
+            &raw const _stack_start as usize,
+        )
+    }
+}
+
+/// fill the unused part of the stack with the paint pattern, called once at startup
+pub fn paint_stack() {
+    let (bottom, _) = stack_bounds();
+    let sp = cortex_m::register::msp::read() as usize;
+    let mut addr = bottom;
+    while addr + PAINT_MARGIN < sp {
+        // SAFETY: the region between the end of the static data and the
+        // current stack pointer is unused
+        unsafe { (addr as *mut u32).write_volatile(STACK_PAINT) };
+        addr += 4;
+    }
+}
+
+/// stack usage high water mark and the total stack size in bytes
+pub fn stack_usage() -> (usize, usize) {
+    let (bottom, top) = stack_bounds();
+    let mut addr = bottom;
+    // SAFETY: the region is inside the stack and word aligned
+    while addr < top && unsafe { (addr as *const u32).read_volatile() } == STACK_PAINT {
+        addr += 4;
+    }
+    (top - addr, top - bottom)
+}
+
+/// task loads in permille of the window and the stack usage for the housekeeping downlink
+pub fn frame(busy_permille: &[u16; PROFILED_TASKS.len()]) -> [u8; PROFILING_LEN] {
+    let (stack_used, stack_size) = stack_usage();
+    let mut frame = [0; PROFILING_LEN];
+    frame[0] = PROFILING_ID;
+    frame[1..5].copy_from_slice(&(stack_used as u32).to_le_bytes());
+    frame[5..9].copy_from_slice(&(stack_size as u32).to_le_bytes());
+    for (busy, out) in busy_permille.iter().zip(frame[9..].chunks_exact_mut(2)) {
+        out.copy_from_slice(&busy.to_le_bytes());
+    }
+    frame
+}
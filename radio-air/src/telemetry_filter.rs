@@ -0,0 +1,55 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embedded_can::Id;
+use heapless::Vec;
+
+// receivable beacons that can be filtered, by their index in the beacon ingress
+const MAX_BEACONS: usize = 8;
+// field can ids kept per beacon
+const MAX_FIELDS: usize = 16;
+// filter args: beacon index(1) flags(1) field can ids(2 LE each), without ids and
+// without the append flag the filter of the beacon is removed
+const FLAG_APPEND: u8 = 0x01;
+
+// fields let into each beacon, None lets all fields in
+static FILTERS: Mutex<ThreadModeRawMutex, RefCell<[Option<Vec<u16, MAX_FIELDS>>; MAX_BEACONS]>> =
+    Mutex::new(RefCell::new([const { None }; MAX_BEACONS]));
+
+/// true if the field with the can id is let into the beacon. The beacon ingress skips
+/// the others, they keep their last value in the beacon and still take up their bytes
+pub fn allows(beacon: usize, id: &Id) -> bool {
+    FILTERS.lock(|filters| match (filters.borrow().get(beacon), id) {
+        (Some(Some(fields)), Id::Standard(id)) => fields.contains(&id.as_raw()),
+        _ => true,
+    })
+}
+
+/// apply the args of a telemetry filter command, returns false if they are invalid
+pub fn apply(args: &[u8]) -> bool {
+    let [beacon, flags, ids @ ..] = args else {
+        return false;
+    };
+    if *beacon as usize >= MAX_BEACONS || ids.len() % 2 != 0 {
+        return false;
+    }
+    FILTERS.lock(|filters| {
+        let filter = &mut filters.borrow_mut()[*beacon as usize];
+        if flags & FLAG_APPEND == 0 {
+            *filter = None;
+            if ids.is_empty() {
+                return true;
+            }
+        }
+        let fields = filter.get_or_insert_with(Vec::new);
+        for id in ids
+            .chunks_exact(2)
+            .map(|id| u16::from_le_bytes([id[0], id[1]]))
+        {
+            if !fields.contains(&id) && fields.push(id).is_err() {
+                return false;
+            }
+        }
+        true
+    })
+}
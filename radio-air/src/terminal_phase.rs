@@ -0,0 +1,103 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use libm::{atan2, cos, sin, sqrt};
+use openlst_driver::link::FLIGHT_STATE_ID;
+
+use crate::mission_phase::Phase;
+
+// altitude above the first fix below which a descending vehicle enters the terminal phase
+const TERMINAL_ALTITUDE_M: f64 = 500.0;
+// consecutive descending altitude samples required, filters gps noise
+const DESCENT_SAMPLES: u8 = 3;
+
+// flight state frame, layout in link::FLIGHT_STATE_ID
+pub const STATE_LEN: usize = 18;
+
+// WGS84 ellipsoid
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_E2: f64 = 6.694_379_990_14e-3;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// true once the vehicle descended below the terminal altitude
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// enter the terminal phase without a detected descent, e.g. on a time-tagged command
+pub fn activate() {
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// highest priority state of the vehicle, downlinked while it may be lost on landing
+pub fn state_frame(phase: Phase, altitude_m: f64, ecef_m: [f32; 3]) -> [u8; STATE_LEN] {
+    let mut frame = [0; STATE_LEN];
+    frame[0] = FLIGHT_STATE_ID;
+    frame[1] = phase as u8;
+    frame[2..6].copy_from_slice(&(altitude_m as f32).to_le_bytes());
+    for (out, coordinate) in frame[6..].chunks_exact_mut(4).zip(ecef_m) {
+        out.copy_from_slice(&coordinate.to_le_bytes());
+    }
+    frame
+}
+
+/// height above the WGS84 ellipsoid of an ecef position in meters
+pub fn ecef_altitude(x: f64, y: f64, z: f64) -> f64 {
+    let p = sqrt(x * x + y * y);
+    // one bowring iteration is accurate to well below gps noise
+    let lat = atan2(z, p * (1.0 - WGS84_E2));
+    let n = WGS84_A / sqrt(1.0 - WGS84_E2 * sin(lat) * sin(lat));
+    if cos(lat).abs() > 1e-6 {
+        p / cos(lat) - n
+    } else {
+        z.abs() - n * (1.0 - WGS84_E2)
+    }
+}
+
+/// Detects the descent through the terminal altitude after the vehicle has climbed above it
+pub struct DescentDetector {
+    reference_alt: Option<f64>,
+    last_alt: Option<f64>,
+    descending: u8,
+    armed: bool,
+}
+
+impl DescentDetector {
+    pub const fn new() -> Self {
+        Self {
+            reference_alt: None,
+            last_alt: None,
+            descending: 0,
+            armed: false,
+        }
+    }
+
+    /// feed a new altitude sample, returns true when the terminal phase is entered
+    pub fn update(&mut self, alt: f64) -> bool {
+        if self.last_alt == Some(alt) {
+            return false;
+        }
+        let reference = *self.reference_alt.get_or_insert(alt);
+        if self.last_alt.is_some_and(|last| alt < last) {
+            self.descending = self.descending.saturating_add(1);
+        } else {
+            self.descending = 0;
+        }
+        self.last_alt = Some(alt);
+
+        let height = alt - reference;
+        // only arm after the ascent, gps noise on the pad must not trigger
+        if height > TERMINAL_ALTITUDE_M {
+            self.armed = true;
+        }
+        if self.armed
+            && !is_active()
+            && self.descending >= DESCENT_SAMPLES
+            && height < TERMINAL_ALTITUDE_M
+        {
+            ACTIVE.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,19 @@
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use embassy_time::Instant;
+use openlst_driver::link::TIME_CORRELATION_ID;
+
+// relayed to the ground with the lst telemetry, the ground pairs it with its utc time of reception
+pub const TIME_CORRELATION_LEN: usize = 11;
+
+static NEXT_SEQ: AtomicU16 = AtomicU16::new(0);
+
+/// correlation frame with the current local time, built right before it is handed to the lst
+pub fn frame() -> [u8; TIME_CORRELATION_LEN] {
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut frame = [0; TIME_CORRELATION_LEN];
+    frame[0] = TIME_CORRELATION_ID;
+    frame[1..3].copy_from_slice(&seq.to_le_bytes());
+    frame[3..11].copy_from_slice(&Instant::now().as_millis().to_le_bytes());
+    frame
+}
@@ -0,0 +1,151 @@
+use defmt::Format;
+use openlst_driver::{
+    link::{
+        self, LST_VERSION_ID, OP_BURST, OP_CRC_SELF_TEST, OP_HIGH_RATE, OP_PING, OP_REBOOT_LST,
+        TEST_BEACON_ID, UPLINK_ACK_ID, UPLINK_ID,
+    },
+    lst_receiver::LSTVersion,
+};
+
+// id(1) source hwid(2 LE) seq(2 LE) op(1) args
+const UPLINK_HEADER_LEN: usize = 6;
+// longest args of a command, the schedule command
+const MAX_ARGS_LEN: usize = 10;
+// number of recent (source hwid, seq) pairs remembered, covers the ground retransmissions
+const DEDUP_WINDOW: usize = 16;
+
+// crc self test beacon, layout in link::TEST_BEACON_ID
+pub const TEST_BEACON_LEN: usize = 22;
+// flight lst version, layout in link::LST_VERSION_ID
+const LST_VERSION_LEN: usize = 9;
+// walking bits, the crc is computed over all bytes before it
+const TEST_PATTERN: [u8; 16] = [
+    0x00, 0xFF, 0x55, 0xAA, 0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0xFE, 0xFD, 0xFB, 0xF7,
+];
+
+#[derive(Format, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum AckStatus {
+    Executed = link::ACK_EXECUTED,
+    /// retransmission of an already executed command, not executed again
+    Duplicate = link::ACK_DUPLICATE,
+    Failed = link::ACK_FAILED,
+    UnknownOp = link::ACK_UNKNOWN_OP,
+}
+
+#[derive(Format, Clone, Copy)]
+pub struct UplinkCommand {
+    pub source_hwid: u16,
+    pub seq: u16,
+    pub op: u8,
+    args_len: u8,
+    args: [u8; MAX_ARGS_LEN],
+}
+
+impl UplinkCommand {
+    /// command without args
+    pub fn new(source_hwid: u16, seq: u16, op: u8) -> Self {
+        Self {
+            source_hwid,
+            seq,
+            op,
+            args_len: 0,
+            args: [0; MAX_ARGS_LEN],
+        }
+    }
+    /// parse a relayed frame, None if it does not carry an uplinked command
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        if frame.len() < UPLINK_HEADER_LEN || frame[0] != UPLINK_ID {
+            return None;
+        }
+        let args = &frame[UPLINK_HEADER_LEN..];
+        if args.len() > MAX_ARGS_LEN {
+            return None;
+        }
+        let mut command = Self::new(
+            u16::from_le_bytes([frame[1], frame[2]]),
+            u16::from_le_bytes([frame[3], frame[4]]),
+            frame[5],
+        );
+        command.args[..args.len()].copy_from_slice(args);
+        command.args_len = args.len() as u8;
+        Some(command)
+    }
+    pub fn args(&self) -> &[u8] {
+        &self.args[..self.args_len as usize]
+    }
+    /// ops that run immediately and can be time-tagged
+    pub fn is_schedulable(op: u8) -> bool {
+        matches!(
+            op,
+            OP_PING | OP_REBOOT_LST | OP_CRC_SELF_TEST | OP_HIGH_RATE | OP_BURST
+        )
+    }
+    /// acknowledgement frame: id(1) source hwid(2 LE) seq(2 LE) status(1)
+    pub fn ack(&self, status: AckStatus) -> [u8; 6] {
+        let hwid = self.source_hwid.to_le_bytes();
+        let seq = self.seq.to_le_bytes();
+        [
+            UPLINK_ACK_ID,
+            hwid[0],
+            hwid[1],
+            seq[0],
+            seq[1],
+            status as u8,
+        ]
+    }
+}
+
+/// window of recently executed commands, keyed on source hwid and sequence number
+pub struct CommandDedup {
+    recent: [Option<(u16, u16)>; DEDUP_WINDOW],
+    pos: usize,
+}
+
+impl CommandDedup {
+    pub const fn new() -> Self {
+        Self {
+            recent: [None; DEDUP_WINDOW],
+            pos: 0,
+        }
+    }
+    /// check the command against the window and remember it if it is new
+    pub fn is_duplicate(&mut self, command: &UplinkCommand) -> bool {
+        let key = Some((command.source_hwid, command.seq));
+        if self.recent.contains(&key) {
+            return true;
+        }
+        self.recent[self.pos] = key;
+        self.pos = (self.pos + 1) % DEDUP_WINDOW;
+        false
+    }
+}
+
+/// test beacon for the ground side crc validation, with a deliberately wrong crc if not valid
+pub fn test_beacon(
+    seq: u16,
+    valid: bool,
+    crc_func: &mut impl FnMut(&[u8]) -> u16,
+) -> [u8; TEST_BEACON_LEN] {
+    let mut frame = [0; TEST_BEACON_LEN];
+    frame[0] = TEST_BEACON_ID;
+    frame[1..3].copy_from_slice(&seq.to_le_bytes());
+    frame[3] = valid as u8;
+    frame[4..20].copy_from_slice(&TEST_PATTERN);
+    let mut crc = crc_func(&frame[..20]);
+    if !valid {
+        crc ^= 0xFFFF;
+    }
+    frame[20..].copy_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+/// version of the flight lst, answering the version request with the sequence number
+pub fn version_frame(seq: u16, version: &LSTVersion) -> [u8; LST_VERSION_LEN] {
+    let mut frame = [0; LST_VERSION_LEN];
+    frame[0] = LST_VERSION_ID;
+    frame[1..3].copy_from_slice(&seq.to_le_bytes());
+    frame[3..5].copy_from_slice(&version.hwid.to_le_bytes());
+    frame[5..9].copy_from_slice(&version.git_rev.to_le_bytes());
+    frame
+}
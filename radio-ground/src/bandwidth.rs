@@ -0,0 +1,166 @@
+use alloc::{format, vec::Vec};
+use core::cell::RefCell;
+
+use defmt::error;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish, timesync};
+
+// vehicle whose beacons this ground station receives
+const VEHICLE: &str = if cfg!(feature = "primary") {
+    "primary"
+} else {
+    "secondary"
+};
+
+// running totals since startup, e.g. gst.status.bandwidth.primary
+const STATUS_SUBJECT: &str = "gst.status.bandwidth";
+const STATUS_INTERVAL: Duration = Duration::from_secs(60);
+// rollups of the completed period, e.g. gst.bandwidth.primary.hourly
+const ROLLUP_SUBJECT: &str = "gst.bandwidth";
+const HOURLY: Duration = Duration::from_secs(60 * 60);
+const DAILY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize, Clone, Copy, Default)]
+pub struct Usage {
+    pub frames: u32,
+    pub received_bytes: u64,
+    pub published_bytes: u64,
+}
+
+#[derive(Serialize, Clone, Copy)]
+pub struct BeaconUsage {
+    pub beacon: &'static str,
+    pub usage: Usage,
+}
+
+#[derive(Serialize)]
+pub struct BandwidthReport<'a> {
+    pub vehicle: &'static str,
+    /// unix time at the end of the reported period
+    pub timestamp: u64,
+    pub duration_s: u64,
+    pub beacons: &'a [BeaconUsage],
+}
+
+struct Window {
+    opened: Instant,
+    beacons: Vec<BeaconUsage>,
+}
+
+impl Window {
+    const fn new() -> Self {
+        Self {
+            opened: Instant::from_ticks(0),
+            beacons: Vec::new(),
+        }
+    }
+    fn add(&mut self, beacon: &'static str, received: usize, published: usize) {
+        let index = match self.beacons.iter().position(|b| b.beacon == beacon) {
+            Some(index) => index,
+            None => {
+                self.beacons.push(BeaconUsage {
+                    beacon,
+                    usage: Usage::default(),
+                });
+                self.beacons.len() - 1
+            }
+        };
+        let usage = &mut self.beacons[index].usage;
+        usage.frames += 1;
+        usage.received_bytes += received as u64;
+        usage.published_bytes += published as u64;
+    }
+    /// close the window if it is older than the period, returning its usage
+    fn roll(&mut self, period: Duration, now: Instant) -> Option<(Duration, Vec<BeaconUsage>)> {
+        let elapsed = now.saturating_duration_since(self.opened);
+        if elapsed < period {
+            return None;
+        }
+        self.opened = now;
+        Some((elapsed, core::mem::take(&mut self.beacons)))
+    }
+}
+
+struct Accounting {
+    hour: Window,
+    day: Window,
+    total: Window,
+    last_status: Instant,
+}
+
+static ACCOUNTING: Mutex<ThreadModeRawMutex, RefCell<Accounting>> =
+    Mutex::new(RefCell::new(Accounting {
+        hour: Window::new(),
+        day: Window::new(),
+        total: Window::new(),
+        last_status: Instant::from_ticks(0),
+    }));
+
+/// account a received beacon frame and the bytes published from it
+pub fn record(beacon: &'static str, received: usize, published: usize) {
+    ACCOUNTING.lock(|accounting| {
+        let mut accounting = accounting.borrow_mut();
+        accounting.hour.add(beacon, received, published);
+        accounting.day.add(beacon, received, published);
+        accounting.total.add(beacon, received, published);
+    });
+}
+
+async fn publish_report(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    subject: &str,
+    timestamp: u64,
+    duration: Duration,
+    beacons: &[BeaconUsage],
+) {
+    let report = BandwidthReport {
+        vehicle: VEHICLE,
+        timestamp,
+        duration_s: duration.as_secs(),
+        beacons,
+    };
+    match cbor_serializer(&report) {
+        Ok(serialized) => nats_sender.publish_gated(subject, serialized).await,
+        Err(_) => error!("could not serialize bandwidth report"),
+    }
+}
+
+/// publish the running totals and the rollups of all completed periods
+pub async fn publish_due(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    unix_time_offset_us: i64,
+) {
+    let now = Instant::now();
+    let timestamp = timesync::current_unix_time_micros(unix_time_offset_us);
+    let (hourly, daily, status) = ACCOUNTING.lock(|accounting| {
+        let mut accounting = accounting.borrow_mut();
+        let status = now.saturating_duration_since(accounting.last_status) >= STATUS_INTERVAL;
+        if status {
+            accounting.last_status = now;
+        }
+        (
+            accounting.hour.roll(HOURLY, now),
+            accounting.day.roll(DAILY, now),
+            status.then(|| {
+                let total = &accounting.total;
+                (
+                    now.saturating_duration_since(total.opened),
+                    total.beacons.clone(),
+                )
+            }),
+        )
+    });
+    for (period, rollup) in [("hourly", hourly), ("daily", daily)] {
+        if let Some((duration, beacons)) = rollup {
+            let subject = format!("{}.{}.{}", ROLLUP_SUBJECT, VEHICLE, period);
+            publish_report(nats_sender, &subject, timestamp, duration, &beacons).await;
+        }
+    }
+    if let Some((duration, beacons)) = status {
+        let subject = format!("{}.{}", STATUS_SUBJECT, VEHICLE);
+        publish_report(nats_sender, &subject, timestamp, duration, &beacons).await;
+    }
+}
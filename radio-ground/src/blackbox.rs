@@ -0,0 +1,55 @@
+use alloc::vec::Vec;
+
+use defmt::{error, info};
+use openlst_driver::link::BLACKBOX_DUMP_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// id(1) timestamp us(8) reason(1) frame len(2)
+const HEADER_LEN: usize = 12;
+const REASONS: [&str; 3] = ["unknown destination", "unknown command", "malformed"];
+
+pub const BLACKBOX_SUBJECT: &str = "tm.blackbox";
+
+/// lst frame the vehicle rejected, downlinked from its blackbox in the terminal phase
+#[derive(Serialize)]
+pub struct BlackboxRecord {
+    /// local time of the vehicle
+    pub timestamp_us: u64,
+    pub reason: &'static str,
+    /// length of the original frame, the stored bytes may be truncated
+    pub len: u16,
+    pub data: Vec<u8>,
+}
+
+/// record of a blackbox dump frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<BlackboxRecord> {
+    if frame.len() < HEADER_LEN || frame[0] != BLACKBOX_DUMP_ID {
+        return None;
+    }
+    Some(BlackboxRecord {
+        timestamp_us: u64::from_le_bytes(frame[1..9].try_into().unwrap()),
+        reason: REASONS.get(frame[9] as usize).copied().unwrap_or("unknown"),
+        len: u16::from_le_bytes([frame[10], frame[11]]),
+        data: frame[HEADER_LEN..].to_vec(),
+    })
+}
+
+pub async fn publish_record(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    record: BlackboxRecord,
+) {
+    info!(
+        "blackbox record at {} us: {}",
+        record.timestamp_us, record.reason
+    );
+    match cbor_serializer(&record) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(BLACKBOX_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize blackbox record"),
+    }
+}
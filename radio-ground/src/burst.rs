@@ -0,0 +1,43 @@
+use core::cell::Cell;
+
+use defmt::{error, info};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+use openlst_driver::link::BURST_UPLINK_TRIGGER;
+use serde::Serialize;
+
+use crate::{cbor_serializer, mission_phase, publisher::GatedPublish};
+
+// a marked frame after this gap belongs to a new burst
+const BURST_GAP: Duration = Duration::from_secs(2);
+
+pub const BURST_SUBJECT: &str = "tm.burst";
+
+/// start of a burst capture, the following marked beacons are sampled at the burst rate
+#[derive(Serialize)]
+pub struct BurstStart {
+    pub trigger: &'static str,
+}
+
+static LAST_MARKED: Mutex<ThreadModeRawMutex, Cell<Option<Instant>>> = Mutex::new(Cell::new(None));
+
+/// publish the start of a burst on its first marked frame
+pub async fn publish_marked(nats_sender: &mut embassy_nats::Client<'static>, trigger: u8) {
+    let now = Instant::now();
+    let new_burst = LAST_MARKED.lock(|last| {
+        let previous = last.replace(Some(now));
+        previous.is_none_or(|previous| now.saturating_duration_since(previous) > BURST_GAP)
+    });
+    if !new_burst {
+        return;
+    }
+    let trigger = match trigger {
+        BURST_UPLINK_TRIGGER => "uplink",
+        phase => mission_phase::name(phase).unwrap_or("unknown"),
+    };
+    info!("burst capture started by {}", trigger);
+    match cbor_serializer(&BurstStart { trigger }) {
+        Ok(serialized) => nats_sender.publish_gated(BURST_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize burst start"),
+    }
+}
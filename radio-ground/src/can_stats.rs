@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+use defmt::{error, warn};
+use openlst_driver::link::{CAN_STATS_BUSIEST, CAN_STATS_ID};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// can receive statistics of radio-air, layout in link::CAN_STATS_ID
+const CAN_STATS_LEN: usize = 11 + CAN_STATS_BUSIEST * 6;
+
+pub const CAN_STATS_SUBJECT: &str = "tm.can_stats";
+
+#[derive(Serialize)]
+pub struct IdRate {
+    pub id: u32,
+    pub frames_per_s: u16,
+}
+
+/// can bus load seen by the vehicle over its report window
+#[derive(Serialize)]
+pub struct CanStats {
+    pub frames_per_s: u16,
+    /// frames of ids beyond the tracked ones
+    pub untracked: u16,
+    /// frames that waited too long in the rx buffer
+    pub backlogged: u16,
+    pub max_latency_us: u32,
+    pub busiest: Vec<IdRate>,
+}
+
+/// can statistics in a can stats frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<CanStats> {
+    if frame.len() != CAN_STATS_LEN || frame[0] != CAN_STATS_ID {
+        return None;
+    }
+    let busiest = frame[11..]
+        .chunks_exact(6)
+        .map(|entry| IdRate {
+            id: u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+            frames_per_s: u16::from_le_bytes([entry[4], entry[5]]),
+        })
+        .filter(|rate| rate.frames_per_s > 0)
+        .collect();
+    Some(CanStats {
+        frames_per_s: u16::from_le_bytes([frame[1], frame[2]]),
+        untracked: u16::from_le_bytes([frame[3], frame[4]]),
+        backlogged: u16::from_le_bytes([frame[5], frame[6]]),
+        max_latency_us: u32::from_le_bytes(frame[7..11].try_into().unwrap()),
+        busiest,
+    })
+}
+
+pub async fn publish_can_stats(nats_sender: &mut embassy_nats::Client<'static>, stats: CanStats) {
+    if stats.backlogged > 0 {
+        warn!("vehicle can rx backlog: {} frames", stats.backlogged);
+    }
+    match cbor_serializer(&stats) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(CAN_STATS_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize can stats"),
+    }
+}
@@ -0,0 +1,195 @@
+use alloc::vec::Vec;
+
+use defmt::{error, info, warn};
+use embassy_stm32::{crc::Crc, mode::Async, usart::UartTx};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::{Duration, Instant};
+use openlst_driver::{
+    link,
+    lst_receiver::LSTMessage,
+    lst_sender::{LSTCmd, LSTSender},
+};
+use serde::Serialize;
+
+use crate::{cbor_serializer, crc_selftest, lst_inbox::LstInbox, publisher::GatedPublish, uplink};
+
+// pre-flight checkout, started by publishing on the subject, the report follows on the report subject
+pub const CHECKOUT_SUBJECT: &str = "gst.radio.checkout";
+// outside of gst.radio.> so the report does not come back on the control subscription
+const REPORT_SUBJECT: &str = "gst.checkout.report";
+// time each step gets for its reply
+const STEP_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize)]
+pub struct StepResult {
+    pub step: &'static str,
+    pub passed: bool,
+    /// round trip time of the step in ms, or the lst uptime for the radio self test
+    pub value: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct CheckoutReport {
+    pub passed: bool,
+    pub steps: Vec<StepResult>,
+}
+
+type Lst = Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>;
+
+/// the local lst answers a telemetry request
+async fn radio_self_test(lst: &Lst, lst_rx: &mut LstInbox) -> StepResult {
+    let sent = lst.lock().await.cmd(LSTCmd::GetTelem).await.is_ok();
+    let uptime = if sent {
+        lst_rx
+            .wait_for(Instant::now() + STEP_TIMEOUT, |msg| match msg {
+                LSTMessage::Telem(tm) => Some(tm.uptime as u64),
+                _ => None,
+            })
+            .await
+    } else {
+        None
+    };
+    StepResult {
+        step: "radio self test",
+        passed: uptime.is_some(),
+        value: uptime,
+    }
+}
+
+/// round trip of a version request to the flight lst, relayed back by radio-air
+async fn echo_rtt(lst: &Lst, lst_rx: &mut LstInbox, local_hwid: u16) -> StepResult {
+    let start = Instant::now();
+    let rtt = match send_uplink(lst, local_hwid, link::OP_LST_VERSION).await {
+        Some(seq) => {
+            lst_rx
+                .wait_for(start + STEP_TIMEOUT, |msg| match msg {
+                    LSTMessage::Relay(frame) => uplink::parse_version(frame)
+                        .filter(|(version_seq, _)| *version_seq == seq)
+                        .map(|_| start.elapsed().as_millis()),
+                    _ => None,
+                })
+                .await
+        }
+        None => None,
+    };
+    StepResult {
+        step: "echo rtt",
+        passed: rtt.is_some(),
+        value: rtt,
+    }
+}
+
+/// relay an uplink command to the vehicle, returns its sequence number
+async fn send_uplink(lst: &Lst, local_hwid: u16, op: u8) -> Option<u16> {
+    let (frame, seq) = uplink::command_frame(local_hwid, op);
+    match uplink::relay(&mut *lst.lock().await, &frame, seq).await {
+        Ok(()) => Some(seq),
+        Err(e) => {
+            error!("checkout: could not send uplink command: {}", e);
+            None
+        }
+    }
+}
+
+/// the vehicle executes a ping and acknowledges it
+async fn command_echo(lst: &Lst, lst_rx: &mut LstInbox, local_hwid: u16) -> StepResult {
+    let start = Instant::now();
+    let rtt = match send_uplink(lst, local_hwid, link::OP_PING).await {
+        Some(seq) => {
+            lst_rx
+                .wait_for(start + STEP_TIMEOUT, |msg| match msg {
+                    LSTMessage::Relay(frame) => uplink::parse_ack(frame)
+                        .filter(|ack| ack.seq == seq && ack.status == link::ACK_EXECUTED)
+                        .map(|_| start.elapsed().as_millis()),
+                    _ => None,
+                })
+                .await
+        }
+        None => None,
+    };
+    StepResult {
+        step: "command echo",
+        passed: rtt.is_some(),
+        value: rtt,
+    }
+}
+
+/// the vehicle sends a test beacon with a valid and one with a corrupted crc,
+/// both have to be classified as expected
+async fn crc_validation(
+    lst: &Lst,
+    lst_rx: &mut LstInbox,
+    crc: &mut Crc<'static>,
+    local_hwid: u16,
+) -> StepResult {
+    let mut passed = false;
+    if let Some(seq) = send_uplink(lst, local_hwid, link::OP_CRC_SELF_TEST).await {
+        let mut results = (None, None);
+        let deadline = Instant::now() + STEP_TIMEOUT;
+        passed = lst_rx
+            .wait_for(deadline, |msg| {
+                let LSTMessage::Relay(frame) = msg else {
+                    return None;
+                };
+                if !crc_selftest::is_test_beacon(frame) {
+                    return None;
+                }
+                crc.reset();
+                let result = crc_selftest::evaluate(frame, &mut |bytes: &[u8]| {
+                    crc.feed_bytes(bytes);
+                    crc.read() as u16
+                });
+                if result.seq != seq {
+                    return None;
+                }
+                if result.expected_valid {
+                    results.0 = Some(result.passed);
+                } else {
+                    results.1 = Some(result.passed);
+                }
+                match results {
+                    (Some(good), Some(bad)) => Some(good && bad),
+                    _ => None,
+                }
+            })
+            .await
+            .unwrap_or(false);
+    }
+    StepResult {
+        step: "crc validation",
+        passed,
+        value: None,
+    }
+}
+
+/// run the checkout sequence and publish the report
+pub async fn run_checkout(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Lst,
+    lst_rx: &mut LstInbox,
+    crc: &mut Crc<'static>,
+    local_hwid: u16,
+) {
+    info!("starting pre-flight checkout");
+    let steps = alloc::vec![
+        radio_self_test(lst, lst_rx).await,
+        echo_rtt(lst, lst_rx, local_hwid).await,
+        command_echo(lst, lst_rx, local_hwid).await,
+        crc_validation(lst, lst_rx, crc, local_hwid).await,
+    ];
+    for step in &steps {
+        if step.passed {
+            info!("checkout {}: passed", step.step);
+        } else {
+            warn!("checkout {}: failed", step.step);
+        }
+    }
+    let report = CheckoutReport {
+        passed: steps.iter().all(|s| s.passed),
+        steps,
+    };
+    match cbor_serializer(&report) {
+        Ok(serialized) => nats_sender.publish_gated(REPORT_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize checkout report"),
+    }
+}
@@ -0,0 +1,57 @@
+use alloc::vec::Vec;
+
+use defmt::{error, info};
+use openlst_driver::link::SCHEDULE_LIST_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// source hwid(2 LE) seq(2 LE) op(1) due utc ms(8 LE)
+const ENTRY_LEN: usize = 13;
+
+pub const SCHEDULE_SUBJECT: &str = "tm.schedule";
+
+/// time-tagged command pending on the vehicle
+#[derive(Serialize)]
+pub struct ScheduledCommand {
+    pub source_hwid: u16,
+    /// sequence number of the uplink command that scheduled it
+    pub seq: u16,
+    pub op: u8,
+    pub due_utc_ms: u64,
+}
+
+/// pending commands of a schedule listing, None for other frames
+pub fn parse(frame: &[u8]) -> Option<Vec<ScheduledCommand>> {
+    let [SCHEDULE_LIST_ID, count, entries @ ..] = frame else {
+        return None;
+    };
+    if entries.len() != *count as usize * ENTRY_LEN {
+        return None;
+    }
+    let commands = entries
+        .chunks_exact(ENTRY_LEN)
+        .map(|entry| ScheduledCommand {
+            source_hwid: u16::from_le_bytes([entry[0], entry[1]]),
+            seq: u16::from_le_bytes([entry[2], entry[3]]),
+            op: entry[4],
+            due_utc_ms: u64::from_le_bytes(entry[5..13].try_into().unwrap()),
+        })
+        .collect();
+    Some(commands)
+}
+
+pub async fn publish_schedule(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    commands: Vec<ScheduledCommand>,
+) {
+    info!("{} scheduled commands pending", commands.len());
+    match cbor_serializer(&commands) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(SCHEDULE_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize command schedule"),
+    }
+}
@@ -0,0 +1,42 @@
+use defmt::warn;
+
+use crate::publisher;
+
+// runtime configuration, e.g. gst.config.publish.disable with the subject prefix as payload
+// or gst.config.route.add with "<subject> <alias>" as payload.
+// gst.config.publish.fields.enable publishes every value on its own subject instead of
+// one cbor map per beacon on tm.<beacon>
+pub const CONFIG_SUBJECT: &str = "gst.config.>";
+const CONFIG_PREFIX: &str = "gst.config.";
+
+/// apply a configuration message received on the config subject
+pub fn handle_config(subject: &str, payload: &[u8]) {
+    let Some(key) = subject.strip_prefix(CONFIG_PREFIX) else {
+        return;
+    };
+    match key {
+        "publish.enable" | "publish.disable" => {
+            let Ok(prefix) = core::str::from_utf8(payload) else {
+                warn!("publish gate prefix is not valid utf8");
+                return;
+            };
+            publisher::set_enabled(prefix.trim(), key == "publish.enable");
+        }
+        "publish.fields.enable" | "publish.fields.disable" => {
+            publisher::set_per_field(key == "publish.fields.enable");
+        }
+        "route.add" | "route.remove" => {
+            let Ok(route) = core::str::from_utf8(payload) else {
+                warn!("route is not valid utf8");
+                return;
+            };
+            let mut parts = route.split_whitespace();
+            match (key, parts.next(), parts.next()) {
+                ("route.add", Some(subject), Some(alias)) => publisher::add_route(subject, alias),
+                ("route.remove", Some(subject), None) => publisher::remove_routes(subject),
+                _ => warn!("invalid route: {}", route),
+            }
+        }
+        _ => warn!("unknown config subject: {}", subject),
+    }
+}
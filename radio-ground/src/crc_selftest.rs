@@ -0,0 +1,67 @@
+use defmt::{error, info, warn};
+use openlst_driver::link::TEST_BEACON_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// crc self test beacon sent by radio-air: id(1) seq(2 LE) valid(1) pattern(16) crc(2 LE)
+const TEST_BEACON_LEN: usize = 22;
+const CRC_OFFSET: usize = TEST_BEACON_LEN - 2;
+
+pub const SELFTEST_SUBJECT: &str = "gst.selftest.crc";
+
+#[derive(Serialize)]
+pub struct CrcCheckResult {
+    pub seq: u16,
+    /// the air side sent this beacon with a valid crc
+    pub expected_valid: bool,
+    pub crc_valid: bool,
+    pub passed: bool,
+}
+
+pub fn is_test_beacon(data: &[u8]) -> bool {
+    data.len() == TEST_BEACON_LEN && data[0] == TEST_BEACON_ID
+}
+
+/// validate the crc of a test beacon against the expectation sent along
+pub fn evaluate(data: &[u8], crc_func: &mut impl FnMut(&[u8]) -> u16) -> CrcCheckResult {
+    let received = u16::from_le_bytes([data[CRC_OFFSET], data[CRC_OFFSET + 1]]);
+    let crc_valid = crc_func(&data[..CRC_OFFSET]) == received;
+    let expected_valid = data[3] != 0;
+    let result = CrcCheckResult {
+        seq: u16::from_le_bytes([data[1], data[2]]),
+        expected_valid,
+        crc_valid,
+        passed: crc_valid == expected_valid,
+    };
+    if !crc_valid {
+        // same alarm path as a beacon with a bad crc
+        error!("crc test beacon {} with bad crc received", result.seq);
+    }
+    if result.passed {
+        info!("crc self test {} passed", result.seq);
+    } else {
+        warn!(
+            "crc self test {} failed, crc valid: {}",
+            result.seq, crc_valid
+        );
+    }
+    result
+}
+
+/// validate the crc of a test beacon and publish whether the outcome matched the expectation
+pub async fn check_test_beacon(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    data: &[u8],
+    crc_func: &mut impl FnMut(&[u8]) -> u16,
+) {
+    let result = evaluate(data, crc_func);
+    match cbor_serializer(&result) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(SELFTEST_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize crc self test result"),
+    }
+}
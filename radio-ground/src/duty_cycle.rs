@@ -0,0 +1,52 @@
+use defmt::{error, warn};
+use openlst_driver::link::DUTY_CYCLE_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// airtime budget of radio-air relayed with its lst telemetry:
+// id(1) used airtime ms(4 LE) budget ms(4 LE) deferred beacons(2 LE)
+const DUTY_CYCLE_LEN: usize = 11;
+
+pub const DUTY_CYCLE_SUBJECT: &str = "tm.duty_cycle";
+
+/// transmit airtime of the vehicle over the regulatory window
+#[derive(Serialize)]
+pub struct DutyCycle {
+    pub used_ms: u32,
+    pub budget_ms: u32,
+    /// non critical beacons held back since startup
+    pub deferred: u16,
+}
+
+/// budget usage in a duty cycle frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<DutyCycle> {
+    if frame.len() != DUTY_CYCLE_LEN || frame[0] != DUTY_CYCLE_ID {
+        return None;
+    }
+    Some(DutyCycle {
+        used_ms: u32::from_le_bytes(frame[1..5].try_into().unwrap()),
+        budget_ms: u32::from_le_bytes(frame[5..9].try_into().unwrap()),
+        deferred: u16::from_le_bytes([frame[9], frame[10]]),
+    })
+}
+
+pub async fn publish_duty_cycle(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    duty_cycle: DutyCycle,
+) {
+    if duty_cycle.used_ms >= duty_cycle.budget_ms {
+        warn!(
+            "vehicle duty cycle budget used up, {} beacons deferred",
+            duty_cycle.deferred
+        );
+    }
+    match cbor_serializer(&duty_cycle) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(DUTY_CYCLE_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize duty cycle"),
+    }
+}
@@ -0,0 +1,145 @@
+use defmt::{error, info, warn};
+use embassy_time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// state transitions for the ops consoles, events.<source>.<transition>
+const FIRST_FRAME_SUBJECT: &str = "events.link.first_frame";
+const LINK_LOST_SUBJECT: &str = "events.link.lost";
+const LINK_REGAINED_SUBJECT: &str = "events.link.regained";
+const REMOTE_REBOOT_SUBJECT: &str = "events.radio.remote_reboot";
+const LOW_BATTERY_SUBJECT: &str = "events.eps.low_battery";
+// no frame of the vehicle for this long counts as a lost link
+const LINK_LOST_AFTER: Duration = Duration::from_secs(10);
+// bat1 voltage of the eps beacon, the alert is raised again once it recovered by the hysteresis
+const LOW_BATTERY_V: f32 = 7.0;
+const BATTERY_HYSTERESIS_V: f32 = 0.2;
+
+const INFO: &str = "info";
+const WARNING: &str = "warning";
+const CRITICAL: &str = "critical";
+
+#[derive(Serialize)]
+pub struct Event {
+    pub severity: &'static str,
+    /// utc of the station in us
+    pub timestamp: u64,
+    /// value that triggered the event, e.g. the seconds without frames or the voltage
+    pub value: Option<f32>,
+}
+
+/// Tracks the link and vehicle state over the received frames and publishes
+/// the transitions as events
+pub struct EventEmitter {
+    last_frame: Option<Instant>,
+    link_lost: bool,
+    remote_uptime: Option<u32>,
+    low_battery: bool,
+}
+
+impl EventEmitter {
+    pub const fn new() -> Self {
+        Self {
+            last_frame: None,
+            link_lost: false,
+            remote_uptime: None,
+            low_battery: false,
+        }
+    }
+
+    async fn publish(
+        nats_sender: &mut embassy_nats::Client<'static>,
+        subject: &str,
+        severity: &'static str,
+        timestamp: u64,
+        value: Option<f32>,
+    ) {
+        let event = Event {
+            severity,
+            timestamp,
+            value,
+        };
+        match cbor_serializer(&event) {
+            Ok(serialized) => nats_sender.publish_gated(subject, serialized).await,
+            Err(_) => error!("could not serialize event"),
+        }
+    }
+
+    /// a frame of the vehicle was received
+    pub async fn frame_received(
+        &mut self,
+        nats_sender: &mut embassy_nats::Client<'static>,
+        utc_us: u64,
+    ) {
+        let previous = self.last_frame.replace(Instant::now());
+        if previous.is_none() {
+            info!("first frame of the vehicle received");
+            Self::publish(nats_sender, FIRST_FRAME_SUBJECT, INFO, utc_us, None).await;
+        } else if self.link_lost {
+            self.link_lost = false;
+            let lost_s = previous.map(|at| at.elapsed().as_millis() as f32 / 1000.0);
+            info!("link to the vehicle regained");
+            Self::publish(nats_sender, LINK_REGAINED_SUBJECT, INFO, utc_us, lost_s).await;
+        }
+    }
+
+    /// check for a lost link, called periodically
+    pub async fn poll(&mut self, nats_sender: &mut embassy_nats::Client<'static>, utc_us: u64) {
+        let Some(last_frame) = self.last_frame else {
+            return;
+        };
+        if self.link_lost || last_frame.elapsed() <= LINK_LOST_AFTER {
+            return;
+        }
+        self.link_lost = true;
+        let lost_s = last_frame.elapsed().as_millis() as f32 / 1000.0;
+        warn!("link to the vehicle lost");
+        Self::publish(
+            nats_sender,
+            LINK_LOST_SUBJECT,
+            WARNING,
+            utc_us,
+            Some(lost_s),
+        )
+        .await;
+    }
+
+    /// lst uptime of the vehicle from its lst beacon, a decrease is a reboot
+    pub async fn remote_uptime(
+        &mut self,
+        nats_sender: &mut embassy_nats::Client<'static>,
+        uptime: u32,
+        utc_us: u64,
+    ) {
+        let previous = self.remote_uptime.replace(uptime);
+        if previous.is_some_and(|previous| uptime < previous) {
+            warn!("remote lst rebooted");
+            let uptime = Some(uptime as f32);
+            Self::publish(nats_sender, REMOTE_REBOOT_SUBJECT, WARNING, utc_us, uptime).await;
+        }
+    }
+
+    /// battery voltage from the eps beacon
+    pub async fn battery(
+        &mut self,
+        nats_sender: &mut embassy_nats::Client<'static>,
+        volts: f32,
+        utc_us: u64,
+    ) {
+        if !self.low_battery && volts < LOW_BATTERY_V {
+            self.low_battery = true;
+            warn!("vehicle battery low: {} V", volts);
+            Self::publish(
+                nats_sender,
+                LOW_BATTERY_SUBJECT,
+                CRITICAL,
+                utc_us,
+                Some(volts),
+            )
+            .await;
+        } else if self.low_battery && volts > LOW_BATTERY_V + BATTERY_HYSTERESIS_V {
+            self.low_battery = false;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use defmt::{error, warn};
+use openlst_driver::link::FLIGHT_STATE_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, mission_phase, publisher::GatedPublish};
+
+// id(1) phase(1) altitude(4) ecef x y z(4 each)
+const STATE_LEN: usize = 18;
+
+pub const FLIGHT_STATE_SUBJECT: &str = "tm.flight_state";
+
+/// state of the vehicle sent in the terminal phase, while it may be lost on landing
+#[derive(Serialize)]
+pub struct FlightState {
+    pub phase: &'static str,
+    pub altitude_m: f32,
+    pub ecef_m: [f32; 3],
+}
+
+fn f32_at(frame: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(frame[offset..offset + 4].try_into().unwrap())
+}
+
+/// flight state of a state frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<FlightState> {
+    if frame.len() != STATE_LEN || frame[0] != FLIGHT_STATE_ID {
+        return None;
+    }
+    Some(FlightState {
+        phase: mission_phase::name(frame[1]).unwrap_or("unknown"),
+        altitude_m: f32_at(frame, 2),
+        ecef_m: [f32_at(frame, 6), f32_at(frame, 10), f32_at(frame, 14)],
+    })
+}
+
+pub async fn publish_state(nats_sender: &mut embassy_nats::Client<'static>, state: FlightState) {
+    warn!("terminal phase: {} at {} m", state.phase, state.altitude_m);
+    match cbor_serializer(&state) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(FLIGHT_STATE_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize flight state"),
+    }
+}
@@ -0,0 +1,152 @@
+use defmt::{Format, error, info, warn};
+use embassy_stm32::{
+    gpio::{Level, Output},
+    mode::Async,
+    usart::UartTx,
+};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::Duration;
+use openlst_driver::lst_sender::LSTSender;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish, uplink};
+
+// station equipment switched over nats: gst.gpio.set with "<output> on|off" as payload,
+// gst.gpio.get only publishes the state
+pub const GPIO_SUBJECT: &str = "gst.gpio.>";
+const GPIO_PREFIX: &str = "gst.gpio.";
+// outside of gst.gpio.> so the state does not come back on the subscription
+const STATE_SUBJECT: &str = "gst.station.gpio";
+// the lst may still transmit for this long after an uplink was handed to it
+const TX_HOLDOFF: Duration = Duration::from_millis(500);
+
+type Lst = Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>;
+
+#[derive(Format, Clone, Copy, PartialEq)]
+pub enum Equipment {
+    AntennaRelay = 0,
+    Lna = 1,
+    Pa = 2,
+    Strobe = 3,
+}
+
+impl Equipment {
+    const ALL: [Self; 4] = [
+        Equipment::AntennaRelay,
+        Equipment::Lna,
+        Equipment::Pa,
+        Equipment::Strobe,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Equipment::AntennaRelay => "antenna_relay",
+            Equipment::Lna => "lna",
+            Equipment::Pa => "pa",
+            Equipment::Strobe => "strobe",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.name() == name)
+    }
+
+    /// in the rf path, switching it while the lst transmits hot switches the rf power
+    fn in_rf_path(self) -> bool {
+        matches!(self, Equipment::AntennaRelay | Equipment::Pa)
+    }
+
+    /// equipment that has to be off before this one is turned on,
+    /// the pa output would overdrive the lna
+    fn excludes(self) -> &'static [Equipment] {
+        match self {
+            Equipment::Lna => &[Equipment::Pa],
+            Equipment::Pa => &[Equipment::Lna],
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct OutputState {
+    pub name: &'static str,
+    pub on: bool,
+}
+
+#[derive(Serialize)]
+pub struct GpioReport {
+    pub outputs: [OutputState; 4],
+    /// reason the last request was not applied
+    pub error: Option<&'static str>,
+}
+
+/// outputs of the ground station driving external equipment, indexed by Equipment
+pub struct StationOutputs {
+    outputs: [Output<'static>; 4],
+}
+
+impl StationOutputs {
+    pub fn new(outputs: [Output<'static>; 4]) -> Self {
+        Self { outputs }
+    }
+
+    fn is_on(&self, equipment: Equipment) -> bool {
+        self.outputs[equipment as usize].is_set_high()
+    }
+
+    /// switch an output after checking the interlocks, returns the reason if refused
+    async fn set(&mut self, lst: &Lst, equipment: Equipment, on: bool) -> Result<(), &'static str> {
+        if on && let Some(blocking) = equipment.excludes().iter().find(|e| self.is_on(**e)) {
+            warn!("{} refused, {} is on", equipment, blocking);
+            return Err("interlocked with other equipment");
+        }
+        // no new uplink starts while the rf path is switched
+        let _lst = lst.lock().await;
+        if equipment.in_rf_path() && uplink::sent_within(TX_HOLDOFF) {
+            warn!("{} refused, the lst is transmitting", equipment);
+            return Err("lst transmitting");
+        }
+        let level = if on { Level::High } else { Level::Low };
+        self.outputs[equipment as usize].set_level(level);
+        info!("{} switched {}", equipment, if on { "on" } else { "off" });
+        Ok(())
+    }
+
+    fn report(&self, error: Option<&'static str>) -> GpioReport {
+        GpioReport {
+            outputs: Equipment::ALL.map(|e| OutputState {
+                name: e.name(),
+                on: self.is_on(e),
+            }),
+            error,
+        }
+    }
+
+    /// apply a request received on the gpio subject and publish the resulting state
+    pub async fn handle_request(
+        &mut self,
+        nats_sender: &mut embassy_nats::Client<'static>,
+        lst: &Lst,
+        subject: &str,
+        payload: &[u8],
+    ) {
+        let result = match subject.strip_prefix(GPIO_PREFIX) {
+            Some("get") => Ok(()),
+            Some("set") => {
+                let mut words = core::str::from_utf8(payload)
+                    .unwrap_or_default()
+                    .split_whitespace();
+                match (words.next().and_then(Equipment::from_name), words.next()) {
+                    (Some(equipment), Some("on")) => self.set(lst, equipment, true).await,
+                    (Some(equipment), Some("off")) => self.set(lst, equipment, false).await,
+                    _ => Err("invalid request"),
+                }
+            }
+            _ => Err("unknown request"),
+        };
+        match cbor_serializer(&self.report(result.err())) {
+            Ok(serialized) => nats_sender.publish_gated(STATE_SUBJECT, serialized).await,
+            Err(_) => error!("could not serialize gpio state"),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use alloc::collections::VecDeque;
+use core::convert::Infallible;
+
+use defmt::{error, warn};
+use embassy_stm32::usart::RingBufferedUartRx;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use embassy_time::{Instant, with_timeout};
+use openlst_driver::lst_receiver::{
+    LSTMessage, LSTReceiver, MessageSource, OwnedLSTMessage, ReceiverError,
+};
+
+use crate::lst_uart;
+
+// complete frames on their way from the receive task to the main loop
+const QUEUE_LEN: usize = 4;
+// relay frames held back while a request waits for its reply, the oldest are dropped
+// once a long request like the checkout overruns it
+const DEFERRED_LEN: usize = 16;
+
+static MESSAGES: Channel<ThreadModeRawMutex, OwnedLSTMessage, QUEUE_LEN> = Channel::new();
+
+/// owns the lst receiver. Receiving a frame is not cancel safe, so it only runs here
+/// and complete frames are handed on to the inbox
+#[embassy_executor::task]
+pub async fn lst_receive_task(mut lst_rx: LSTReceiver<RingBufferedUartRx<'static>>) {
+    loop {
+        match lst_rx.receive().await {
+            Ok(msg) => {
+                lst_uart::received();
+                MESSAGES.send(msg.into_owned()).await;
+            }
+            Err(e) => {
+                error!("error in receiving frame: {:?}", e);
+                lst_uart::error(&mut lst_rx, &e);
+            }
+        }
+    }
+}
+
+/// frames of the receive task, can be selected against the nats subscriptions
+/// without losing one
+pub struct LstInbox {
+    deferred: VecDeque<OwnedLSTMessage>,
+    current: Option<OwnedLSTMessage>,
+}
+
+impl LstInbox {
+    pub const fn new() -> Self {
+        Self {
+            deferred: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    /// next frame, relay frames held back during a request first
+    pub async fn receive(&mut self) -> OwnedLSTMessage {
+        match self.deferred.pop_front() {
+            Some(msg) => msg,
+            None => MESSAGES.receive().await,
+        }
+    }
+
+    /// hold back a relay frame for the main loop, other messages are replies
+    /// nobody waits for anymore
+    fn defer(&mut self, msg: OwnedLSTMessage) {
+        if !matches!(msg, OwnedLSTMessage::Relay(_)) {
+            return;
+        }
+        if self.deferred.len() == DEFERRED_LEN {
+            self.deferred.pop_front();
+            warn!("dropped a held back relay frame");
+        }
+        self.deferred.push_back(msg);
+    }
+
+    /// receive until the handler accepts a message or the deadline passes,
+    /// relay frames it does not accept are kept for the main loop
+    pub async fn wait_for<T>(
+        &mut self,
+        deadline: Instant,
+        mut handler: impl FnMut(LSTMessage<'_>) -> Option<T>,
+    ) -> Option<T> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let msg = with_timeout(remaining, MESSAGES.receive()).await.ok()?;
+            if let Some(result) = handler(msg.as_message()) {
+                return Some(result);
+            }
+            self.defer(msg);
+        }
+    }
+}
+
+/// replies for the lst requests, relay frames are kept for the main loop
+impl MessageSource for LstInbox {
+    type Error = Infallible;
+
+    async fn next_message(&mut self) -> Result<LSTMessage<'_>, ReceiverError<Infallible>> {
+        loop {
+            let msg = MESSAGES.receive().await;
+            if matches!(msg, OwnedLSTMessage::Relay(_)) {
+                self.defer(msg);
+                continue;
+            }
+            return Ok(self.current.insert(msg).as_message());
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use core::cell::RefCell;
+
+use defmt::{error, warn};
+use embassy_stm32::usart::{self, RingBufferedUartRx};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embedded_io_async::ReadExactError;
+use openlst_driver::{
+    link::UART_HEALTH_ID,
+    lst_receiver::{LSTReceiver, ReceiverError},
+    uart_recovery::{UartErrorClass, UartHealth, UartRecovery},
+};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// error counters of the local lst uart and of the one on the vehicle
+const LOCAL_SUBJECT: &str = "gst.status.lst_uart";
+const REMOTE_SUBJECT: &str = "tm.lst_uart";
+
+static RECOVERY: Mutex<ThreadModeRawMutex, RefCell<UartRecovery>> =
+    Mutex::new(RefCell::new(UartRecovery::new()));
+
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub framing: u16,
+    pub noise: u16,
+    pub overrun: u16,
+    pub other: u16,
+    pub recoveries: u16,
+}
+
+impl From<UartHealth> for HealthReport {
+    fn from(health: UartHealth) -> Self {
+        Self {
+            framing: health.framing,
+            noise: health.noise,
+            overrun: health.overrun,
+            other: health.other,
+            recoveries: health.recoveries,
+        }
+    }
+}
+
+fn classify(e: &usart::Error) -> UartErrorClass {
+    match e {
+        usart::Error::Framing => UartErrorClass::Framing,
+        usart::Error::Noise => UartErrorClass::Noise,
+        usart::Error::Overrun => UartErrorClass::Overrun,
+        _ => UartErrorClass::Other,
+    }
+}
+
+/// a frame was received from the lst
+pub fn received() {
+    RECOVERY.lock(|recovery| recovery.borrow_mut().received());
+}
+
+/// count a receive error, flushes and restarts the dma ring buffer once the
+/// uart errors persist
+pub fn error(
+    lst_rx: &mut LSTReceiver<RingBufferedUartRx<'static>>,
+    e: &ReceiverError<usart::Error>,
+) {
+    let class = match e {
+        ReceiverError::ReadError(ReadExactError::Other(e)) => classify(e),
+        ReceiverError::ReadError(ReadExactError::UnexpectedEof) => UartErrorClass::Other,
+        // the uart itself is fine
+        ReceiverError::ParseError(_) | ReceiverError::MsgTooShort => return,
+    };
+    if RECOVERY.lock(|recovery| recovery.borrow_mut().error(class)) {
+        warn!("persistent lst uart errors ({}), reinitializing", class);
+        lst_rx.uart_mut().start_uart();
+    }
+}
+
+/// counters of the vehicle in a relayed health frame, None for other frames
+pub fn parse_remote(frame: &[u8]) -> Option<UartHealth> {
+    match frame.split_first() {
+        Some((&UART_HEALTH_ID, counters)) => UartHealth::from_bytes(counters),
+        _ => None,
+    }
+}
+
+async fn publish(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    subject: &str,
+    health: UartHealth,
+) {
+    match cbor_serializer(&HealthReport::from(health)) {
+        Ok(serialized) => nats_sender.publish_gated(subject, serialized).await,
+        Err(_) => error!("could not serialize lst uart health"),
+    }
+}
+
+/// publish the counters of the local lst uart
+pub async fn publish_local(nats_sender: &mut embassy_nats::Client<'static>) {
+    let health = RECOVERY.lock(|recovery| recovery.borrow().health());
+    publish(nats_sender, LOCAL_SUBJECT, health).await;
+}
+
+/// publish the counters relayed by radio-air
+pub async fn publish_remote(nats_sender: &mut embassy_nats::Client<'static>, health: UartHealth) {
+    publish(nats_sender, REMOTE_SUBJECT, health).await;
+}
@@ -1,9 +1,11 @@
+/// parse a relayed frame into the beacon and publish its values, batched on the subject of the
+/// payload unless per field publishing is enabled. Evaluates to true if the frame belonged to the beacon
 #[macro_export]
 macro_rules! parse_beacon {
-    ($data: ident, $beacon:ident, $crc_func:ident, $nats_sender:ident $(, ($($field:ident),*))?) => {
+    ($data: ident, $payload: ident, $beacon:ident, $crc_func:ident, $nats_sender:ident $(, ($($field:ident),*))?) => {
         paste::paste! {
-            match $beacon.from_bytes($data, &mut $crc_func) {
-                Ok(()) => {
+            match ground_decode::decode!($data, $beacon, &mut $crc_func) {
+                Ok(values) => {
                     info!("{} Received at {}", stringify!([<$beacon:snake:upper>]), &$beacon.timestamp);
                     $($(
                         if let Some(value) = $beacon.$field {
@@ -12,21 +14,30 @@ macro_rules! parse_beacon {
                             warn!("No telemetry received for {}", stringify!($field));
                         }
                     )*)?
-                    match $beacon.serialize(&cbor_serializer) {
-                        Ok(serialized) => {
-                            for v in serialized {
-                                let _ = $nats_sender.publish(v.0.into(), v.1).await;
-                            }
-                        },
-                        Err(_) => error!("could not serialize received value")
+                    let mut published = 0;
+                    let per_field = $crate::publisher::per_field();
+                    if !per_field {
+                        // routes of single values still apply while they travel in the batch
+                        for (subject, value) in &values {
+                            $nats_sender.publish_aliases(subject, value).await;
+                        }
+                    }
+                    for (subject, value) in ground_decode::messages(stringify!($beacon), $payload, values, per_field) {
+                        published += value.len();
+                        $nats_sender.publish_gated(&subject, value).await;
                     }
+                    $crate::bandwidth::record(stringify!($beacon), $data.len(), published);
+                    true
                 }
                 Err(e) => {
                     match e {
-                        ParseError::WrongId => (),
-                        ParseError::BadCRC => error!("{} with bad crc received", stringify!($beacon)),
-                        ParseError::OutOfMemory => error!("{} could not be parsed: not enough bytes", stringify!($beacon)),
+                        DecodeError::WrongId => (),
+                        DecodeError::BadCrc => error!("{} with bad crc received", stringify!($beacon)),
+                        DecodeError::TooShort => error!("{} could not be parsed: not enough bytes", stringify!($beacon)),
+                        DecodeError::Serialize => error!("could not serialize received value"),
                     }
+                    // the values of a beacon that failed to serialize were still parsed
+                    e == DecodeError::Serialize
                 }
             }
         }
@@ -58,7 +69,7 @@ macro_rules! pub_lst_values {
                                     .expect("could not serialize value");
 
                 for v in serialized {
-                    let _ = $nats_sender.publish(v.0.into(), v.1).await;
+                    $nats_sender.publish_gated(&v.0, v.1).await;
                 }
             )*
         }
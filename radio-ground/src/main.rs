@@ -4,18 +4,46 @@
 #![feature(const_cmp)]
 #![feature(never_type)]
 
+mod bandwidth;
+mod blackbox;
+mod burst;
+mod can_stats;
+mod checkout;
+mod command_schedule;
+mod config;
+mod crc_selftest;
+mod duty_cycle;
+mod events;
+mod flight_state;
+mod gpio;
 mod ground_tm_defs;
+mod lst_inbox;
+mod lst_uart;
 mod macros;
+mod met;
+mod mission_phase;
+mod net_config;
+mod profiling;
+mod publisher;
+mod queue_latency;
+mod quota;
+mod radio_control;
+mod raw_uplink;
+mod standby;
+mod time_correlation;
 mod timesync;
+#[cfg(feature = "primary")]
+mod tracking;
+mod uplink;
 
 use core::net::SocketAddr;
 
 use defmt::*;
 use embassy_executor::Spawner;
+use embassy_futures::select::{Either4, select4};
 use embassy_nats::{self, UserPwdAuthenticator};
 use embassy_net::{
     Stack, StackResources,
-    dns::DnsQueryType,
     tcp::{self, TcpSocket},
 };
 use embassy_stm32::{
@@ -23,6 +51,7 @@ use embassy_stm32::{
     crc::{self, Crc},
     dma,
     eth::{self, Ethernet, GenericPhy, PacketQueue, Sma},
+    gpio::{Level, Output, Speed},
     mode::Async,
     peripherals::{DMA1_CH1, DMA1_CH2, ETH, ETH_SMA, IWDG1, RNG, USART2},
     rcc,
@@ -30,16 +59,23 @@ use embassy_stm32::{
     usart::{self, Uart, UartTx},
     wdg::IndependentWatchdog,
 };
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
 use embassy_time::{Duration, Ticker, Timer};
+use events::EventEmitter;
+use lst_inbox::LstInbox;
 use openlst_driver::{
-    lst_receiver::{LSTMessage, LSTReceiver, LSTTelemetry},
+    link,
+    lst_receiver::{LSTMessage, LSTReceiver, LSTTelemetry, TrafficPolicy},
     lst_sender::{LSTCmd, LSTSender},
+    relay_route::{RelayRouter, Route, Routing},
 };
+use publisher::GatedPublish;
 use static_cell::StaticCell;
 
 use {defmt_rtt as _, panic_probe as _};
 
-use south_common::chell::{Beacon, ParseError, ground::SerializableChellValue};
+use ground_decode::DecodeError;
+use south_common::chell::ground::SerializableChellValue;
 
 #[cfg(feature = "primary")]
 use south_common::beacons::{
@@ -64,6 +100,9 @@ extern crate alloc;
 // lst setup
 const OPENLST_HWID: u16 = 0x2DEC;
 
+static LST: StaticCell<Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>> =
+    StaticCell::new();
+
 // Static uart buffer
 const S_RX_BUF_SIZE: usize = 256;
 static S_RX_BUF: StaticCell<[u8; S_RX_BUF_SIZE]> = StaticCell::new();
@@ -143,14 +182,7 @@ fn get_crc_config() -> crc::Config {
     .unwrap()
 }
 
-fn cbor_serializer(
-    value: &dyn erased_serde::Serialize,
-) -> Result<alloc::vec::Vec<u8>, erased_serde::Error> {
-    let mut buffer = alloc::vec::Vec::new();
-    let mut serializer = minicbor_serde::Serializer::new(&mut buffer);
-    value.erased_serialize(&mut <dyn erased_serde::Serializer>::erase(&mut serializer))?;
-    Ok(buffer)
-}
+pub use ground_decode::cbor_serializer;
 
 /// Watchdog petting task
 #[embassy_executor::task]
@@ -172,12 +204,14 @@ async fn nats_task(mut runner: embassy_nats::Runner<'static, UserPwdAuthenticato
 }
 
 #[embassy_executor::task]
-async fn telemetry_request_thread(mut lst_sender: LSTSender<UartTx<'static, Async>>) {
+async fn telemetry_request_thread(
+    lst_sender: &'static Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>,
+) {
     const LST_TM_INTERVALL: Duration = Duration::from_secs(1);
     let mut ticker = Ticker::every(LST_TM_INTERVALL);
     loop {
         ticker.next().await;
-        if let Err(e) = lst_sender.cmd(LSTCmd::GetTelem).await {
+        if let Err(e) = lst_sender.lock().await.cmd(LSTCmd::GetTelem).await {
             error!("could not send cmd over serial: {}", e);
         }
     }
@@ -227,11 +261,8 @@ pub async fn parse_or_resolve(
         return Ok(sa);
     }
 
-    let ips = stack.dns_query(s, DnsQueryType::A).await?;
-    let Some(ip) = ips.first() else {
-        return Err(embassy_net::dns::Error::Failed);
-    };
-    Ok(SocketAddr::new((*ip).into(), 4222))
+    let ip = net_config::resolve(stack, s).await?;
+    Ok(SocketAddr::new(ip.into(), 4222))
 }
 
 #[embassy_executor::main]
@@ -277,8 +308,31 @@ async fn main(spawner: Spawner) {
     // .unwrap()
     // .split();
 
-    let lst_tx = LSTSender::new(uart_tx, OPENLST_HWID);
+    let mut lst_sender = LSTSender::new(uart_tx, OPENLST_HWID);
+    // the rf switching interlock holds off after anything the lst transmits
+    lst_sender.set_rf_hook(uplink::on_air);
+    let lst_tx = LST.init(Mutex::new(lst_sender));
     let mut lst_rx = LSTReceiver::new(uart_rx.into_ring_buffered(S_RX_BUF.init([0; _])));
+    // command replies should not wait behind the relayed beacons
+    lst_rx.set_policy(TrafficPolicy::LocalFirst);
+    spawner.spawn(lst_inbox::lst_receive_task(lst_rx).unwrap());
+    let mut lst_rx = LstInbox::new();
+
+    // external station equipment, all off until switched over nats
+    let mut station_outputs = gpio::StationOutputs::new([
+        Output::new(p.PE2, Level::Low, Speed::Low),
+        Output::new(p.PE3, Level::Low, Speed::Low),
+        Output::new(p.PE4, Level::Low, Speed::Low),
+        Output::new(p.PE5, Level::Low, Speed::Low),
+    ]);
+
+    // antenna rotator on the spare uart
+    #[cfg(feature = "rotator")]
+    let mut rotator = {
+        let mut rotator_config = usart::Config::default();
+        rotator_config.baudrate = 9600;
+        tracking::Rotator::new(UartTx::new_blocking(p.USART3, p.PB10, rotator_config).unwrap())
+    };
 
     // Initialize ethernet
     let eth_int = p.ETH;
@@ -302,6 +356,10 @@ async fn main(spawner: Spawner) {
     rng.fill_bytes(&mut seed);
     let seed = u64::from_le_bytes(seed);
 
+    let mut uplink_seq = [0; 2];
+    rng.fill_bytes(&mut uplink_seq);
+    uplink::seed_seq(u16::from_le_bytes(uplink_seq));
+
     let mut mac_addr = [0; 6];
     rng.fill_bytes(&mut mac_addr);
     // make sure mac addr is unicast
@@ -325,7 +383,9 @@ async fn main(spawner: Spawner) {
         mdc,
     );
 
-    let net_cfg = embassy_net::Config::dhcpv4(Default::default());
+    // dual stack: dhcpv4 alongside ipv6
+    let mut net_cfg = embassy_net::Config::dhcpv4(Default::default());
+    net_cfg.ipv6 = net_config::ipv6_config(mac_addr);
 
     // Initialize network stack
     info!("Initializing network task");
@@ -342,7 +402,9 @@ async fn main(spawner: Spawner) {
 
     info!("Network initialized");
 
-    let unix_time_offset_us = timesync::sync_internet_time(&stack).await;
+    let ntp_offset_us = timesync::sync_internet_time(&stack).await;
+    let mut time_synced = ntp_offset_us.is_some();
+    let mut unix_time_offset_us = ntp_offset_us.unwrap_or(0);
 
     // Initizlize Nats socket
     let socket = TcpSocket::new(stack, TCP_RX_BUF.init([0; _]), TCP_TX_BUF.init([0; _]));
@@ -384,39 +446,349 @@ async fn main(spawner: Spawner) {
     // launch nats task
     spawner.spawn(nats_task(runner).unwrap());
 
+    // subscribe to operator radio control
+    let mut control_sub = loop {
+        match client.subscribe(radio_control::CONTROL_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to radio control, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // subscribe to runtime configuration
+    let mut config_sub = loop {
+        match client.subscribe(config::CONFIG_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to config, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // subscribe to the server time echo if ntp is unavailable
+    let mut time_sub = loop {
+        match client.subscribe(timesync::TIME_REPLY_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to server time, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+    if !time_synced {
+        timesync::request_server_time(&mut client).await;
+    }
+
+    // subscribe to the heartbeats of a redundant ground station
+    let mut standby_sub = loop {
+        match client.subscribe(standby::HEARTBEAT_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to standby heartbeats, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // subscribe to raw uplink payloads of external command tools
+    let mut raw_sub = loop {
+        match client.subscribe(raw_uplink::RAW_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to raw uplink, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // subscribe to telemetry filters for the vehicle
+    let mut filter_sub = loop {
+        match client.subscribe(raw_uplink::FILTER_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to telemetry filter, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // subscribe to the uplink commands of the ops consoles
+    let mut command_sub = loop {
+        match client.subscribe(raw_uplink::COMMAND_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to uplink commands, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // subscribe to T-0 for the mission elapsed time of the vehicle
+    let mut t0_sub = loop {
+        match client.subscribe(met::T0_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to T-0, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
+    // frames of the vehicle reaching this station over repeaters
+    let mut router = RelayRouter::new(link::ROUTE_NODE_GROUND);
+    // link and vehicle state transitions for the ops consoles
+    let mut events = EventEmitter::new();
+
+    // subscribe to the station equipment control
+    let mut gpio_sub = loop {
+        match client.subscribe(gpio::GPIO_SUBJECT).await {
+            Ok(sub) => break sub,
+            Err(_) => {
+                warn!("could not subscribe to gpio control, retrying...");
+                Timer::after_secs(2).await;
+            }
+        }
+    };
+
     // receiving main loop
     loop {
-        match lst_rx.receive().await {
-            Ok(msg) => match msg {
-                LSTMessage::Relay(data) => {
-                    crc.reset();
-                    let mut crc_func = |bytes: &[u8]| {
-                        crc.feed_bytes(bytes);
-                        crc.read() as u16
-                    };
-                    #[cfg(feature = "primary")]
+        let received = match select4(
+            lst_rx.receive(),
+            control_sub.next(),
+            config_sub.next(),
+            select4(
+                time_sub.next(),
+                standby_sub.next(),
+                raw_sub.next(),
+                select4(
+                    filter_sub.next(),
+                    gpio_sub.next(),
+                    t0_sub.next(),
+                    command_sub.next(),
+                ),
+            ),
+        )
+        .await
+        {
+            Either4::First(received) => received,
+            Either4::Second(request) => {
+                if request.subject == checkout::CHECKOUT_SUBJECT {
+                    checkout::run_checkout(
+                        &mut client,
+                        lst_tx,
+                        &mut lst_rx,
+                        &mut crc,
+                        OPENLST_HWID,
+                    )
+                    .await;
+                    continue;
+                }
+                radio_control::handle_request(
+                    &mut client,
+                    lst_tx,
+                    &mut lst_rx,
+                    OPENLST_HWID,
+                    &request.subject,
+                )
+                .await;
+                continue;
+            }
+            Either4::Third(config) => {
+                config::handle_config(&config.subject, &config.payload);
+                continue;
+            }
+            Either4::Fourth(Either4::Second(heartbeat)) => {
+                standby::handle_heartbeat(&heartbeat.payload);
+                continue;
+            }
+            Either4::Fourth(Either4::Third(raw)) => {
+                raw_uplink::handle_raw(&mut client, lst_tx, OPENLST_HWID, &raw.payload).await;
+                continue;
+            }
+            Either4::Fourth(Either4::Fourth(Either4::First(filter))) => {
+                raw_uplink::handle_filter(&mut client, lst_tx, OPENLST_HWID, &filter.payload).await;
+                continue;
+            }
+            Either4::Fourth(Either4::Fourth(Either4::Second(request))) => {
+                station_outputs
+                    .handle_request(&mut client, lst_tx, &request.subject, &request.payload)
+                    .await;
+                continue;
+            }
+            Either4::Fourth(Either4::Fourth(Either4::Third(t0))) => {
+                raw_uplink::handle_t0(&mut client, lst_tx, OPENLST_HWID, &t0.payload).await;
+                continue;
+            }
+            Either4::Fourth(Either4::Fourth(Either4::Fourth(command))) => {
+                raw_uplink::handle_command(
+                    &mut client,
+                    lst_tx,
+                    OPENLST_HWID,
+                    &command.subject,
+                    &command.payload,
+                )
+                .await;
+                continue;
+            }
+            Either4::Fourth(Either4::First(reply)) => {
+                if !time_synced && let Some(offset) = timesync::server_time_offset(&reply.payload) {
+                    // the first echo is kept, like the ntp offset
+                    unix_time_offset_us = offset;
+                    time_synced = true;
+                }
+                continue;
+            }
+        };
+        match received.as_message() {
+            LSTMessage::Relay(data) => {
+                let data = match Route::parse(data) {
+                    Some((route, inner)) => match router.route(route, inner) {
+                        Routing::Deliver(inner) => inner,
+                        // left to the repeaters, or already received over another path
+                        Routing::Forward(..) | Routing::Drop => continue,
+                    },
+                    None => data,
+                };
+                let utc_us = timesync::current_unix_time_micros(unix_time_offset_us);
+                events.frame_received(&mut client, utc_us).await;
+                crc.reset();
+                let mut crc_func = |bytes: &[u8]| {
+                    crc.feed_bytes(bytes);
+                    crc.read() as u16
+                };
+                if crc_selftest::is_test_beacon(data) {
+                    crc_selftest::check_test_beacon(&mut client, data, &mut crc_func).await;
+                    continue;
+                }
+                if let Some(ack) = uplink::parse_ack(data) {
+                    uplink::publish_ack(&mut client, ack).await;
+                    continue;
+                }
+                if let Some((_, version)) = uplink::parse_version(data) {
+                    let target = radio_control::RadioTarget::Remote;
+                    radio_control::publish_version(&mut client, target, version).await;
+                    continue;
+                }
+                if let Some(commands) = command_schedule::parse(data) {
+                    command_schedule::publish_schedule(&mut client, commands).await;
+                    continue;
+                }
+                if let Some(state) = flight_state::parse(data) {
+                    flight_state::publish_state(&mut client, state).await;
+                    continue;
+                }
+                if let Some(record) = blackbox::parse(data) {
+                    blackbox::publish_record(&mut client, record).await;
+                    continue;
+                }
+                if let Some(phase) = mission_phase::parse(data) {
+                    mission_phase::publish_phase(&mut client, phase).await;
+                    continue;
+                }
+                if let Some(health) = lst_uart::parse_remote(data) {
+                    lst_uart::publish_remote(&mut client, health).await;
+                    continue;
+                }
+                if let Some(stats) = can_stats::parse(data) {
+                    can_stats::publish_can_stats(&mut client, stats).await;
+                    continue;
+                }
+                if let Some(profiling) = profiling::parse(data) {
+                    profiling::publish_profiling(&mut client, profiling).await;
+                    continue;
+                }
+                if let Some(duty_cycle) = duty_cycle::parse(data) {
+                    duty_cycle::publish_duty_cycle(&mut client, duty_cycle).await;
+                    continue;
+                }
+                if let Some(classes) = quota::parse(data) {
+                    quota::publish_quota(&mut client, classes).await;
+                    continue;
+                }
+                if let Some((seq, local_ms)) = time_correlation::parse(data) {
+                    time_correlation::publish_model(&mut client, seq, local_ms, utc_us).await;
+                    continue;
+                }
+                let (envelope, data) = link::split_envelope(data);
+                if let Some(met_ms) = envelope.met_ms {
+                    met::publish_met(&mut client, met_ms, utc_us).await;
+                }
+                if let Some(trigger) = envelope.burst_trigger {
+                    burst::publish_marked(&mut client, trigger).await;
+                }
+                let payload = envelope.payload.unwrap_or(ground_decode::BUS_PAYLOAD);
+                #[cfg(feature = "primary")]
+                {
+                    if parse_beacon!(data, payload, lst_beacon, crc_func, client, (packets_sent))
+                        && let Some(uptime) = lst_beacon.uptime
+                    {
+                        events.remote_uptime(&mut client, uptime, utc_us).await;
+                    }
+                    if parse_beacon!(data, payload, eps_beacon, crc_func, client, (bat1_voltage))
+                        && let Some(volts) = eps_beacon.bat1_voltage
                     {
-                        parse_beacon!(data, lst_beacon, crc_func, client, (packets_sent));
-                        parse_beacon!(data, eps_beacon, crc_func, client, (bat1_voltage));
-                        parse_beacon!(data, high_rate_upper_beacon, crc_func, client);
-                        parse_beacon!(data, low_rate_upper_beacon, crc_func, client, (gps_pos));
-                        parse_beacon!(data, lower_sensor_beacon, crc_func, client);
-                        parse_beacon!(data, pyro_beacon, crc_func, client);
+                        events.battery(&mut client, volts as f32, utc_us).await;
                     }
-                    #[cfg(feature = "secondary")]
+                    parse_beacon!(data, payload, high_rate_upper_beacon, crc_func, client);
+                    if parse_beacon!(
+                        data,
+                        payload,
+                        low_rate_upper_beacon,
+                        crc_func,
+                        client,
+                        (gps_pos)
+                    ) && let Some(pos) = low_rate_upper_beacon.gps_pos
                     {
-                        parse_beacon!(data, secondary_lst_beacon, crc_func, channel);
+                        let angles = tracking::look_angles(
+                            low_rate_upper_beacon.timestamp,
+                            [pos.x as f64, pos.y as f64, pos.z as f64],
+                        );
+                        tracking::publish_look_angles(&mut client, &angles).await;
+                        #[cfg(feature = "rotator")]
+                        rotator.point(&angles);
                     }
+                    parse_beacon!(data, payload, lower_sensor_beacon, crc_func, client);
+                    parse_beacon!(data, payload, pyro_beacon, crc_func, client);
                 }
-                LSTMessage::Telem(tm) => {
-                    local_lst_telemetry(&mut client, tm, unix_time_offset_us).await;
+                #[cfg(feature = "secondary")]
+                {
+                    parse_beacon!(data, payload, secondary_lst_beacon, crc_func, channel);
                 }
-                LSTMessage::Ack => info!("LST Ack"),
-                LSTMessage::Nack => info!("LST Nack"),
-                LSTMessage::Unknown(a, _) => info!("LST Unknown: {}", a),
-            },
-            Err(e) => {
-                error!("error in receiving frame: {:?}", e);
+            }
+            LSTMessage::Telem(tm) => {
+                // retry the server time request at the local telemetry rate until answered
+                if !time_synced {
+                    timesync::request_server_time(&mut client).await;
+                }
+                standby::send_heartbeat(&mut client, tm.packets_good).await;
+                let utc_us = timesync::current_unix_time_micros(unix_time_offset_us);
+                events.poll(&mut client, utc_us).await;
+                local_lst_telemetry(&mut client, tm, unix_time_offset_us).await;
+                bandwidth::publish_due(&mut client, unix_time_offset_us).await;
+                lst_uart::publish_local(&mut client).await;
+                queue_latency::publish_due(&mut client).await;
+                // keep draining queued telemetry while no beacons arrive
+                client.flush_bulk().await;
+            }
+            LSTMessage::Version(version) => {
+                radio_control::publish_version(
+                    &mut client,
+                    radio_control::RadioTarget::Local,
+                    version,
+                )
+                .await;
+            }
+            LSTMessage::Channels(table) => info!("LST channels: {}", table),
+            LSTMessage::Ack => info!("LST Ack"),
+            LSTMessage::Nack => info!("LST Nack"),
+            LSTMessage::BootloaderAck(a) => info!("LST bootloader Ack: {}", a),
+            LSTMessage::BootloaderNack => info!("LST bootloader Nack"),
+            LSTMessage::Unknown(a, _) => info!("LST Unknown: {}", a),
+            LSTMessage::Captured(reason, frame) => {
+                warn!("LST captured frame ({}): {:x}", reason, frame)
             }
         }
     }
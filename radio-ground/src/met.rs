@@ -0,0 +1,54 @@
+use core::cell::Cell;
+
+use defmt::error;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// T-0 for radio-air as utc ms(8 LE), an empty payload clears it
+pub const T0_SUBJECT: &str = "gst.uplink.t0";
+pub const MET_SUBJECT: &str = "tm.met";
+// the marker comes with every beacon, the clock is published at a lower rate
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// mission elapsed time of the vehicle, negative during the countdown
+#[derive(Serialize)]
+pub struct MissionElapsedTime {
+    pub met_ms: i32,
+    /// utc of the station when the stamped beacon was received
+    pub received_us: u64,
+}
+
+static LAST_PUBLISHED: Mutex<ThreadModeRawMutex, Cell<Option<Instant>>> =
+    Mutex::new(Cell::new(None));
+
+/// publish the mission elapsed time of a stamped frame, at most once per interval
+pub async fn publish_met(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    met_ms: i32,
+    received_us: u64,
+) {
+    let now = Instant::now();
+    let due = LAST_PUBLISHED.lock(|last| {
+        let due = last
+            .get()
+            .is_none_or(|last| now.saturating_duration_since(last) >= PUBLISH_INTERVAL);
+        if due {
+            last.set(Some(now));
+        }
+        due
+    });
+    if !due {
+        return;
+    }
+    let met = MissionElapsedTime {
+        met_ms,
+        received_us,
+    };
+    match cbor_serializer(&met) {
+        Ok(serialized) => nats_sender.publish_gated(MET_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize mission elapsed time"),
+    }
+}
@@ -0,0 +1,28 @@
+use defmt::{error, info};
+use openlst_driver::link::PHASE_ID;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+const PHASES: [&str; 5] = ["pad", "ascent", "coast", "descent", "recovery"];
+
+pub const PHASE_SUBJECT: &str = "tm.phase";
+
+pub fn name(phase: u8) -> Option<&'static str> {
+    PHASES.get(phase as usize).copied()
+}
+
+/// name of the phase in a phase frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<&'static str> {
+    match frame {
+        [PHASE_ID, phase] => name(*phase),
+        _ => None,
+    }
+}
+
+pub async fn publish_phase(nats_sender: &mut embassy_nats::Client<'static>, phase: &str) {
+    info!("mission phase: {}", phase);
+    match cbor_serializer(&phase) {
+        Ok(serialized) => nats_sender.publish_gated(PHASE_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize mission phase"),
+    }
+}
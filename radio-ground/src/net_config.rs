@@ -0,0 +1,56 @@
+use core::net::Ipv6Addr;
+
+use embassy_net::{ConfigV6, IpAddress, Ipv6Cidr, Stack, StaticConfigV6, dns::DnsQueryType};
+
+// static global ipv6 address (address, prefix length, gateway). If unset,
+// only the link local address derived from the mac is configured
+const IPV6_STATIC: Option<(Ipv6Addr, u8, Option<Ipv6Addr>)> = None;
+// dns servers reachable over ipv6, used on networks without dhcpv4
+const IPV6_DNS_SERVERS: &[Ipv6Addr] = &[];
+
+/// link local address with an EUI-64 interface id derived from the mac
+fn link_local(mac: [u8; 6]) -> Ipv6Addr {
+    Ipv6Addr::new(
+        0xfe80,
+        0,
+        0,
+        0,
+        u16::from_be_bytes([mac[0] ^ 0x02, mac[1]]),
+        u16::from_be_bytes([mac[2], 0xff]),
+        u16::from_be_bytes([0xfe, mac[3]]),
+        u16::from_be_bytes([mac[4], mac[5]]),
+    )
+}
+
+/// ipv6 configuration used alongside dhcpv4
+pub fn ipv6_config(mac: [u8; 6]) -> ConfigV6 {
+    let (address, gateway) = match IPV6_STATIC {
+        Some((address, prefix_len, gateway)) => (Ipv6Cidr::new(address, prefix_len), gateway),
+        None => (Ipv6Cidr::new(link_local(mac), 64), None),
+    };
+    let mut config = StaticConfigV6 {
+        address,
+        gateway,
+        dns_servers: Default::default(),
+    };
+    for server in IPV6_DNS_SERVERS {
+        let _ = config.dns_servers.push(*server);
+    }
+    ConfigV6::Static(config)
+}
+
+/// resolve a host name, preferring ipv4 and falling back to ipv6 only networks
+pub async fn resolve(stack: &Stack<'_>, host: &str) -> Result<IpAddress, embassy_net::dns::Error> {
+    let mut result = Err(embassy_net::dns::Error::Failed);
+    for query_type in [DnsQueryType::A, DnsQueryType::Aaaa] {
+        match stack.dns_query(host, query_type).await {
+            Ok(ips) => {
+                if let Some(ip) = ips.first() {
+                    return Ok(*ip);
+                }
+            }
+            Err(e) => result = Err(e),
+        }
+    }
+    result
+}
@@ -0,0 +1,71 @@
+use alloc::vec::Vec;
+
+use defmt::{error, warn};
+use openlst_driver::link::{PROFILED_TASKS, PROFILING_ID};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// cpu and stack usage of radio-air, layout in link::PROFILING_ID
+const PROFILING_LEN: usize = 9 + PROFILED_TASKS.len() * 2;
+// load or stack usage above which the report is logged as a warning
+const WARN_PERMILLE: u32 = 800;
+
+pub const PROFILING_SUBJECT: &str = "tm.profiling";
+
+#[derive(Serialize)]
+pub struct TaskLoad {
+    pub name: &'static str,
+    pub busy_permille: u16,
+}
+
+/// cpu load and stack high water mark of the vehicle over its report window
+#[derive(Serialize)]
+pub struct Profiling {
+    pub cpu_permille: u32,
+    pub stack_used: u32,
+    pub stack_size: u32,
+    pub tasks: Vec<TaskLoad>,
+}
+
+/// task loads and stack usage in a profiling frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<Profiling> {
+    if frame.len() != PROFILING_LEN || frame[0] != PROFILING_ID {
+        return None;
+    }
+    let tasks: Vec<TaskLoad> = PROFILED_TASKS
+        .iter()
+        .zip(frame[9..].chunks_exact(2))
+        .map(|(&name, busy)| TaskLoad {
+            name,
+            busy_permille: u16::from_le_bytes([busy[0], busy[1]]),
+        })
+        .collect();
+    Some(Profiling {
+        cpu_permille: tasks.iter().map(|task| task.busy_permille as u32).sum(),
+        stack_used: u32::from_le_bytes(frame[1..5].try_into().unwrap()),
+        stack_size: u32::from_le_bytes(frame[5..9].try_into().unwrap()),
+        tasks,
+    })
+}
+
+pub async fn publish_profiling(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    profiling: Profiling,
+) {
+    let stack_permille = profiling.stack_used as u64 * 1000 / profiling.stack_size.max(1) as u64;
+    if profiling.cpu_permille > WARN_PERMILLE || stack_permille > WARN_PERMILLE as u64 {
+        warn!(
+            "vehicle cpu load {} permille, stack {}/{} bytes",
+            profiling.cpu_permille, profiling.stack_used, profiling.stack_size
+        );
+    }
+    match cbor_serializer(&profiling) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(PROFILING_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize profiling"),
+    }
+}
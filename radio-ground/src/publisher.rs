@@ -0,0 +1,199 @@
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::Instant;
+
+use crate::{queue_latency, standby};
+
+// subject prefixes that are currently muted
+static MUTED: Mutex<ThreadModeRawMutex, RefCell<Vec<String>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+// additional subjects values are published on, (subject, alias)
+static ROUTES: Mutex<ThreadModeRawMutex, RefCell<Vec<(String, String)>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+// publish every value on its own subject instead of one batch per beacon
+static PER_FIELD: AtomicBool = AtomicBool::new(false);
+// telemetry and reports waiting for the socket, (subject, payload, time of the publish)
+static BULK: Mutex<ThreadModeRawMutex, RefCell<VecDeque<(String, Vec<u8>, Instant)>>> =
+    Mutex::new(RefCell::new(VecDeque::new()));
+
+// alarms and command acknowledgements, written before any queued bulk message
+const PRIORITY_SUBJECTS: [&str; 5] = [
+    "events.",
+    "gst.response.radio.",
+    "gst.uplink.receipt",
+    "gst.station.gpio",
+    "gst.status.queue_latency",
+];
+// bulk messages written per publish, bounds the wait of a following alarm
+const BULK_QUOTA: usize = 4;
+// oldest bulk messages are dropped beyond this
+const BULK_QUEUE_LEN: usize = 64;
+
+/// enable or disable publishing on all subjects starting with the given prefix
+pub fn set_enabled(prefix: &str, enabled: bool) {
+    MUTED.lock(|muted| {
+        let mut muted = muted.borrow_mut();
+        muted.retain(|p| p != prefix);
+        if !enabled {
+            muted.push(String::from(prefix));
+        }
+    });
+    info!(
+        "publishing on {}* {}",
+        prefix,
+        if enabled { "enabled" } else { "disabled" }
+    );
+}
+
+pub fn is_enabled(subject: &str) -> bool {
+    MUTED.lock(|muted| {
+        !muted
+            .borrow()
+            .iter()
+            .any(|p| subject.starts_with(p.as_str()))
+    })
+}
+
+/// additionally publish a subject on an alias. A subject ending in `>` matches
+/// all subjects with that prefix, the remainder is appended to the alias prefix
+pub fn add_route(subject: &str, alias: &str) {
+    ROUTES.lock(|routes| {
+        routes
+            .borrow_mut()
+            .push((String::from(subject), String::from(alias)))
+    });
+    info!("routing {} to {}", subject, alias);
+}
+
+/// remove all aliases of a subject
+pub fn remove_routes(subject: &str) {
+    ROUTES.lock(|routes| routes.borrow_mut().retain(|(s, _)| s != subject));
+    info!("removed routes of {}", subject);
+}
+
+fn route(subject: &str, alias: &str, target: &str) -> Option<String> {
+    match subject.strip_suffix('>') {
+        Some(prefix) => {
+            let rest = target.strip_prefix(prefix)?;
+            let mut routed = String::from(alias.strip_suffix('>').unwrap_or(alias));
+            routed.push_str(rest);
+            Some(routed)
+        }
+        None => (subject == target).then(|| String::from(alias)),
+    }
+}
+
+fn aliases(target: &str) -> Vec<String> {
+    ROUTES.lock(|routes| {
+        routes
+            .borrow()
+            .iter()
+            .filter_map(|(subject, alias)| route(subject, alias, target))
+            .collect()
+    })
+}
+
+pub fn set_per_field(enabled: bool) {
+    PER_FIELD.store(enabled, Ordering::Relaxed);
+    info!(
+        "publishing {}",
+        if enabled { "per field" } else { "per beacon" }
+    );
+}
+
+pub fn per_field() -> bool {
+    PER_FIELD.load(Ordering::Relaxed)
+}
+
+fn is_priority(subject: &str) -> bool {
+    PRIORITY_SUBJECTS
+        .iter()
+        .any(|prefix| subject.starts_with(prefix))
+}
+
+/// hand a message to the client, the latency counts from the publish so it includes
+/// the time queued in BULK
+async fn write(
+    client: &mut embassy_nats::Client<'static>,
+    subject: &str,
+    payload: Vec<u8>,
+    published: Instant,
+) {
+    let result = client.publish(subject.into(), payload).await;
+    queue_latency::record(subject, published.elapsed());
+    if result.is_err() {
+        warn!("could not publish on {}", subject);
+    }
+}
+
+async fn publish_targets(
+    client: &mut embassy_nats::Client<'static>,
+    targets: Vec<String>,
+    payload: Vec<u8>,
+) {
+    let published = Instant::now();
+    for target in targets {
+        let target = standby::route(&target);
+        if !is_enabled(&target) || queue_latency::shed(&target) {
+            continue;
+        }
+        if is_priority(&target) {
+            write(client, &target, payload.clone(), published).await;
+            continue;
+        }
+        BULK.lock(|bulk| {
+            let mut bulk = bulk.borrow_mut();
+            if bulk.len() == BULK_QUEUE_LEN {
+                warn!("bulk publish queue full, dropping {}", bulk[0].0.as_str());
+                bulk.pop_front();
+            }
+            bulk.push_back((target.into_owned(), payload.clone(), published));
+        });
+    }
+    client.flush_bulk().await;
+}
+
+/// publish through the gate and routing tables, muted subjects are dropped silently.
+/// On the standby ground station telemetry subjects are moved below standby.
+/// Bulk traffic is dropped while the queue latency alarm is raised.
+/// Alarms and acks are written right away, everything else is queued and written
+/// at most BULK_QUOTA messages per publish
+pub trait GatedPublish {
+    async fn publish_gated(&mut self, subject: &str, payload: Vec<u8>);
+    /// publish only on the aliases of a subject, for values that went out inside a batch
+    async fn publish_aliases(&mut self, subject: &str, payload: &[u8]);
+    /// write the next queued bulk messages, up to BULK_QUOTA
+    async fn flush_bulk(&mut self);
+}
+
+impl GatedPublish for embassy_nats::Client<'static> {
+    async fn publish_gated(&mut self, subject: &str, payload: Vec<u8>) {
+        let mut targets = aliases(subject);
+        targets.push(String::from(subject));
+        publish_targets(self, targets, payload).await;
+    }
+
+    async fn publish_aliases(&mut self, subject: &str, payload: &[u8]) {
+        let targets = aliases(subject);
+        if !targets.is_empty() {
+            publish_targets(self, targets, payload.to_vec()).await;
+        }
+    }
+
+    async fn flush_bulk(&mut self) {
+        for _ in 0..BULK_QUOTA {
+            let Some((subject, payload, published)) =
+                BULK.lock(|bulk| bulk.borrow_mut().pop_front())
+            else {
+                return;
+            };
+            write(self, &subject, payload, published).await;
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use core::cell::RefCell;
+
+use defmt::{Format, error, info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// time from a publish until the nats client took the message: the wait in the bulk
+// queue and the backpressure of the client channel. The runner writes the socket
+// afterwards, so the tcp send itself is not part of it
+const STATUS_SUBJECT: &str = "gst.status.queue_latency";
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+// latency samples kept per class for the percentile, cleared with each status report so
+// the stale samples of the shed bulk class can not hold the alarm
+const SAMPLES: usize = 64;
+// p95 latency that raises the alarm, cleared again below half of it
+const ALARM_LATENCY_US: u32 = 50_000;
+
+#[derive(Format, Serialize, Clone, Copy, PartialEq)]
+pub enum MessageClass {
+    /// station control and replies
+    Control,
+    Telemetry,
+    /// reports and the standby copy of the telemetry, dropped while the alarm is raised
+    Bulk,
+}
+
+impl MessageClass {
+    fn of(subject: &str) -> Self {
+        if subject.starts_with("standby.")
+            || subject.starts_with("gst.status.")
+            || subject.starts_with("gst.bandwidth.")
+        {
+            MessageClass::Bulk
+        } else if subject.starts_with("gst.") {
+            MessageClass::Control
+        } else {
+            MessageClass::Telemetry
+        }
+    }
+}
+
+const CLASSES: [MessageClass; 3] = [
+    MessageClass::Control,
+    MessageClass::Telemetry,
+    MessageClass::Bulk,
+];
+
+#[derive(Serialize, Clone, Copy)]
+pub struct ClassLatency {
+    pub class: MessageClass,
+    pub messages: u32,
+    pub p95_us: u32,
+    pub max_us: u32,
+}
+
+#[derive(Serialize)]
+pub struct LatencyReport {
+    pub alarm: bool,
+    pub dropped: u32,
+    pub classes: [ClassLatency; 3],
+}
+
+#[derive(Clone, Copy)]
+struct ClassStats {
+    samples: [u32; SAMPLES],
+    next: usize,
+    // messages and max since the last report
+    messages: u32,
+    max_us: u32,
+}
+
+impl ClassStats {
+    const fn new() -> Self {
+        Self {
+            samples: [0; SAMPLES],
+            next: 0,
+            messages: 0,
+            max_us: 0,
+        }
+    }
+    fn add(&mut self, latency_us: u32) {
+        self.samples[self.next % SAMPLES] = latency_us;
+        self.next = self.next.wrapping_add(1);
+        self.messages = self.messages.wrapping_add(1);
+        self.max_us = self.max_us.max(latency_us);
+    }
+    /// 95th percentile of the samples since the last report
+    fn p95_us(&self) -> u32 {
+        let len = self.next.min(SAMPLES);
+        if len == 0 {
+            return 0;
+        }
+        let mut sorted = self.samples;
+        sorted[..len].sort_unstable();
+        sorted[(len * 95).div_ceil(100) - 1]
+    }
+}
+
+struct Latencies {
+    classes: [ClassStats; 3],
+    alarm: bool,
+    dropped: u32,
+    last_status: Instant,
+}
+
+static LATENCIES: Mutex<ThreadModeRawMutex, RefCell<Latencies>> =
+    Mutex::new(RefCell::new(Latencies {
+        classes: [ClassStats::new(); 3],
+        alarm: false,
+        dropped: 0,
+        last_status: Instant::from_ticks(0),
+    }));
+
+/// record the time a message on the subject waited until the client took it
+pub fn record(subject: &str, latency: Duration) {
+    let class = MessageClass::of(subject);
+    let latency_us = latency.as_micros().min(u32::MAX as u64) as u32;
+    LATENCIES.lock(|latencies| {
+        let mut latencies = latencies.borrow_mut();
+        latencies.classes[class as usize].add(latency_us);
+        let p95 = latencies.classes[class as usize].p95_us();
+        if !latencies.alarm && p95 > ALARM_LATENCY_US {
+            latencies.alarm = true;
+            warn!(
+                "queue latency alarm: {} p95 {} us, dropping bulk traffic",
+                class, p95
+            );
+        } else if latencies.alarm
+            && latencies
+                .classes
+                .iter()
+                .all(|c| c.p95_us() < ALARM_LATENCY_US / 2)
+        {
+            latencies.alarm = false;
+            info!("queue latency back to normal");
+        }
+    });
+}
+
+/// true if the message should be dropped to relieve the publisher
+pub fn shed(subject: &str) -> bool {
+    // the alarm itself has to get through
+    if subject == STATUS_SUBJECT || MessageClass::of(subject) != MessageClass::Bulk {
+        return false;
+    }
+    LATENCIES.lock(|latencies| {
+        let mut latencies = latencies.borrow_mut();
+        if latencies.alarm {
+            latencies.dropped = latencies.dropped.wrapping_add(1);
+        }
+        latencies.alarm
+    })
+}
+
+/// publish the latency per class if the status interval passed
+pub async fn publish_due(nats_sender: &mut embassy_nats::Client<'static>) {
+    let now = Instant::now();
+    let report = LATENCIES.lock(|latencies| {
+        let mut latencies = latencies.borrow_mut();
+        if now.saturating_duration_since(latencies.last_status) < STATUS_INTERVAL {
+            return None;
+        }
+        latencies.last_status = now;
+        let report = LatencyReport {
+            alarm: latencies.alarm,
+            dropped: latencies.dropped,
+            classes: CLASSES.map(|class| {
+                let stats = &mut latencies.classes[class as usize];
+                let latency = ClassLatency {
+                    class,
+                    messages: stats.messages,
+                    p95_us: stats.p95_us(),
+                    max_us: stats.max_us,
+                };
+                *stats = ClassStats::new();
+                latency
+            }),
+        };
+        latencies.dropped = 0;
+        Some(report)
+    });
+    let Some(report) = report else {
+        return;
+    };
+    match cbor_serializer(&report) {
+        Ok(serialized) => nats_sender.publish_gated(STATUS_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize queue latency report"),
+    }
+}
@@ -0,0 +1,52 @@
+use defmt::{error, warn};
+use openlst_driver::link::SHAPER_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// downlink quota utilization of radio-air relayed with its lst telemetry:
+// id(1) per class: utilization permille(2 LE) deferred beacons(2 LE)
+const CLASSES: [&str; 3] = ["safety", "housekeeping", "science"];
+const SHAPER_LEN: usize = 1 + CLASSES.len() * 4;
+
+pub const QUOTA_SUBJECT: &str = "tm.quota";
+
+#[derive(Serialize)]
+pub struct ClassUtilization {
+    pub class: &'static str,
+    /// bytes sent since the previous report relative to the quota of the class
+    pub utilization_permille: u16,
+    /// beacons held back since startup
+    pub deferred: u16,
+}
+
+/// utilization of the beacon class quotas in a shaper frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<[ClassUtilization; 3]> {
+    if frame.len() != SHAPER_LEN || frame[0] != SHAPER_ID {
+        return None;
+    }
+    Some(core::array::from_fn(|i| {
+        let class = &frame[1 + i * 4..5 + i * 4];
+        ClassUtilization {
+            class: CLASSES[i],
+            utilization_permille: u16::from_le_bytes([class[0], class[1]]),
+            deferred: u16::from_le_bytes([class[2], class[3]]),
+        }
+    }))
+}
+
+pub async fn publish_quota(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    classes: [ClassUtilization; 3],
+) {
+    for class in classes.iter().filter(|c| c.utilization_permille >= 1000) {
+        warn!(
+            "vehicle {} quota used up, {} beacons deferred",
+            class.class, class.deferred
+        );
+    }
+    match cbor_serializer(&classes) {
+        Ok(serialized) => nats_sender.publish_gated(QUOTA_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize quota utilization"),
+    }
+}
@@ -0,0 +1,171 @@
+use defmt::{Format, error, info, warn};
+use embassy_stm32::{mode::Async, usart::UartTx};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::Delay;
+use openlst_driver::{
+    link,
+    lst_control::reboot_radio,
+    lst_receiver::LSTVersion,
+    lst_sender::{LSTCmd, LSTSender},
+};
+use serde::Serialize;
+
+use crate::{cbor_serializer, lst_inbox::LstInbox, publisher::GatedPublish, uplink};
+
+// operator control interface, e.g. gst.radio.local.reboot or gst.radio.remote.version.
+// The remote lst is the flight lst, reached by uplink commands to radio-air
+pub const CONTROL_SUBJECT: &str = "gst.radio.>";
+const CONTROL_PREFIX: &str = "gst.radio.";
+// time the local lst gets to come back up after a reboot
+const BOOT_TIMEOUT_MS: u32 = 5000;
+
+#[derive(Format, Clone, Copy, PartialEq)]
+pub enum RadioTarget {
+    Local,
+    Remote,
+}
+
+#[derive(Format, Clone, Copy, PartialEq)]
+pub enum RadioRequest {
+    Reboot,
+    Version,
+}
+
+#[derive(Serialize)]
+pub enum RadioResponse {
+    /// uplink command sent to radio-air, its ack follows on the ack subject
+    Uplinked {
+        seq: u16,
+    },
+    RebootConfirmed {
+        uptime: u32,
+    },
+    RebootTimeout,
+    SendFailed,
+    Version {
+        hwid: u16,
+        git_rev: u32,
+    },
+}
+
+impl RadioTarget {
+    fn response_subject(&self) -> &'static str {
+        match self {
+            // outside of gst.radio.> so the responses do not come back on the control subscription
+            RadioTarget::Local => "gst.response.radio.local",
+            RadioTarget::Remote => "gst.response.radio.remote",
+        }
+    }
+}
+
+/// parse a control subject of the form gst.radio.<target>.<request>
+pub fn parse_control_subject(subject: &str) -> Option<(RadioTarget, RadioRequest)> {
+    let mut parts = subject.strip_prefix(CONTROL_PREFIX)?.split('.');
+    let target = match parts.next()? {
+        "local" => RadioTarget::Local,
+        "remote" => RadioTarget::Remote,
+        _ => return None,
+    };
+    let request = match parts.next()? {
+        "reboot" => RadioRequest::Reboot,
+        "version" => RadioRequest::Version,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((target, request))
+}
+
+async fn publish_response(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    target: RadioTarget,
+    response: &RadioResponse,
+) {
+    match cbor_serializer(response) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(target.response_subject(), serialized)
+                .await;
+        }
+        Err(_) => error!("could not serialize radio response"),
+    }
+}
+
+/// execute an operator request received on the control subject
+pub async fn handle_request(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>,
+    lst_rx: &mut LstInbox,
+    local_hwid: u16,
+    subject: &str,
+) {
+    let Some((target, request)) = parse_control_subject(subject) else {
+        warn!("unknown radio control subject: {}", subject);
+        return;
+    };
+    info!("radio control: {} {}", target, request);
+
+    let response = match (target, request) {
+        // the local lst answers over uart, so its reboot can be confirmed
+        (RadioTarget::Local, RadioRequest::Reboot) => {
+            let result = {
+                let mut lst = lst.lock().await;
+                reboot_radio(&mut lst, lst_rx, &mut Delay, BOOT_TIMEOUT_MS).await
+            };
+            match result {
+                Ok(boot) => {
+                    info!("local lst rebooted: {}", boot);
+                    RadioResponse::RebootConfirmed {
+                        uptime: boot.uptime,
+                    }
+                }
+                Err(e) => {
+                    error!("local lst reboot not confirmed: {}", e);
+                    RadioResponse::RebootTimeout
+                }
+            }
+        }
+        // the version reply is published once it arrives from the lst
+        (RadioTarget::Local, RadioRequest::Version) => {
+            match lst.lock().await.cmd(LSTCmd::GetVersion).await {
+                Ok(()) => return,
+                Err(e) => {
+                    error!("could not send radio control cmd: {}", e);
+                    RadioResponse::SendFailed
+                }
+            }
+        }
+        // the flight lst is only reachable through radio-air, which relays the version
+        // frame or reboots it and acks the command
+        (RadioTarget::Remote, request) => {
+            let op = match request {
+                RadioRequest::Reboot => link::OP_REBOOT_LST,
+                RadioRequest::Version => link::OP_LST_VERSION,
+            };
+            let (frame, seq) = uplink::command_frame(local_hwid, op);
+            match uplink::relay(&mut *lst.lock().await, &frame, seq).await {
+                Ok(()) => RadioResponse::Uplinked { seq },
+                Err(e) => {
+                    error!("could not uplink radio control cmd: {}", e);
+                    RadioResponse::SendFailed
+                }
+            }
+        }
+    };
+    publish_response(nats_sender, target, &response).await;
+}
+
+/// publish a version reply, of the local lst over uart or of the remote lst relayed by radio-air
+pub async fn publish_version(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    target: RadioTarget,
+    version: LSTVersion,
+) {
+    info!("LST {} version: {:x}", target, version.git_rev);
+    let response = RadioResponse::Version {
+        hwid: version.hwid,
+        git_rev: version.git_rev,
+    };
+    publish_response(nats_sender, target, &response).await;
+}
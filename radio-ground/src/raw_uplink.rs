@@ -0,0 +1,188 @@
+use alloc::vec::Vec;
+
+use defmt::{error, warn};
+use embassy_stm32::{mode::Async, usart::UartTx};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use openlst_driver::{link, lst_sender::LSTSender};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish, uplink};
+
+// arbitrary payloads published here are framed and relayed to radio-air as is,
+// for external command tools
+pub const RAW_SUBJECT: &str = "gst.uplink.raw";
+// filter args of the telemetry filter command: beacon index(1) flags(1) field can ids(2 LE each)
+pub const FILTER_SUBJECT: &str = "gst.uplink.filter";
+// uplink commands of the ops consoles: gst.uplink.cmd.<command> with the binary args as payload
+pub const COMMAND_SUBJECT: &str = "gst.uplink.cmd.>";
+const COMMAND_PREFIX: &str = "gst.uplink.cmd.";
+const RECEIPT_SUBJECT: &str = "gst.uplink.receipt";
+
+/// command of the ops consoles and the args lengths radio-air accepts for it
+struct Command {
+    name: &'static str,
+    op: u8,
+    args_lens: &'static [usize],
+}
+
+// args layouts are documented at the ops in openlst_driver::link
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "schedule",
+        op: link::OP_SCHEDULE,
+        args_lens: &[10],
+    },
+    Command {
+        name: "list_schedule",
+        op: link::OP_LIST_SCHEDULE,
+        args_lens: &[0],
+    },
+    Command {
+        name: "cancel_scheduled",
+        op: link::OP_CANCEL_SCHEDULED,
+        args_lens: &[2],
+    },
+    Command {
+        name: "beacon_config",
+        op: link::OP_BEACON_CONFIG,
+        args_lens: &[2, 6],
+    },
+    Command {
+        name: "beacon_save",
+        op: link::OP_BEACON_SAVE,
+        args_lens: &[link::BEACON_SAVE_CONFIRM.len()],
+    },
+    Command {
+        name: "payload_enable",
+        op: link::OP_PAYLOAD_ENABLE,
+        args_lens: &[2],
+    },
+    Command {
+        name: "payload_rate",
+        op: link::OP_PAYLOAD_RATE,
+        args_lens: &[2],
+    },
+    Command {
+        name: "set_phase",
+        op: link::OP_SET_PHASE,
+        args_lens: &[1],
+    },
+    Command {
+        name: "burst",
+        op: link::OP_BURST,
+        args_lens: &[0, 2],
+    },
+];
+
+type Lst = Mutex<ThreadModeRawMutex, LSTSender<UartTx<'static, Async>>>;
+
+#[derive(Serialize)]
+pub struct RawUplinkReceipt {
+    /// sequence number of the relayed frame, None if it was not sent
+    pub seq: Option<u16>,
+    pub len: usize,
+    pub error: Option<&'static str>,
+}
+
+/// relay a framed uplink and publish the receipt, the frame is None if the
+/// payload was rejected for the given reason
+async fn relay_with_receipt(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Lst,
+    frame: Option<(Vec<u8>, u16)>,
+    len: usize,
+    rejected: &'static str,
+) {
+    let (seq, error) = match frame {
+        Some((frame, seq)) => match uplink::relay(&mut *lst.lock().await, &frame, seq).await {
+            Ok(()) => (Some(seq), None),
+            Err(e) => {
+                error!("could not relay uplink: {}", e);
+                (None, Some("lst write failed"))
+            }
+        },
+        None => {
+            warn!("uplink of {} bytes rejected: {}", len, rejected);
+            (None, Some(rejected))
+        }
+    };
+    let receipt = RawUplinkReceipt { seq, len, error };
+    match cbor_serializer(&receipt) {
+        Ok(serialized) => nats_sender.publish_gated(RECEIPT_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize uplink receipt"),
+    }
+}
+
+/// relay a raw payload to the remote radio and publish the receipt
+pub async fn handle_raw(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Lst,
+    local_hwid: u16,
+    payload: &[u8],
+) {
+    let frame = uplink::raw_frame(local_hwid, payload);
+    relay_with_receipt(
+        nats_sender,
+        lst,
+        frame,
+        payload.len(),
+        "payload exceeds mtu",
+    )
+    .await;
+}
+
+/// uplink a telemetry filter command with the payload as args and publish the receipt
+pub async fn handle_filter(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Lst,
+    local_hwid: u16,
+    args: &[u8],
+) {
+    let frame = uplink::command_frame_args(local_hwid, link::OP_TELEMETRY_FILTER, args);
+    relay_with_receipt(
+        nats_sender,
+        lst,
+        frame,
+        args.len(),
+        "too many filter fields",
+    )
+    .await;
+}
+
+/// uplink T-0 of the mission elapsed time with the payload as args and publish the receipt
+pub async fn handle_t0(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Lst,
+    local_hwid: u16,
+    args: &[u8],
+) {
+    let frame = match args.len() {
+        0 | 8 => uplink::command_frame_args(local_hwid, link::OP_SET_T0, args),
+        _ => None,
+    };
+    relay_with_receipt(nats_sender, lst, frame, args.len(), "T-0 is not 8 bytes").await;
+}
+
+/// uplink the command named by the subject with the payload as args and publish the receipt
+pub async fn handle_command(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    lst: &Lst,
+    local_hwid: u16,
+    subject: &str,
+    args: &[u8],
+) {
+    let Some(command) = subject
+        .strip_prefix(COMMAND_PREFIX)
+        .and_then(|name| COMMANDS.iter().find(|c| c.name == name))
+    else {
+        warn!("unknown uplink command");
+        relay_with_receipt(nats_sender, lst, None, args.len(), "unknown command").await;
+        return;
+    };
+    let frame = if command.args_lens.contains(&args.len()) {
+        uplink::command_frame_args(local_hwid, command.op, args)
+    } else {
+        None
+    };
+    relay_with_receipt(nats_sender, lst, frame, args.len(), "invalid args length").await;
+}
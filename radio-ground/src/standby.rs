@@ -0,0 +1,152 @@
+use alloc::{borrow::Cow, format};
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use defmt::{info, warn};
+use embassy_stm32::uid;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+
+// hot standby arbitration between two ground stations receiving the same downlink.
+// Every station sends a heartbeat with its link quality on each local lst telemetry:
+// station(4 LE) quality(4 LE) leader(1)
+pub const HEARTBEAT_SUBJECT: &str = "gst.standby.heartbeat";
+const HEARTBEAT_LEN: usize = 9;
+// the standby station publishes telemetry with this prefix
+const STANDBY_PREFIX: &str = "standby.";
+// station control and status subjects are station specific and never redirected
+const STATION_PREFIX: &str = "gst.";
+// a peer without heartbeat for this long is considered gone
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+// link quality advantage required to take over from the leader, avoids flapping
+const TAKEOVER_MARGIN: u32 = 2;
+
+struct Peer {
+    station: u32,
+    quality: u32,
+    leader: bool,
+    last_seen: Instant,
+}
+
+struct Arbiter {
+    station: u32,
+    // good packets of the local lst at the last heartbeat
+    last_packets: Option<u32>,
+    quality: u32,
+    peer: Option<Peer>,
+}
+
+static LEADER: AtomicBool = AtomicBool::new(true);
+static ARBITER: Mutex<ThreadModeRawMutex, RefCell<Option<Arbiter>>> =
+    Mutex::new(RefCell::new(None));
+
+/// true while this station publishes the primary telemetry subjects
+pub fn is_leader() -> bool {
+    LEADER.load(Ordering::Relaxed)
+}
+
+/// subject to publish on, telemetry of the standby station goes to standby.<subject>
+pub fn route(subject: &str) -> Cow<'_, str> {
+    if is_leader() || subject.starts_with(STATION_PREFIX) {
+        Cow::Borrowed(subject)
+    } else {
+        Cow::Owned(format!("{}{}", STANDBY_PREFIX, subject))
+    }
+}
+
+fn station_id() -> u32 {
+    uid::uid().chunks(4).fold(0, |id, word| {
+        id ^ u32::from_le_bytes(word.try_into().unwrap())
+    })
+}
+
+impl Arbiter {
+    fn elect(&self) -> bool {
+        let leader = is_leader();
+        let Some(peer) = self
+            .peer
+            .as_ref()
+            .filter(|p| p.last_seen.elapsed() < PEER_TIMEOUT)
+        else {
+            return true;
+        };
+        // strict order on quality and station id, so both stations agree
+        let better = (self.quality, peer.station) > (peer.quality, self.station);
+        match (leader, peer.leader) {
+            // both claim the lead, the better station keeps it
+            (true, true) => better,
+            (true, false) => true,
+            (false, true) => self.quality >= peer.quality + TAKEOVER_MARGIN,
+            (false, false) => better,
+        }
+    }
+    fn update_leader(&self) {
+        let leader = self.elect();
+        if LEADER.swap(leader, Ordering::Relaxed) != leader {
+            if leader {
+                info!("taking over as leading ground station");
+            } else {
+                warn!("switching to standby, publishing on {}*", STANDBY_PREFIX);
+            }
+        }
+    }
+}
+
+fn with_arbiter<R>(f: impl FnOnce(&mut Arbiter) -> R) -> R {
+    ARBITER.lock(|arbiter| {
+        let mut arbiter = arbiter.borrow_mut();
+        f(arbiter.get_or_insert_with(|| Arbiter {
+            station: station_id(),
+            last_packets: None,
+            quality: 0,
+            peer: None,
+        }))
+    })
+}
+
+/// send a heartbeat with the good packets received since the last one as link quality
+pub async fn send_heartbeat(nats_sender: &mut embassy_nats::Client<'static>, packets_good: u32) {
+    let heartbeat = with_arbiter(|arbiter| {
+        arbiter.quality = arbiter
+            .last_packets
+            .map_or(0, |last| packets_good.wrapping_sub(last));
+        arbiter.last_packets = Some(packets_good);
+        arbiter.update_leader();
+        let mut heartbeat = [0; HEARTBEAT_LEN];
+        heartbeat[0..4].copy_from_slice(&arbiter.station.to_le_bytes());
+        heartbeat[4..8].copy_from_slice(&arbiter.quality.to_le_bytes());
+        heartbeat[8] = is_leader() as u8;
+        heartbeat
+    });
+    // not gated, the arbitration has to continue while publishing is muted
+    if nats_sender
+        .publish(HEARTBEAT_SUBJECT.into(), heartbeat.to_vec())
+        .await
+        .is_err()
+    {
+        warn!("could not publish standby heartbeat");
+    }
+}
+
+/// apply a heartbeat received on the heartbeat subject, including our own
+pub fn handle_heartbeat(payload: &[u8]) {
+    let Ok(heartbeat) = <[u8; HEARTBEAT_LEN]>::try_from(payload) else {
+        warn!("invalid standby heartbeat");
+        return;
+    };
+    let station = u32::from_le_bytes(heartbeat[0..4].try_into().unwrap());
+    with_arbiter(|arbiter| {
+        if station == arbiter.station {
+            return;
+        }
+        arbiter.peer = Some(Peer {
+            station,
+            quality: u32::from_le_bytes(heartbeat[4..8].try_into().unwrap()),
+            leader: heartbeat[8] != 0,
+            last_seen: Instant::now(),
+        });
+        arbiter.update_leader();
+    });
+}
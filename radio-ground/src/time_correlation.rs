@@ -0,0 +1,128 @@
+use alloc::collections::VecDeque;
+use core::cell::RefCell;
+
+use defmt::{error, info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use openlst_driver::link::TIME_CORRELATION_ID;
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// local time of radio-air relayed with its lst telemetry: id(1) seq(2 LE) local ms(8 LE)
+const TIME_CORRELATION_LEN: usize = 11;
+// correlation pairs in the fit, about 10 min at one pair per lst telemetry
+const WINDOW: usize = 64;
+
+pub const CLOCK_MODEL_SUBJECT: &str = "tm.clock_model";
+
+/// onboard local time paired with the utc time of reception
+#[derive(Clone, Copy)]
+struct Correlation {
+    local_us: u64,
+    utc_us: u64,
+}
+
+impl Correlation {
+    fn offset_us(&self) -> i64 {
+        self.utc_us as i64 - self.local_us as i64
+    }
+}
+
+/// Linear model of the onboard clock, utc = local + offset_us + drift since local_ms.
+/// The offset includes the downlink latency of the correlation frame
+#[derive(Serialize)]
+pub struct ClockModel {
+    /// sequence number of the latest correlation frame
+    pub seq: u16,
+    pub local_ms: u64,
+    pub offset_us: i64,
+    pub drift_ppb: i64,
+    pub samples: usize,
+}
+
+static PAIRS: Mutex<ThreadModeRawMutex, RefCell<VecDeque<Correlation>>> =
+    Mutex::new(RefCell::new(VecDeque::new()));
+
+/// seq and local ms of a correlation frame, None for other frames
+pub fn parse(frame: &[u8]) -> Option<(u16, u64)> {
+    if frame.len() != TIME_CORRELATION_LEN || frame[0] != TIME_CORRELATION_ID {
+        return None;
+    }
+    Some((
+        u16::from_le_bytes([frame[1], frame[2]]),
+        u64::from_le_bytes(frame[3..11].try_into().unwrap()),
+    ))
+}
+
+/// least squares fit of the offset over the local time, returns the offset at
+/// the latest pair and the drift in ppb
+fn fit(pairs: &VecDeque<Correlation>) -> (i64, i64) {
+    let latest = pairs[pairs.len() - 1];
+    // relative to the latest pair, keeps the values small enough for f64
+    let points = || {
+        pairs.iter().map(move |p| {
+            (
+                p.local_us as f64 - latest.local_us as f64,
+                (p.offset_us() - latest.offset_us()) as f64,
+            )
+        })
+    };
+    let n = pairs.len() as f64;
+    let (mean_x, mean_y) = points().fold((0.0, 0.0), |(x, y), (px, py)| (x + px / n, y + py / n));
+    let (cov, var) = points().fold((0.0, 0.0), |(cov, var), (x, y)| {
+        (
+            cov + (x - mean_x) * (y - mean_y),
+            var + (x - mean_x) * (x - mean_x),
+        )
+    });
+    let slope = if var > 0.0 { cov / var } else { 0.0 };
+    let offset = latest.offset_us() + (mean_y - slope * mean_x) as i64;
+    (offset, (slope * 1e9) as i64)
+}
+
+/// add a correlation frame received at the given utc time and publish the updated clock model
+pub async fn publish_model(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    seq: u16,
+    local_ms: u64,
+    utc_us: u64,
+) {
+    let pair = Correlation {
+        local_us: local_ms * 1000,
+        utc_us,
+    };
+    let model = PAIRS.lock(|pairs| {
+        let mut pairs = pairs.borrow_mut();
+        if pairs
+            .back()
+            .is_some_and(|last| last.local_us > pair.local_us)
+        {
+            warn!("radio-air clock restarted, resetting the clock model");
+            pairs.clear();
+        }
+        if pairs.len() == WINDOW {
+            pairs.pop_front();
+        }
+        pairs.push_back(pair);
+        let (offset_us, drift_ppb) = fit(&pairs);
+        ClockModel {
+            seq,
+            local_ms,
+            offset_us,
+            drift_ppb,
+            samples: pairs.len(),
+        }
+    });
+    info!(
+        "clock model: offset {} us, drift {} ppb",
+        model.offset_us, model.drift_ppb
+    );
+    match cbor_serializer(&model) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(CLOCK_MODEL_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize clock model"),
+    }
+}
@@ -1,11 +1,12 @@
 use defmt::{info, warn};
 use embassy_net::{
     IpEndpoint, Stack,
-    dns::DnsQueryType,
     udp::{PacketMetadata, UdpSocket},
 };
 use embassy_time::{Duration, Instant, Timer, with_timeout};
 
+use crate::publisher::GatedPublish;
+
 // internet time sync (NTP)
 const NTP_ADDR: &str = "pool.ntp.org";
 const NTP_PORT: u16 = 123;
@@ -42,13 +43,9 @@ fn ntp_packet_to_unix_micros(packet: &[u8]) -> Option<u64> {
 }
 
 async fn try_sync_internet_time(stack: &Stack<'_>) -> Result<i64, &'static str> {
-    let ips = stack
-        .dns_query(NTP_ADDR, DnsQueryType::A)
+    let ip = crate::net_config::resolve(stack, NTP_ADDR)
         .await
         .map_err(|_| "dns")?;
-    let Some(ip) = ips.first() else {
-        return Err("dns-empty");
-    };
 
     let mut rx_meta = [PacketMetadata::EMPTY; 1];
     let mut tx_meta = [PacketMetadata::EMPTY; 1];
@@ -57,7 +54,7 @@ async fn try_sync_internet_time(stack: &Stack<'_>) -> Result<i64, &'static str>
     let mut socket = UdpSocket::new(*stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
     socket.bind(0).map_err(|_| "bind")?;
 
-    let endpoint = IpEndpoint::new((*ip).into(), NTP_PORT);
+    let endpoint = IpEndpoint::new(ip, NTP_PORT);
     let mut request = [0u8; 48];
     request[0] = 0x23; // LI=0, VN=4, Mode=3 (client)
 
@@ -83,12 +80,12 @@ async fn try_sync_internet_time(stack: &Stack<'_>) -> Result<i64, &'static str>
     Ok(offset)
 }
 
-pub async fn sync_internet_time(stack: &Stack<'_>) -> i64 {
+pub async fn sync_internet_time(stack: &Stack<'_>) -> Option<i64> {
     for attempt in 1..=10 {
         match try_sync_internet_time(stack).await {
             Ok(offset) => {
                 info!("internet time synced on attempt {}", attempt);
-                return offset;
+                return Some(offset);
             }
             Err(e) => {
                 warn!("internet time sync failed ({}): {}", attempt, e);
@@ -97,6 +94,34 @@ pub async fn sync_internet_time(stack: &Stack<'_>) -> i64 {
         }
     }
 
-    warn!("internet time unavailable, using monotonic fallback");
-    0
+    warn!("internet time unavailable, falling back to nats server time");
+    None
+}
+
+// server time echo over nats, used when ntp is unavailable. The echo service
+// replies with the 8 byte request payload followed by its unix time in micros (LE)
+pub const TIME_REPLY_SUBJECT: &str = "gst.time.reply";
+const TIME_REQUEST_SUBJECT: &str = "gst.time.request";
+
+/// ask the server time echo service for its wall clock
+pub async fn request_server_time(nats_sender: &mut embassy_nats::Client<'static>) {
+    let local_us = Instant::now().as_micros();
+    nats_sender
+        .publish_gated(TIME_REQUEST_SUBJECT, local_us.to_le_bytes().to_vec())
+        .await;
+}
+
+/// offset between unix time and local time from a server time echo,
+/// assuming a symmetric round trip
+pub fn server_time_offset(reply: &[u8]) -> Option<i64> {
+    let sent_us = u64::from_le_bytes(reply.get(0..8)?.try_into().ok()?);
+    let server_us = u64::from_le_bytes(reply.get(8..16)?.try_into().ok()?);
+    let now = Instant::now().as_micros();
+    let round_trip = now.checked_sub(sent_us)?;
+    let offset = (server_us + round_trip / 2) as i64 - now as i64;
+    info!(
+        "nats server time offset {} us (round trip {} us)",
+        offset, round_trip
+    );
+    Some(offset)
 }
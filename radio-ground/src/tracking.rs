@@ -0,0 +1,107 @@
+use defmt::{error, info};
+#[cfg(feature = "rotator")]
+use embassy_stm32::{mode::Blocking, usart::UartTx};
+use libm::{atan2, cos, sin, sqrt};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+pub const TRACKING_SUBJECT: &str = "tracking.azel";
+
+// antenna position, set to the station location before operation
+const STATION_LAT_DEG: f64 = 0.0;
+const STATION_LON_DEG: f64 = 0.0;
+const STATION_ALT_M: f64 = 0.0;
+
+// WGS84 ellipsoid
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_E2: f64 = 6.694_379_990_14e-3;
+
+#[derive(Serialize, Clone, Copy)]
+pub struct LookAngles {
+    pub timestamp: u64,
+    pub azimuth_deg: f64,
+    pub elevation_deg: f64,
+    pub range_m: f64,
+}
+
+fn geodetic_to_ecef(lat: f64, lon: f64, alt: f64) -> [f64; 3] {
+    let n = WGS84_A / sqrt(1.0 - WGS84_E2 * sin(lat) * sin(lat));
+    [
+        (n + alt) * cos(lat) * cos(lon),
+        (n + alt) * cos(lat) * sin(lon),
+        (n * (1.0 - WGS84_E2) + alt) * sin(lat),
+    ]
+}
+
+/// azimuth, elevation and range from the station to an ecef position in meters
+pub fn look_angles(timestamp: u64, target: [f64; 3]) -> LookAngles {
+    let lat = STATION_LAT_DEG.to_radians();
+    let lon = STATION_LON_DEG.to_radians();
+    let station = geodetic_to_ecef(lat, lon, STATION_ALT_M);
+    let [dx, dy, dz] = [
+        target[0] - station[0],
+        target[1] - station[1],
+        target[2] - station[2],
+    ];
+
+    // rotate the difference vector into the local east north up frame
+    let east = -sin(lon) * dx + cos(lon) * dy;
+    let north = -sin(lat) * cos(lon) * dx - sin(lat) * sin(lon) * dy + cos(lat) * dz;
+    let up = cos(lat) * cos(lon) * dx + cos(lat) * sin(lon) * dy + sin(lat) * dz;
+
+    let azimuth = atan2(east, north).to_degrees();
+    LookAngles {
+        timestamp,
+        azimuth_deg: if azimuth < 0.0 {
+            azimuth + 360.0
+        } else {
+            azimuth
+        },
+        elevation_deg: atan2(up, sqrt(east * east + north * north)).to_degrees(),
+        range_m: sqrt(dx * dx + dy * dy + dz * dz),
+    }
+}
+
+pub async fn publish_look_angles(
+    nats_sender: &mut embassy_nats::Client<'static>,
+    angles: &LookAngles,
+) {
+    info!(
+        "tracking: az {} el {} range {}",
+        angles.azimuth_deg, angles.elevation_deg, angles.range_m
+    );
+    match cbor_serializer(angles) {
+        Ok(serialized) => {
+            nats_sender
+                .publish_gated(TRACKING_SUBJECT, serialized)
+                .await
+        }
+        Err(_) => error!("could not serialize look angles"),
+    }
+}
+
+/// rotctl compatible rotator output on a spare uart
+#[cfg(feature = "rotator")]
+pub struct Rotator {
+    uart: UartTx<'static, Blocking>,
+}
+
+#[cfg(feature = "rotator")]
+impl Rotator {
+    pub fn new(uart: UartTx<'static, Blocking>) -> Self {
+        Self { uart }
+    }
+
+    /// send a set_pos command, the rotator can not point below the horizon
+    pub fn point(&mut self, angles: &LookAngles) {
+        let cmd = alloc::format!(
+            "P {:.2} {:.2}\n",
+            angles.azimuth_deg,
+            angles.elevation_deg.max(0.0)
+        );
+        if let Err(e) = self.uart.blocking_write(cmd.as_bytes()) {
+            error!("could not write rotator command: {}", e);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use alloc::vec::Vec;
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU16, Ordering},
+};
+
+use defmt::{error, info};
+use embassy_stm32::{
+    mode::Async,
+    usart::{self, UartTx},
+};
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+use embassy_time::{Duration, Instant};
+use openlst_driver::{
+    link::{self, LST_VERSION_ID, RAW_UPLINK_ID, UPLINK_ACK_ID, UPLINK_ID},
+    lst_receiver::LSTVersion,
+    lst_sender::{LSTSender, SenderError},
+    relay_route::{ROUTE_HEADER_LEN, Route},
+};
+use serde::Serialize;
+
+use crate::{cbor_serializer, publisher::GatedPublish};
+
+// acknowledgement from radio-air: id(1) source hwid(2 LE) seq(2 LE) status(1)
+const UPLINK_ACK_LEN: usize = 6;
+// version of the flight lst: id(1) seq of the request(2 LE) hwid(2 LE) git rev(4 LE)
+const LST_VERSION_LEN: usize = 9;
+// longest args of a command
+const MAX_ARGS_LEN: usize = 10;
+// arbitrary payload passed through to radio-air: id(1) source hwid(2 LE) seq(2 LE) payload
+const RAW_HEADER_LEN: usize = 5;
+// relay payload of a 256 byte lst packet without framing, openlst header and routing header
+const RELAY_MTU: usize = 256 - 3 - 5 - ROUTE_HEADER_LEN;
+pub const MAX_RAW_LEN: usize = RELAY_MTU - RAW_HEADER_LEN;
+
+// acknowledgements of the vehicle for the commands of this station
+pub const ACK_SUBJECT: &str = "gst.uplink.ack";
+
+// retransmissions reuse the sequence number, new commands count up. Seeded at boot, radio-air
+// remembers recent (source hwid, seq) pairs and a restarted or standby station starting at 0
+// would be acknowledged as duplicate
+static NEXT_SEQ: AtomicU16 = AtomicU16::new(0);
+// set by the lst sender after every frame it puts on air, uplinks as well as remote commands
+static LAST_FRAME: Mutex<ThreadModeRawMutex, Cell<Option<Instant>>> = Mutex::new(Cell::new(None));
+
+#[derive(defmt::Format, Serialize, Clone, Copy)]
+pub struct UplinkAck {
+    pub source_hwid: u16,
+    pub seq: u16,
+    pub status: u8,
+}
+
+/// start the sequence numbers at a random value, before the first command is sent
+pub fn seed_seq(seed: u16) {
+    NEXT_SEQ.store(seed, Ordering::Relaxed);
+}
+
+fn next_seq() -> u16 {
+    NEXT_SEQ.fetch_add(1, Ordering::Relaxed)
+}
+
+/// rf hook of the lst sender, records when the lst was last handed a frame to transmit
+pub fn on_air() {
+    LAST_FRAME.lock(|last| last.set(Some(Instant::now())));
+}
+
+/// true if a frame went to the lst for transmission within the duration, it may still be on air
+pub fn sent_within(duration: Duration) -> bool {
+    LAST_FRAME.lock(|last| last.get().is_some_and(|at| at.elapsed() < duration))
+}
+
+/// frame of a new uplink command and its sequence number
+pub fn command_frame(source_hwid: u16, op: u8) -> ([u8; 6], u16) {
+    let seq = next_seq();
+    let hwid = source_hwid.to_le_bytes();
+    let seq_bytes = seq.to_le_bytes();
+    (
+        [UPLINK_ID, hwid[0], hwid[1], seq_bytes[0], seq_bytes[1], op],
+        seq,
+    )
+}
+
+/// frame of a new uplink command with args and its sequence number, None if the args are too long
+pub fn command_frame_args(source_hwid: u16, op: u8, args: &[u8]) -> Option<(Vec<u8>, u16)> {
+    if args.len() > MAX_ARGS_LEN {
+        return None;
+    }
+    let (command, seq) = command_frame(source_hwid, op);
+    let mut frame = Vec::with_capacity(command.len() + args.len());
+    frame.extend_from_slice(&command);
+    frame.extend_from_slice(args);
+    Some((frame, seq))
+}
+
+/// frame of a raw uplink payload and its sequence number, None if it exceeds the mtu
+pub fn raw_frame(source_hwid: u16, payload: &[u8]) -> Option<(Vec<u8>, u16)> {
+    if payload.len() > MAX_RAW_LEN {
+        return None;
+    }
+    let seq = next_seq();
+    let mut frame = Vec::with_capacity(RAW_HEADER_LEN + payload.len());
+    frame.push(RAW_UPLINK_ID);
+    frame.extend_from_slice(&source_hwid.to_le_bytes());
+    frame.extend_from_slice(&seq.to_le_bytes());
+    frame.extend_from_slice(payload);
+    Some((frame, seq))
+}
+
+/// hand an uplink frame to the lst behind a routing header, repeaters carry it to the vehicle
+pub async fn relay(
+    lst: &mut LSTSender<UartTx<'static, Async>>,
+    frame: &[u8],
+    seq: u16,
+) -> Result<(), SenderError<usart::Error>> {
+    let route = Route::new(link::ROUTE_NODE_AIR, link::ROUTE_NODE_GROUND, seq);
+    lst.relay_routed(&route, frame).await
+}
+
+/// parse a relayed frame, None if it is not an uplink acknowledgement
+pub fn parse_ack(frame: &[u8]) -> Option<UplinkAck> {
+    if frame.len() != UPLINK_ACK_LEN || frame[0] != UPLINK_ACK_ID {
+        return None;
+    }
+    Some(UplinkAck {
+        source_hwid: u16::from_le_bytes([frame[1], frame[2]]),
+        seq: u16::from_le_bytes([frame[3], frame[4]]),
+        status: frame[5],
+    })
+}
+
+/// parse a relayed frame, None if it is not the version of the flight lst.
+/// Returns the sequence number of the version request with the version
+pub fn parse_version(frame: &[u8]) -> Option<(u16, LSTVersion)> {
+    if frame.len() != LST_VERSION_LEN || frame[0] != LST_VERSION_ID {
+        return None;
+    }
+    let version = LSTVersion {
+        hwid: u16::from_le_bytes([frame[3], frame[4]]),
+        git_rev: u32::from_le_bytes([frame[5], frame[6], frame[7], frame[8]]),
+    };
+    Some((u16::from_le_bytes([frame[1], frame[2]]), version))
+}
+
+fn status_name(status: u8) -> &'static str {
+    match status {
+        link::ACK_EXECUTED => "executed",
+        link::ACK_DUPLICATE => "duplicate",
+        link::ACK_FAILED => "failed",
+        link::ACK_UNKNOWN_OP => "unknown op",
+        _ => "invalid",
+    }
+}
+
+pub async fn publish_ack(nats_sender: &mut embassy_nats::Client<'static>, ack: UplinkAck) {
+    info!(
+        "uplink {} of {:x}: {}",
+        ack.seq,
+        ack.source_hwid,
+        status_name(ack.status)
+    );
+    match cbor_serializer(&ack) {
+        Ok(serialized) => nats_sender.publish_gated(ACK_SUBJECT, serialized).await,
+        Err(_) => error!("could not serialize uplink ack"),
+    }
+}